@@ -47,6 +47,30 @@ fn voxel_terrain(b: &mut Bencher) -> Result<()> {
   Ok(())
 }
 
+#[bench]
+#[allow(clippy::unnecessary_wraps)]
+fn voxel_terrain_greedy(b: &mut Bencher) -> Result<()> {
+  let (chunks, texture) = get_chunks("terrain").unwrap();
+  let options = BakerOptions {
+    texture,
+    greedy_meshing: true,
+    ..Default::default()
+  };
+
+  b.iter(|| {
+    let mut meshes: Vec<(Mesh, [f32; 3])> = vec![];
+
+    for chunk in chunks.iter() {
+      let mesh = VoxelBaker::bake(chunk, &options).unwrap();
+      if let Some(mesh) = mesh {
+        meshes.push((mesh, chunk.position()));
+      }
+    }
+  });
+
+  Ok(())
+}
+
 #[bench]
 #[allow(clippy::unnecessary_wraps)]
 fn voxel_planet(b: &mut Bencher) -> Result<()> {
@@ -0,0 +1,224 @@
+use gaiku_common::{
+  dual_grid::{Cell, Crossing, DualGrid},
+  prelude::*,
+  Result,
+};
+use glam::Vec3;
+
+use crate::qef::Qef;
+
+const EPSILON: f32 = 1e-4;
+
+/// Implementation of the dual contouring terrain generation, it places one vertex per cell that
+/// crosses the surface, at the point that best fits the tangent planes of the surface inside the
+/// cell, so sharp edges and corners are kept.
+///
+/// The tangent planes use the gradient of the chunk (see `Chunkify::get_gradient`) when it
+/// provides it, for exact normals, or the gradient of the 8 samples around each cell. The vertices
+/// are shared by the quads, with the average of the normals of their cell.
+///
+/// With neighbor data the chunk also places the vertices of the cells between it and its
+/// neighbors, and connects the edges that start on its samples, so the chunks join without gaps.
+pub struct DualContouringBaker;
+
+impl Baker for DualContouringBaker {
+  type Value = f32;
+  type AtlasValue = u8;
+
+  // The quads connect the vertices of the cells around each edge
+  const REGION_MARGIN: usize = 2;
+
+  fn bake<C, T, M>(chunk: &C, options: &BakerOptions<T>) -> Result<Option<M>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable,
+    T: Texturify2d,
+    M: Meshify,
+  {
+    let isovalue = options.isovalue;
+    let mut grid = DualGrid::new(chunk);
+
+    // One vertex for each cell that crosses the surface
+    for cell in grid.cells(chunk, isovalue) {
+      // The QEF is solved in cell space to keep the numbers small
+      let mut qef = Qef::default();
+      let mut normals = Vec3::zero();
+      for crossing in cell.crossings(isovalue) {
+        let normal = gradient(chunk, &cell, &crossing);
+
+        // Without a normal the intersection still counts for the mass point
+        let normal = if normal.length() > EPSILON {
+          normal.normalize()
+        } else {
+          Vec3::zero()
+        };
+
+        qef.add(crossing.point, normal);
+        normals += normal;
+      }
+
+      if let Some(vertex) = qef.solve() {
+        // Keep the vertex inside the cell, the QEF minimum can be far away on almost
+        // parallel planes
+        let vertex = vertex.max(Vec3::zero()).min(Vec3::one());
+        let origin = Vec3::new(
+          cell.origin[0] as f32,
+          cell.origin[1] as f32,
+          cell.origin[2] as f32,
+        );
+        grid.set_vertex(cell.origin, origin + vertex, normals);
+      }
+    }
+
+    // One quad for each edge that crosses the surface, connecting the 4 cells around it
+    Ok(grid.build::<C, T, M>(chunk, options))
+  }
+}
+
+// The gradient of the chunk interpolated between the samples of the edge, or the one of the cell
+fn gradient<C>(chunk: &C, cell: &Cell, crossing: &Crossing) -> Vec3
+where
+  C: Chunkify<f32>,
+{
+  let [a, b] = crossing.samples;
+  match (
+    chunk.get_gradient(a[0], a[1], a[2]),
+    chunk.get_gradient(b[0], b[1], b[2]),
+  ) {
+    (Some(gradient_a), Some(gradient_b)) => {
+      let (gradient_a, gradient_b) = (Vec3::from(gradient_a), Vec3::from(gradient_b));
+      gradient_a + (gradient_b - gradient_a) * crossing.weight
+    }
+    _ => cell.gradient(crossing.point),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use gaiku_common::{chunk::Chunk, mesh::Mesh, rebake, texture::Texture2d};
+  type BakerType = DualContouringBaker;
+
+  // Roof shaped field with the ridge at x = 4.5, y = 4.8, between the samples
+  struct Roof(Chunk);
+
+  impl Roof {
+    fn new() -> Self {
+      let mut chunk = Chunk::new([0.0, 0.0, 0.0], 10, 10, 4);
+      for x in 0..10 {
+        for y in 0..10 {
+          for z in 0..4 {
+            let value = 4.8 - y as f32 - 0.5 * (x as f32 - 4.5).abs();
+            chunk.set(x, y, z, value);
+          }
+        }
+      }
+      Self(chunk)
+    }
+  }
+
+  impl Chunkify<f32> for Roof {
+    fn is_air(&self, x: usize, y: usize, z: usize, isovalue: f32) -> bool {
+      self.0.is_air(x, y, z, isovalue)
+    }
+
+    fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+      self.0.get(x, y, z)
+    }
+
+    fn get_gradient(&self, x: usize, _y: usize, _z: usize) -> Option<[f32; 3]> {
+      if (x as f32) < 4.5 {
+        Some([0.5, -1.0, 0.0])
+      } else {
+        Some([-0.5, -1.0, 0.0])
+      }
+    }
+  }
+
+  impl Atlasify<u8> for Roof {
+    fn get_atlas(&self, x: usize, y: usize, z: usize) -> u8 {
+      self.0.get_atlas(x, y, z)
+    }
+  }
+
+  impl Sizable for Roof {
+    fn with_size(width: u16, height: u16, depth: u16) -> Self {
+      Self(Chunk::with_size(width, height, depth))
+    }
+
+    fn width(&self) -> u16 {
+      self.0.width()
+    }
+
+    fn height(&self) -> u16 {
+      self.0.height()
+    }
+
+    fn depth(&self) -> u16 {
+      self.0.depth()
+    }
+  }
+
+  #[test]
+  fn simple_test_dual_contouring() {
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 3, 3, 3);
+
+    chunk.set(1, 1, 1, 1.);
+    chunk.set_atlas(1, 1, 1, 0);
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    let positions_count = mesh.get_positions().len();
+    let indices_count = mesh.get_indices().len();
+
+    // One quad for each of the 6 edges that leave the voxel, sharing the vertices of the 8 cells
+    assert_eq!(indices_count, 36);
+    assert_eq!(positions_count, 8);
+
+    // All the normals point away from the voxel
+    for (index, normal) in mesh.get_normals().iter().enumerate() {
+      let position = Vec3::from(mesh.get_positions()[index]);
+      let outwards = position - Vec3::new(1.0, 1.0, 1.0);
+      assert!(Vec3::from(*normal).dot(outwards) > 0.0);
+    }
+  }
+
+  #[test]
+  fn sharp_edge_dual_contouring() {
+    let options = BakerOptions::default();
+    let roof = Roof::new();
+
+    let mesh = BakerType::bake::<Roof, Texture2d, Mesh>(&roof, &options)
+      .unwrap()
+      .unwrap();
+
+    // The ridge vertices are placed on the edge, not at the average of the intersections
+    let ridge = mesh
+      .get_positions()
+      .iter()
+      .filter(|position| (position[0] - 4.5).abs() < 1e-3 && (position[1] - 4.8).abs() < 1e-3)
+      .count();
+    assert!(ridge > 0);
+
+    // Every vertex lies on one of the two sides of the roof
+    for position in mesh.get_positions().iter() {
+      let value = 4.8 - position[1] - 0.5 * (position[0] - 4.5).abs();
+      assert!(value.abs() < 1e-3);
+    }
+  }
+
+  #[test]
+  fn bake_region_test_dual_contouring() {
+    rebake::test_support::check_bake_region::<BakerType>(&BakerOptions::default());
+  }
+
+  #[test]
+  fn seams_test_dual_contouring() {
+    rebake::test_support::check_seams::<BakerType>(&BakerOptions::default());
+  }
+}
@@ -0,0 +1,4 @@
+mod baker;
+mod qef;
+
+pub use self::baker::DualContouringBaker;
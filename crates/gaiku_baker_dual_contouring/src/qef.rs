@@ -0,0 +1,124 @@
+use glam::Vec3;
+
+// Eigenvalues below this are treated as zero, so the directions without enough information (flat
+// surfaces, edges) fall back to the mass point instead of shooting the vertex away
+const TRUNCATION: f32 = 0.1;
+const SWEEPS: usize = 6;
+
+/// Quadratic error function built from the hermite data (intersection points and normals) of a
+/// cell, the minimizer is the point closest to all the tangent planes.
+#[derive(Debug, Default)]
+pub(crate) struct Qef {
+  ata: [[f32; 3]; 3],
+  atb: [f32; 3],
+  mass: Vec3,
+  count: usize,
+}
+
+impl Qef {
+  pub fn add(&mut self, point: Vec3, normal: Vec3) {
+    let distance = normal.dot(point);
+    let normal: [f32; 3] = normal.into();
+
+    for (row, ata) in self.ata.iter_mut().enumerate() {
+      for (col, ata) in ata.iter_mut().enumerate() {
+        *ata += normal[row] * normal[col];
+      }
+      self.atb[row] += normal[row] * distance;
+    }
+
+    self.mass += point;
+    self.count += 1;
+  }
+
+  /// Solves the QEF around the mass point using the pseudo inverse, `None` when it's empty.
+  pub fn solve(&self) -> Option<Vec3> {
+    if self.count == 0 {
+      return None;
+    }
+
+    let mass = self.mass / self.count as f32;
+    let mass_array: [f32; 3] = mass.into();
+    let inverse = pseudo_inverse(self.ata);
+
+    let mut residual = [0.0; 3];
+    for (row, residual) in residual.iter_mut().enumerate() {
+      *residual = self.atb[row]
+        - (0..3)
+          .map(|col| self.ata[row][col] * mass_array[col])
+          .sum::<f32>();
+    }
+
+    let mut result = mass_array;
+    for (row, result) in result.iter_mut().enumerate() {
+      *result += (0..3)
+        .map(|col| inverse[row][col] * residual[col])
+        .sum::<f32>();
+    }
+
+    Some(result.into())
+  }
+}
+
+// Pseudo inverse of a symmetric matrix using the Jacobi eigenvalue method
+fn pseudo_inverse(mut matrix: [[f32; 3]; 3]) -> [[f32; 3]; 3] {
+  let mut vectors = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+  for _ in 0..SWEEPS {
+    for &(p, q) in [(0, 1), (0, 2), (1, 2)].iter() {
+      if matrix[p][q].abs() < 1e-8 {
+        continue;
+      }
+
+      let theta = (matrix[q][q] - matrix[p][p]) / (2.0 * matrix[p][q]);
+      let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+      let c = 1.0 / (t * t + 1.0).sqrt();
+      let s = t * c;
+
+      let mut rotation = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+      rotation[p][p] = c;
+      rotation[q][q] = c;
+      rotation[p][q] = s;
+      rotation[q][p] = -s;
+
+      matrix = multiply(transpose(rotation), multiply(matrix, rotation));
+      vectors = multiply(vectors, rotation);
+    }
+  }
+
+  let mut inverse = [[0.0; 3]; 3];
+  for (i, row) in matrix.iter().enumerate() {
+    let value = row[i];
+    if value.abs() < TRUNCATION {
+      continue;
+    }
+
+    for (row, inverse) in inverse.iter_mut().enumerate() {
+      for (col, inverse) in inverse.iter_mut().enumerate() {
+        *inverse += vectors[row][i] * vectors[col][i] / value;
+      }
+    }
+  }
+
+  inverse
+}
+
+fn multiply(a: [[f32; 3]; 3], b: [[f32; 3]; 3]) -> [[f32; 3]; 3] {
+  let mut result = [[0.0; 3]; 3];
+  for (row, result) in result.iter_mut().enumerate() {
+    for (col, result) in result.iter_mut().enumerate() {
+      *result = (0..3).map(|i| a[row][i] * b[i][col]).sum();
+    }
+  }
+  result
+}
+
+fn transpose(matrix: [[f32; 3]; 3]) -> [[f32; 3]; 3] {
+  let mut result = [[0.0; 3]; 3];
+  for (row, result) in result.iter_mut().enumerate() {
+    for (col, result) in result.iter_mut().enumerate() {
+      *result = matrix[col][row];
+    }
+  }
+  result
+}
@@ -0,0 +1,379 @@
+use gaiku_common::prelude::*;
+
+/// Options of the `hydraulic` erosion, the defaults are meant for the `0.0` to `255.0` values of
+/// the heightmap readers.
+#[derive(Clone, Debug)]
+pub struct HydraulicOptions {
+  /// Seed of the droplet positions, the same seed and options always give the same result.
+  pub seed: u64,
+  /// Number of droplets.
+  pub iterations: usize,
+  /// Max number of steps of each droplet.
+  pub max_lifetime: usize,
+  /// How much a droplet keeps its direction instead of following the slope, from `0.0` to `1.0`.
+  pub inertia: f32,
+  /// Sediment that a droplet can carry by unit of slope, speed and water.
+  pub capacity: f32,
+  /// Sediment that a droplet can carry on the flat terrain.
+  pub min_capacity: f32,
+  /// Fraction of the excess sediment deposited on each step.
+  pub deposition: f32,
+  /// Fraction of the free capacity eroded on each step.
+  pub erosion: f32,
+  /// Fraction of the water that evaporates on each step.
+  pub evaporation: f32,
+  pub gravity: f32,
+}
+
+impl Default for HydraulicOptions {
+  fn default() -> Self {
+    Self {
+      seed: 0,
+      iterations: 10_000,
+      max_lifetime: 30,
+      inertia: 0.05,
+      capacity: 4.0,
+      min_capacity: 0.01,
+      deposition: 0.3,
+      erosion: 0.3,
+      evaporation: 0.01,
+      gravity: 4.0,
+    }
+  }
+}
+
+/// Options of the `thermal` erosion.
+#[derive(Clone, Debug)]
+pub struct ThermalOptions {
+  pub iterations: usize,
+  /// Max height difference between neighbor samples, the material above it slides down.
+  pub talus: f32,
+  /// Fraction of the material above the `talus` moved on each iteration, up to `0.5`.
+  pub rate: f32,
+}
+
+impl Default for ThermalOptions {
+  fn default() -> Self {
+    Self {
+      iterations: 50,
+      talus: 4.0,
+      rate: 0.5,
+    }
+  }
+}
+
+/// Simulates water droplets running down the heightmap, eroding the slopes and depositing the
+/// sediment where they slow down. Only the `z = 0` samples of the chunk are used, like the
+/// heightmap baker does.
+pub fn hydraulic<C>(chunk: &mut C, options: &HydraulicOptions)
+where
+  C: Chunkify<f32> + ChunkifyMut<f32> + Sizable,
+{
+  let mut heights = Heights::read(chunk);
+  if heights.width < 2 || heights.height < 2 {
+    return;
+  }
+
+  let mut random = Random(options.seed);
+
+  for _ in 0..options.iterations {
+    let mut position = [
+      random.next() * (heights.width - 1) as f32,
+      random.next() * (heights.height - 1) as f32,
+    ];
+    let mut direction = [0.0, 0.0];
+    let mut speed = 1.0;
+    let mut water = 1.0;
+    let mut sediment = 0.0;
+
+    for _ in 0..options.max_lifetime {
+      let (height, gradient) = heights.sample(position);
+
+      direction = [
+        direction[0] * options.inertia - gradient[0] * (1.0 - options.inertia),
+        direction[1] * options.inertia - gradient[1] * (1.0 - options.inertia),
+      ];
+      let length = (direction[0] * direction[0] + direction[1] * direction[1]).sqrt();
+      if length < f32::EPSILON {
+        break;
+      }
+      direction = [direction[0] / length, direction[1] / length];
+
+      let previous = position;
+      position = [position[0] + direction[0], position[1] + direction[1]];
+      if !heights.contains(position) {
+        break;
+      }
+
+      let delta = heights.sample(position).0 - height;
+      let capacity = (-delta * speed * water * options.capacity).max(options.min_capacity);
+
+      if delta > 0.0 || sediment > capacity {
+        // Fills the pit it climbs out of, or drops the excess
+        let amount = if delta > 0.0 {
+          delta.min(sediment)
+        } else {
+          (sediment - capacity) * options.deposition
+        };
+        sediment -= amount;
+        heights.add(previous, amount);
+      } else {
+        // Never deeper than the slope, so it doesn't dig holes
+        let amount = ((capacity - sediment) * options.erosion).min(-delta);
+        sediment += amount;
+        heights.add(previous, -amount);
+      }
+
+      // Going down (a negative delta) speeds up the droplet
+      speed = (speed * speed - delta * options.gravity).max(0.0).sqrt();
+      water *= 1.0 - options.evaporation;
+    }
+  }
+
+  heights.write(chunk);
+}
+
+/// Moves the material of the slopes steeper than the `talus` down to their lower neighbors, the
+/// total height is preserved. Only the `z = 0` samples of the chunk are used.
+pub fn thermal<C>(chunk: &mut C, options: &ThermalOptions)
+where
+  C: Chunkify<f32> + ChunkifyMut<f32> + Sizable,
+{
+  let mut heights = Heights::read(chunk);
+  let (width, height) = (heights.width, heights.height);
+
+  for _ in 0..options.iterations {
+    let mut deltas = vec![0.0; heights.values.len()];
+
+    for y in 0..height {
+      for x in 0..width {
+        let value = heights.values[x + y * width];
+        let neighbors = [
+          (x > 0, x.wrapping_sub(1), y),
+          (x + 1 < width, x + 1, y),
+          (y > 0, x, y.wrapping_sub(1)),
+          (y + 1 < height, x, y + 1),
+        ];
+
+        let mut total = 0.0;
+        let mut steepest = 0.0_f32;
+        for &(_, x, y) in neighbors.iter().filter(|(inside, _, _)| *inside) {
+          let difference = value - heights.values[x + y * width];
+          if difference > options.talus {
+            total += difference;
+            steepest = steepest.max(difference);
+          }
+        }
+
+        if total <= 0.0 {
+          continue;
+        }
+
+        // Split between the lower neighbors by their height difference
+        let moved = options.rate * (steepest - options.talus);
+        deltas[x + y * width] -= moved;
+        for &(_, x, y) in neighbors.iter().filter(|(inside, _, _)| *inside) {
+          let difference = value - heights.values[x + y * width];
+          if difference > options.talus {
+            deltas[x + y * width] += moved * difference / total;
+          }
+        }
+      }
+    }
+
+    for (value, delta) in heights.values.iter_mut().zip(deltas.iter()) {
+      *value += delta;
+    }
+  }
+
+  heights.write(chunk);
+}
+
+/// Copy of the samples of the chunk, row by row.
+struct Heights {
+  width: usize,
+  height: usize,
+  values: Vec<f32>,
+}
+
+impl Heights {
+  fn read<C>(chunk: &C) -> Self
+  where
+    C: Chunkify<f32> + Sizable,
+  {
+    let (width, height) = (chunk.width() as usize, chunk.height() as usize);
+    let values = (0..height)
+      .flat_map(|y| (0..width).map(move |x| chunk.get(x, y, 0)))
+      .collect();
+
+    Self {
+      width,
+      height,
+      values,
+    }
+  }
+
+  fn write<C>(&self, chunk: &mut C)
+  where
+    C: ChunkifyMut<f32>,
+  {
+    for y in 0..self.height {
+      for x in 0..self.width {
+        chunk.set(x, y, 0, self.values[x + y * self.width]);
+      }
+    }
+  }
+
+  fn contains(&self, position: [f32; 2]) -> bool {
+    position[0] >= 0.0
+      && position[1] >= 0.0
+      && position[0] < (self.width - 1) as f32
+      && position[1] < (self.height - 1) as f32
+  }
+
+  /// The cell of the position, with the offset inside of it.
+  fn cell(&self, position: [f32; 2]) -> (usize, [f32; 2]) {
+    let (x, y) = (position[0].floor(), position[1].floor());
+    let index = x as usize + y as usize * self.width;
+    (index, [position[0] - x, position[1] - y])
+  }
+
+  /// Bilinear interpolation of the height and gradient inside of the cell.
+  fn sample(&self, position: [f32; 2]) -> (f32, [f32; 2]) {
+    let (index, [u, v]) = self.cell(position);
+    let back_left = self.values[index];
+    let back_right = self.values[index + 1];
+    let front_left = self.values[index + self.width];
+    let front_right = self.values[index + self.width + 1];
+
+    let height = back_left * (1.0 - u) * (1.0 - v)
+      + back_right * u * (1.0 - v)
+      + front_left * (1.0 - u) * v
+      + front_right * u * v;
+    let gradient = [
+      (back_right - back_left) * (1.0 - v) + (front_right - front_left) * v,
+      (front_left - back_left) * (1.0 - u) + (front_right - back_right) * u,
+    ];
+
+    (height, gradient)
+  }
+
+  /// Spreads the amount on the corners of the cell, by their distance to the position.
+  fn add(&mut self, position: [f32; 2], amount: f32) {
+    let (index, [u, v]) = self.cell(position);
+    self.values[index] += amount * (1.0 - u) * (1.0 - v);
+    self.values[index + 1] += amount * u * (1.0 - v);
+    self.values[index + self.width] += amount * (1.0 - u) * v;
+    self.values[index + self.width + 1] += amount * u * v;
+  }
+}
+
+/// SplitMix64, small and good enough to place the droplets.
+struct Random(u64);
+
+impl Random {
+  /// From `0.0` (included) to `1.0` (excluded).
+  fn next(&mut self) -> f32 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut value = self.0;
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^= value >> 31;
+    (value >> 40) as f32 / (1_u64 << 24) as f32
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use gaiku_common::chunk::Chunk;
+
+  // A cone, 8 units higher on each sample closer to the center
+  fn cone() -> Chunk {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 16, 16, 1);
+    for x in 0..16 {
+      for y in 0..16 {
+        let distance = ((x as f32 - 7.5).powi(2) + (y as f32 - 7.5).powi(2)).sqrt();
+        chunk.set(x, y, 0, 255.0 - distance * 8.0);
+      }
+    }
+    chunk
+  }
+
+  fn values(chunk: &Chunk) -> Vec<f32> {
+    chunk.values().iter().map(|(_, value)| *value).collect()
+  }
+
+  #[test]
+  fn test_hydraulic() {
+    let options = HydraulicOptions {
+      seed: 7,
+      iterations: 500,
+      ..Default::default()
+    };
+
+    let mut first = cone();
+    hydraulic(&mut first, &options);
+    let mut second = cone();
+    hydraulic(&mut second, &options);
+    assert_eq!(values(&first), values(&second));
+    assert_ne!(values(&first), values(&cone()));
+
+    let mut other = cone();
+    hydraulic(&mut other, &HydraulicOptions { seed: 8, ..options });
+    assert_ne!(values(&first), values(&other));
+  }
+
+  #[test]
+  fn test_hydraulic_speed() {
+    // A long slope going down on x
+    let slope = || {
+      let mut chunk = Chunk::new([0.0, 0.0, 0.0], 32, 8, 1);
+      for x in 0..32 {
+        for y in 0..8 {
+          chunk.set(x, y, 0, 255.0 - x as f32 * 2.0);
+        }
+      }
+      chunk
+    };
+    let eroded = |gravity: f32| {
+      let mut chunk = slope();
+      let options = HydraulicOptions {
+        seed: 3,
+        iterations: 200,
+        gravity,
+        ..Default::default()
+      };
+      hydraulic(&mut chunk, &options);
+      values(&slope())
+        .iter()
+        .zip(values(&chunk).iter())
+        .map(|(before, after)| (before - after).max(0.0))
+        .sum::<f32>()
+    };
+
+    // The droplets speed up going down, so they carry and erode more than at a constant speed
+    assert!(eroded(4.0) > eroded(0.0));
+  }
+
+  #[test]
+  fn test_thermal() {
+    let options = ThermalOptions::default();
+    let mut chunk = cone();
+    thermal(&mut chunk, &options);
+
+    let before: f32 = values(&cone()).iter().sum();
+    let after: f32 = values(&chunk).iter().sum();
+    assert!((before - after).abs() / before < 1e-5);
+
+    // The slopes get closer to the talus
+    let steepest = |chunk: &Chunk| {
+      (0..15)
+        .flat_map(|x| (0..16).map(move |y| (x, y)))
+        .map(|(x, y)| (chunk.get(x, y, 0) - chunk.get(x + 1, y, 0)).abs())
+        .fold(0.0_f32, f32::max)
+    };
+    assert!(steepest(&chunk) < steepest(&cone()));
+    assert!(steepest(&chunk) < options.talus + 1.0);
+  }
+}
@@ -1,7 +1,15 @@
 use gaiku_common::{prelude::*, Result};
 use std::marker::PhantomData;
 
+/// Hydraulic and thermal erosion of the heightmap chunks.
+pub mod erosion;
+
 /// Implementation of a naive heightmap terrain generation.
+///
+/// The positions of the chunks are in samples on their own x and y axes, without overlapping, like
+/// the tiles of the heightmap readers. The mesh of the chunk at `[x, y, _]` goes at
+/// `[x * cell_size, 0, y * cell_size]`, and with neighbor data (see `Apronify`) its last cells
+/// reach the first samples of the next chunks, so the tiles join without cracks.
 pub struct HeightMapBaker;
 
 impl HeightMapBaker {
@@ -17,38 +25,170 @@ impl HeightMapBaker {
     M: Meshify,
     MB: MeshBuilder,
   {
-    let height = 30.;
+    let heightmap = &options.heightmap;
+    let [low, high] = heightmap.value_range;
+    let cell_size = heightmap.cell_size;
     let isovalue = options.isovalue;
+
+    let height_of =
+      |value: f32| heightmap.base_height + (value - low) * heightmap.vertical_scale / (high - low);
+    let height = |x: usize, y: usize| height_of(chunk.get(x, y, 0));
+
+    // The samples past the border only exist on the sides with a neighbor, the other ones read
+    // as air, so each side and corner is checked. The samples before the chunk wrap around.
+    let (width, depth) = (chunk.width() as usize, chunk.height() as usize);
+    let side = |coord: usize, size: usize| {
+      if (coord as isize) < 0 {
+        -1
+      } else if coord >= size {
+        1
+      } else {
+        0
+      }
+    };
+    let exists = |x: usize, y: usize| {
+      let offset = [side(x, width), side(y, depth), 0];
+      offset == [0, 0, 0] || chunk.has_neighbor(offset)
+    };
+
+    // With a neighbor on +x or +y the cells reach the first samples of the next chunk
+    let last_x = width - 1 + exists(width, 0) as usize;
+    let last_y = depth - 1 + exists(0, depth) as usize;
+
+    // The builder needs bounds containing every vertex, the extrapolated heights and the skirts
+    // included
+    let (bottom, top) = (0..=last_x)
+      .flat_map(|x| (0..=last_y).map(move |y| (x, y)))
+      .filter(|&(x, y)| exists(x, y))
+      .map(|(x, y)| height(x, y))
+      .chain(vec![height_of(low), height_of(high)])
+      .fold((f32::MAX, f32::MIN), |(bottom, top), height| {
+        (bottom.min(height), top.max(height))
+      });
+    let bottom = bottom - heightmap.skirt_depth.unwrap_or(0.0);
+
     let mut builder: MB = MB::create(
       [
-        chunk.width() as f32 / 2.0,
-        height as f32 / 2.0,
-        chunk.height() as f32 / 2.0,
+        chunk.width() as f32 * cell_size / 2.0,
+        (bottom + top) / 2.0,
+        chunk.height() as f32 * cell_size / 2.0,
+      ],
+      [
+        chunk.width() as f32 * cell_size,
+        top - bottom,
+        chunk.height() as f32 * cell_size,
       ],
-      [chunk.width() as f32, height as f32, chunk.height() as f32],
     );
 
-    for x in 0..chunk.width() as usize - 1 {
-      for y in 0..chunk.height() as usize - 1 {
-        if chunk.is_air(x, y, 0, isovalue) {
+    // Central differences, one sided where the sample around doesn't exist, so two chunks with
+    // each other as neighbors get the same normals on the edge they share
+    let normal = |x: usize, y: usize| {
+      let (left, right) = (
+        Some(x.wrapping_sub(1))
+          .filter(|&left| exists(left, y))
+          .unwrap_or(x),
+        Some(x + 1).filter(|&right| exists(right, y)).unwrap_or(x),
+      );
+      let (back, front) = (
+        Some(y.wrapping_sub(1))
+          .filter(|&back| exists(x, back))
+          .unwrap_or(y),
+        Some(y + 1).filter(|&front| exists(x, front)).unwrap_or(y),
+      );
+      let slope = |high: f32, low: f32, distance: usize| {
+        if distance == 0 {
+          0.0
+        } else {
+          (high - low) / (distance as f32 * cell_size)
+        }
+      };
+      let dx = slope(height(right, y), height(left, y), right.wrapping_sub(left));
+      let dz = slope(height(x, front), height(x, back), front.wrapping_sub(back));
+      let length = (dx * dx + 1.0 + dz * dz).sqrt();
+      [-dx / length, 1.0 / length, -dz / length]
+    };
+
+    // Every cell takes the whole tile of its atlas index, otherwise the uvs span the chunk
+    let vertex = |x: usize, y: usize, corner: [usize; 2], tile: Option<([f32; 2], [f32; 2])>| {
+      let (x, y) = (x + corner[0], y + corner[1]);
+      let uv = match tile {
+        Some((origin, size)) => [
+          origin[0] + corner[0] as f32 * size[0],
+          origin[1] + corner[1] as f32 * size[1],
+        ],
+        None => [x as f32 / last_x as f32, y as f32 / last_y as f32],
+      };
+
+      (
+        [x as f32 * cell_size, height(x, y), y as f32 * cell_size],
+        normal(x, y),
+        uv,
+      )
+    };
+
+    for x in 0..last_x {
+      for y in 0..last_y {
+        // The last cell needs the neighbor on the corner too
+        if chunk.is_air(x, y, 0, isovalue) || !exists(x + 1, y + 1) {
           continue;
         }
 
-        let fx = x as f32;
-        let fz = y as f32;
+        let tile = options.texture.as_ref().map(|texture| {
+          let uvs = texture.get_uv(chunk.get_atlas(x, y, 0));
+          (uvs.0, [uvs.2[0] - uvs.0[0], uvs.2[1] - uvs.0[1]])
+        });
+
+        // Left front, right back, left back and right front, right back, left front
+        for triangle in [[[0, 1], [1, 0], [0, 0]], [[1, 1], [1, 0], [0, 1]]].iter() {
+          for &corner in triangle.iter() {
+            let (position, normal, uv) = vertex(x, y, corner, tile);
+            builder.add(Vertex {
+              position,
+              normal: Some(normal),
+              uv: Some(uv),
+              ..Default::default()
+            });
+          }
+        }
+
+        let skirt_depth = match heightmap.skirt_depth {
+          Some(skirt_depth) => skirt_depth,
+          None => continue,
+        };
 
-        let lb = chunk.get(x, y, 0) * height / 255.0;
-        let lf = chunk.get(x, y + 1, 0) * height / 255.0;
-        let rb = chunk.get(x + 1, y, 0) * height / 255.0;
-        let rf = chunk.get(x + 1, y + 1, 0) * height / 255.0;
+        // The edges on the border of the chunk, ordered so the skirts face outwards. They keep
+        // the normals of the surface so the lighting doesn't change on them.
+        let edges = [
+          (y == 0, [1, 0], [0, 0]),
+          (y + 1 == last_y, [0, 1], [1, 1]),
+          (x == 0, [0, 0], [0, 1]),
+          (x + 1 == last_x, [1, 1], [1, 0]),
+        ];
 
-        let left_back = [fx, lb, fz];
-        let right_back = [fx + 1.0, rb, fz];
-        let right_front = [fx + 1.0, rf, fz + 1.0];
-        let left_front = [fx, lf, fz + 1.0];
+        for &(_, start, end) in edges.iter().filter(|(border, _, _)| *border) {
+          let (start, start_normal, start_uv) = vertex(x, y, start, tile);
+          let (end, end_normal, end_uv) = vertex(x, y, end, tile);
+          let start_bottom = [start[0], start[1] - skirt_depth, start[2]];
+          let end_bottom = [end[0], end[1] - skirt_depth, end[2]];
 
-        builder.add_triangle([left_front, right_back, left_back], None, None, 0);
-        builder.add_triangle([right_front, right_back, left_front], None, None, 0);
+          for &(position, normal, uv) in [
+            (start, start_normal, start_uv),
+            (start_bottom, start_normal, start_uv),
+            (end_bottom, end_normal, end_uv),
+            (start, start_normal, start_uv),
+            (end_bottom, end_normal, end_uv),
+            (end, end_normal, end_uv),
+          ]
+          .iter()
+          {
+            builder.add(Vertex {
+              position,
+              normal: Some(normal),
+              uv: Some(uv),
+              ..Default::default()
+            });
+          }
+        }
       }
     }
 
@@ -72,6 +212,22 @@ impl Baker for HeightMapBaker {
       Self::bake_with_builder::<C, T, M, NoTreeBuilder>(chunk, options, Default::default())
     }
   }
+
+  /// The normals, skirts and scale of the heightmaps don't follow the samples of the chunk, so
+  /// it's baked again entirely, which is cheap for the 2d chunks.
+  fn bake_region<C, T, M>(
+    chunk: &C,
+    _region: &DirtyRegion,
+    _mesh: Option<M>,
+    options: &BakerOptions<T>,
+  ) -> Result<Option<M>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable,
+    T: Texturify2d,
+    M: Meshify,
+  {
+    Self::bake(chunk, options)
+  }
 }
 
 #[cfg(test)]
@@ -108,4 +264,278 @@ mod test {
     assert_eq!(indices_count, 6);
     assert_eq!(positions_count, 4);
   }
+
+  #[test]
+  fn test_heightmap_options() {
+    let options = BakerOptions {
+      heightmap: HeightMapOptions {
+        vertical_scale: 10.0,
+        base_height: 5.0,
+        cell_size: 2.0,
+        value_range: [0.0, 1000.0],
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 2, 2, 1);
+
+    chunk.set(0, 0, 0, 500.);
+    chunk.set(0, 1, 0, 1000.);
+    chunk.set(1, 0, 0, 0.);
+    chunk.set(1, 1, 0, 1500.);
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+    let positions = mesh.get_positions();
+
+    assert!(positions.contains(&[0.0, 10.0, 0.0]));
+    assert!(positions.contains(&[0.0, 15.0, 2.0]));
+    assert!(positions.contains(&[2.0, 5.0, 0.0]));
+    // Extrapolated above the range
+    assert!(positions.contains(&[2.0, 20.0, 2.0]));
+  }
+
+  #[test]
+  fn test_normals_and_uvs() {
+    let mut options = BakerOptions {
+      remove_duplicate_verts: true,
+      heightmap: HeightMapOptions {
+        vertical_scale: 1.0,
+        value_range: [0.0, 1.0],
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    // A slope rising one unit per sample on the x axis
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 3, 2, 1);
+    for x in 0..3 {
+      for y in 0..2 {
+        chunk.set(x, y, 0, x as f32 + 1.0);
+      }
+    }
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    let slope = [-0.5_f32.sqrt(), 0.5_f32.sqrt(), 0.0];
+    for normal in mesh.get_normals() {
+      for (value, expected) in normal.iter().zip(slope.iter()) {
+        assert!((value - expected).abs() < 1e-5);
+      }
+    }
+
+    let index = mesh
+      .get_positions()
+      .iter()
+      .position(|position| position == &[2.0, 3.0, 1.0])
+      .unwrap();
+    assert_eq!(mesh.get_uvs()[index], [1.0, 1.0]);
+
+    let texture = TextureAtlas2d::<Texture2d>::new(16);
+    let (origin, _, end, _) = texture.get_uv(0);
+    options.texture = Some(texture);
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    assert!(mesh.get_uvs().contains(&origin));
+    assert!(mesh.get_uvs().contains(&end));
+    assert!(mesh
+      .get_uvs()
+      .iter()
+      .all(|uv| uv[0] >= origin[0] && uv[0] <= end[0] && uv[1] >= origin[1] && uv[1] <= end[1]));
+  }
+
+  #[test]
+  fn test_neighbor_data() {
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      heightmap: HeightMapOptions {
+        vertical_scale: 1.0,
+        value_range: [0.0, 1.0],
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 2, 2, 1);
+    for x in 0..2 {
+      for y in 0..2 {
+        chunk.set(x, y, 0, 1.0);
+      }
+    }
+
+    // The neighbors on +x, +y and their diagonal, in the axes of the chunk
+    for position in [[2.0, 0.0, 0.0], [0.0, 2.0, 0.0], [2.0, 2.0, 0.0]].iter() {
+      let mut neighbor = Chunk::new(*position, 2, 2, 1);
+      neighbor.set(0, 0, 0, 2.0);
+      neighbor.set(0, 1, 0, 2.0);
+      neighbor.set(1, 0, 0, 2.0);
+      neighbor.set(1, 1, 0, 2.0);
+      assert!(chunk.update_neighbor_data(&neighbor));
+    }
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    // 2x2 cells instead of one, reaching the first samples of the neighbors
+    assert_eq!(mesh.get_indices().len(), 24);
+    assert!(mesh.get_positions().contains(&[2.0, 2.0, 2.0]));
+    assert!(mesh.get_positions().contains(&[2.0, 2.0, 0.0]));
+  }
+
+  #[test]
+  fn test_partial_neighbor_data() {
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      heightmap: HeightMapOptions {
+        vertical_scale: 1.0,
+        value_range: [0.0, 1.0],
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 2, 2, 1);
+    let mut right = Chunk::new([2.0, 0.0, 0.0], 2, 2, 1);
+    for x in 0..2 {
+      for y in 0..2 {
+        chunk.set(x, y, 0, 1.0);
+        right.set(x, y, 0, 1.0);
+      }
+    }
+    // Only the neighbor on +x, the samples on +y and on the corner are missing
+    assert!(chunk.update_neighbor_data(&right));
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    // The cells reach the neighbor on x only, without falling to the missing samples
+    assert_eq!(mesh.get_indices().len(), 12);
+    assert!(mesh.get_positions().contains(&[2.0, 1.0, 1.0]));
+    assert!(mesh
+      .get_positions()
+      .iter()
+      .all(|position| position[1] == 1.0));
+    assert!(mesh
+      .get_normals()
+      .iter()
+      .all(|normal| normal == &[0.0, 1.0, 0.0]));
+  }
+
+  #[test]
+  fn test_neighbor_normals() {
+    let options = BakerOptions {
+      heightmap: HeightMapOptions {
+        vertical_scale: 1.0,
+        value_range: [0.0, 1.0],
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    // A bump on the edge shared by two tiles, the central differences differ from one sided ones
+    let value = |x: usize, y: usize| ((x as f32 - 8.0).powi(2) * 0.3 + y as f32 * 0.5).sin() + 2.0;
+    // Four tiles in a row, the two in the middle get the neighbor data from both sides
+    let mut chunks: Vec<_> = (0..4)
+      .map(|tile| {
+        let mut chunk = Chunk::new([tile as f32 * 4.0, 0.0, 0.0], 4, 4, 1);
+        for x in 0..4 {
+          for y in 0..4 {
+            chunk.set(x, y, 0, value(tile * 4 + x, y));
+          }
+        }
+        chunk
+      })
+      .collect();
+    for tile in 1..3 {
+      for neighbor in [tile - 1, tile + 1].iter() {
+        let neighbor = chunks[*neighbor].clone();
+        assert!(chunks[tile].update_neighbor_data(&neighbor));
+      }
+    }
+
+    let bake = |tile: usize| {
+      BakerType::bake::<Chunk, Texture2d, Mesh>(&chunks[tile], &options)
+        .unwrap()
+        .unwrap()
+    };
+    let (first, second) = (bake(1), bake(2));
+
+    // The last vertices of the first tile are the first ones of the second
+    let edge = |mesh: &Mesh, x: f32| {
+      let mut edge: Vec<_> = mesh
+        .get_positions()
+        .iter()
+        .zip(mesh.get_normals().iter())
+        .filter(|(position, _)| position[0] == x)
+        .map(|(position, normal)| (position[1].to_bits(), position[2].to_bits(), *normal))
+        .collect();
+      edge.sort_by_key(|(height, z, _)| (*height, *z));
+      edge.dedup_by_key(|(height, z, _)| (*height, *z));
+      edge
+    };
+    let (first_edge, second_edge) = (edge(&first, 4.0), edge(&second, 0.0));
+    assert!(!first_edge.is_empty());
+    assert_eq!(first_edge.len(), second_edge.len());
+    for (a, b) in first_edge.iter().zip(second_edge.iter()) {
+      assert_eq!((a.0, a.1), (b.0, b.1));
+      for (a, b) in a.2.iter().zip(b.2.iter()) {
+        assert!((a - b).abs() < 1e-5);
+      }
+    }
+  }
+
+  #[test]
+  fn test_skirts() {
+    let options = BakerOptions {
+      heightmap: HeightMapOptions {
+        vertical_scale: 1.0,
+        value_range: [0.0, 1.0],
+        skirt_depth: Some(0.5),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 3, 2, 1);
+    for x in 0..3 {
+      for y in 0..2 {
+        chunk.set(x, y, 0, 1.0);
+      }
+    }
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+    let positions = mesh.get_positions();
+
+    // 2 cells and 6 edges on the borders, 2 triangles each
+    assert_eq!(positions.len(), (2 + 6) * 6);
+    assert!(positions.contains(&[0.0, 0.5, 0.0]));
+    assert!(positions.contains(&[2.0, 0.5, 1.0]));
+
+    // Facing outwards
+    let skirts = positions
+      .chunks(3)
+      .filter(|triangle| triangle.iter().any(|vertex| vertex[1] < 1.0));
+    for triangle in skirts {
+      let center = (0..3)
+        .map(|axis| triangle.iter().map(|vertex| vertex[axis]).sum::<f32>() / 3.0)
+        .collect::<Vec<_>>();
+      let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+      let (u, v) = (
+        [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+        [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+      );
+      let normal = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+      ];
+      let outwards = [center[0] - 1.0, 0.0, center[2] - 0.5];
+      assert!(normal[0] * outwards[0] + normal[2] * outwards[2] > 0.0);
+    }
+  }
 }
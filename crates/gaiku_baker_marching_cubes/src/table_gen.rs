@@ -494,6 +494,7 @@ impl Edge {
   }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct Face {
   indices: [i8; 3],
@@ -692,9 +693,9 @@ impl NGon {
       }
     }
 
-    return vec![NGon {
+    vec![NGon {
       verts: self.verts.clone(),
-    }];
+    }]
   }
 
   // Read up on Polygon triangulation
@@ -781,7 +782,6 @@ fn main() {
   ];
 
   let values: [[bool; 8]; 256] = (0..256)
-    .into_iter()
     .map(|cube_index| {
       [
         (cube_index & 1) == 0,
@@ -800,7 +800,7 @@ fn main() {
 
   // We don't split the planes and instead
   // just work out the uvs
-  let planes = vec![];
+  let planes = [];
 
   let mut new_edges = vec![];
   let mut new_barys = vec![];
@@ -1,8 +1,29 @@
 use super::common::*;
-use gaiku_common::{prelude::*, Result};
+use gaiku_common::{
+  prelude::*,
+  transition::{TransitionCell, LOW_RESOLUTION},
+  Result,
+};
+use glam::Vec3;
 use std::{convert::TryInto, marker::PhantomData};
 
+// Corners of a cell, same order used by the marching cubes table
+const CORNERS: [[usize; 3]; 8] = [
+  [0, 0, 0],
+  [1, 0, 0],
+  [1, 1, 0],
+  [0, 1, 0],
+  [0, 0, 1],
+  [1, 0, 1],
+  [1, 1, 1],
+  [0, 1, 1],
+];
+
 /// Implementation of the marching cubes terrain generation.
+///
+/// With a `level_of_detail` greater than 0 the cells span `2^level_of_detail` samples, and the
+/// sides listed in `lod_transitions` get transition cells so they match a neighbor baked with one
+/// level of detail less. Transitions only work when the chunk size is a multiple of the cell size.
 pub struct ModMarchingCubesBaker;
 
 impl ModMarchingCubesBaker {
@@ -18,7 +39,6 @@ impl ModMarchingCubesBaker {
     M: Meshify,
     MB: MeshBuilder,
   {
-    type Coord = usize;
     let mut builder = MB::create(
       [
         chunk.width() as f32 / 2.0,
@@ -34,102 +54,133 @@ impl ModMarchingCubesBaker {
 
     let isovalue = options.isovalue;
 
-    for x in 0..chunk.width() as usize - 1 {
-      let fx = x as f32;
-      let x = x as Coord;
-      for y in 0..chunk.height() as usize - 1 {
-        let fy = y as f32;
-        let y = y as Coord;
-        for z in 0..chunk.depth() as usize - 1 {
-          let fz = z as f32;
-          let z = z as Coord;
-
-          let air_check = [
-            chunk.is_air(x, y, z, isovalue),
-            chunk.is_air(x + 1, y, z, isovalue),
-            chunk.is_air(x + 1, y + 1, z, isovalue),
-            chunk.is_air(x, y + 1, z, isovalue),
-            chunk.is_air(x, y, z + 1, isovalue),
-            chunk.is_air(x + 1, y, z + 1, isovalue),
-            chunk.is_air(x + 1, y + 1, z + 1, isovalue),
-            chunk.is_air(x, y + 1, z + 1, isovalue),
+    // With the neighbor data we can also generate the cells between this chunk and the next one
+    let border = if chunk.has_neighbor_data() { 0 } else { 1 };
+    let limits = [
+      chunk.width() as usize - border,
+      chunk.height() as usize - border,
+      chunk.depth() as usize - border,
+    ];
+
+    let stride = 1 << options.level_of_detail;
+    let transitions = if options.level_of_detail > 0 {
+      options.lod_transitions
+    } else {
+      [false; 6]
+    };
+
+    // The samples on the sides with transitions are moved inside the chunk, to make room for the
+    // transition cells
+    let position = |sample: [usize; 3]| {
+      let mut point = [sample[0] as f32, sample[1] as f32, sample[2] as f32];
+      for axis in 0..3 {
+        if transitions[axis * 2] && sample[axis] == 0 {
+          point[axis] += stride as f32 / 2.0;
+        }
+        if transitions[axis * 2 + 1] && sample[axis] == limits[axis] {
+          point[axis] -= stride as f32 / 2.0;
+        }
+      }
+      Vec3::from(point)
+    };
+
+    for x in (0..limits[0]).step_by(stride) {
+      for y in (0..limits[1]).step_by(stride) {
+        for z in (0..limits[2]).step_by(stride) {
+          let start = [x, y, z];
+          let end = [
+            (x + stride).min(limits[0]),
+            (y + stride).min(limits[1]),
+            (z + stride).min(limits[2]),
           ];
-          if air_check.iter().all(|&v| !v) || air_check.iter().all(|&v| v) {
-            continue;
+
+          let mut samples = [[0; 3]; 8];
+          for (sample, corner) in samples.iter_mut().zip(CORNERS.iter()) {
+            for axis in 0..3 {
+              sample[axis] = if corner[axis] == 0 {
+                start[axis]
+              } else {
+                end[axis]
+              };
+            }
           }
 
-          let grid = GridCell {
-            value: [
-              chunk.get(x, y, z) as f32,
-              chunk.get(x + 1, y, z) as f32,
-              chunk.get(x + 1, y + 1, z) as f32,
-              chunk.get(x, y + 1, z) as f32,
-              chunk.get(x, y, z + 1) as f32,
-              chunk.get(x + 1, y, z + 1) as f32,
-              chunk.get(x + 1, y + 1, z + 1) as f32,
-              chunk.get(x, y + 1, z + 1) as f32,
-            ],
-            point: [
-              [fx + 0.0, fy + 0.0, fz + 0.0].into(),
-              [fx + 1.0, fy + 0.0, fz + 0.0].into(),
-              [fx + 1.0, fy + 1.0, fz + 0.0].into(),
-              [fx + 0.0, fy + 1.0, fz + 0.0].into(),
-              [fx + 0.0, fy + 0.0, fz + 1.0].into(),
-              [fx + 1.0, fy + 0.0, fz + 1.0].into(),
-              [fx + 1.0, fy + 1.0, fz + 1.0].into(),
-              [fx + 0.0, fy + 1.0, fz + 1.0].into(),
-            ],
-          };
-
-          let polys = grid.polygonize(isovalue);
-
-          for (vertex, face_uvs, corner) in polys {
-            let normal = compute_normal(&vertex);
-
-            // Get atlas at this corner_idx
-            let atlas = match corner {
-              0 => chunk.get_atlas(x, y, z),
-              1 => chunk.get_atlas(x + 1, y, z),
-              2 => chunk.get_atlas(x + 1, y + 1, z),
-              3 => chunk.get_atlas(x, y + 1, z),
-              4 => chunk.get_atlas(x, y, z + 1),
-              5 => chunk.get_atlas(x + 1, y, z + 1),
-              6 => chunk.get_atlas(x + 1, y + 1, z + 1),
-              7 => chunk.get_atlas(x, y + 1, z + 1),
-              _ => unreachable!(),
+          let air = samples
+            .iter()
+            .filter(|&&[x, y, z]| chunk.is_air(x, y, z, isovalue))
+            .count();
+
+          if air > 0 && air < samples.len() {
+            let mut grid = GridCell {
+              value: [0.0; 8],
+              point: [[0.0, 0.0, 0.0].into(); 8],
             };
+            for (index, &[x, y, z]) in samples.iter().enumerate() {
+              grid.value[index] = chunk.get(x, y, z);
+              grid.point[index] = position([x, y, z]).into();
+            }
+
+            for (vertex, face_uvs, corner) in grid.polygonize(isovalue) {
+              let [x, y, z] = samples[corner as usize];
+              add_triangle(
+                &mut builder,
+                options,
+                vertex,
+                face_uvs,
+                chunk.get_atlas(x, y, z),
+              );
+            }
+          }
+
+          // Transition cells on the sides that border a chunk with more detail
+          for (side, _) in transitions.iter().enumerate().filter(|(_, &v)| v) {
+            let axis = side / 2;
+            let positive = side % 2 == 1;
+            if (positive && end[axis] != limits[axis]) || (!positive && start[axis] != 0) {
+              continue;
+            }
 
-            let uvs = if let Some(texture) = &options.texture {
-              // Get the atlas corners
-              // 3-2
-              // 0-1
-              let uvs = texture.get_uv(atlas);
-
-              let atlas_origin = uvs.0;
-              let atlas_dimensions = [uvs.2[0] - uvs.0[0], uvs.2[1] - uvs.0[1]];
-              // Put face uvs into atlas uv space
-              let final_uvs: [[f32; 2]; 3] = face_uvs
-                .iter()
-                .map(|uv| {
-                  [
-                    atlas_origin[0] + uv[0] * atlas_dimensions[0],
-                    atlas_origin[1] + uv[1] * atlas_dimensions[1],
-                  ]
-                })
-                .collect::<Vec<[f32; 2]>>()
-                .try_into()
-                .unwrap();
-              Some(final_uvs)
-            } else {
-              None
+            let u_axis = (axis + 1) % 3;
+            let v_axis = (axis + 2) % 3;
+            let steps = |axis: usize| [start[axis], (start[axis] + end[axis]) / 2, end[axis]];
+
+            let mut samples = [[0; 3]; 13];
+            for (v, v_sample) in steps(v_axis).iter().enumerate() {
+              for (u, u_sample) in steps(u_axis).iter().enumerate() {
+                let sample = &mut samples[u + v * 3];
+                sample[axis] = if positive { end[axis] } else { start[axis] };
+                sample[u_axis] = *u_sample;
+                sample[v_axis] = *v_sample;
+              }
+            }
+            for (index, &sample) in LOW_RESOLUTION.iter().enumerate() {
+              samples[9 + index] = samples[sample];
+            }
+
+            let mut cell = TransitionCell {
+              value: [0.0; 13],
+              point: [Vec3::zero(); 13],
             };
+            for (index, &[x, y, z]) in samples.iter().enumerate() {
+              cell.value[index] = chunk.get(x, y, z);
+              // The high resolution face stays where the neighbor chunk has it
+              cell.point[index] = if index < 9 {
+                Vec3::new(x as f32, y as f32, z as f32)
+              } else {
+                position([x, y, z])
+              };
+            }
 
-            builder.add_triangle(
-              vertex,       // triangle
-              Some(normal), // normal
-              uvs,          // uv
-              atlas.into(), // atlas
-            );
+            for (vertex, face_uvs, sample) in cell.polygonize(isovalue, true) {
+              let [x, y, z] = samples[sample];
+              add_triangle(
+                &mut builder,
+                options,
+                vertex,
+                face_uvs,
+                chunk.get_atlas(x, y, z),
+              );
+            }
           }
         }
       }
@@ -139,6 +190,53 @@ impl ModMarchingCubesBaker {
   }
 }
 
+fn add_triangle<MB, T>(
+  builder: &mut MB,
+  options: &BakerOptions<T>,
+  vertex: [[f32; 3]; 3],
+  face_uvs: [[f32; 2]; 3],
+  atlas: u8,
+) where
+  MB: MeshBuilder,
+  T: Texturify2d,
+{
+  let normal = compute_normal(&vertex);
+
+  let uvs = if let Some(texture) = &options.texture {
+    // Get the atlas corners
+    // 3-2
+    // 0-1
+    let uvs = texture.get_uv(atlas);
+
+    let atlas_origin = uvs.0;
+    let atlas_dimensions = [uvs.2[0] - uvs.0[0], uvs.2[1] - uvs.0[1]];
+    // Put face uvs into atlas uv space
+    let final_uvs: [[f32; 2]; 3] = face_uvs
+      .iter()
+      .map(|uv| {
+        [
+          atlas_origin[0] + uv[0] * atlas_dimensions[0],
+          atlas_origin[1] + uv[1] * atlas_dimensions[1],
+        ]
+      })
+      .collect::<Vec<[f32; 2]>>()
+      .try_into()
+      .unwrap();
+    Some(final_uvs)
+  } else {
+    None
+  };
+
+  builder.add_triangle([0, 1, 2].map(|i| Vertex {
+    position: vertex[i],
+    normal: Some(normal),
+    uv: uvs.map(|uvs| uvs[i]),
+    color: options.color(atlas),
+    atlas_index: atlas.into(),
+    ..Default::default()
+  }));
+}
+
 impl Baker for ModMarchingCubesBaker {
   type Value = f32;
   type AtlasValue = u8;
@@ -160,7 +258,9 @@ impl Baker for ModMarchingCubesBaker {
 #[cfg(test)]
 mod test {
   use super::*;
-  use gaiku_common::{chunk::Chunk, mesh::Mesh, texture::Texture2d};
+  use gaiku_common::{
+    chunk::Chunk, mesh::Mesh, rebake::test_support::terrain, texture::Texture2d, world::World,
+  };
   type BakerType = ModMarchingCubesBaker;
 
   #[test]
@@ -185,4 +285,215 @@ mod test {
     assert_eq!(indices_count, 144);
     assert_eq!(positions_count, 102);
   }
+
+  // Segments of the triangles that lie on the plane x = `plane`, moved by `offset`
+  fn segments_on_plane(mesh: &Mesh, plane: f32, offset: f32) -> Vec<[Vec3; 2]> {
+    let positions = mesh.get_positions();
+    let mut segments = vec![];
+
+    for triangle in mesh.get_indices().chunks(3) {
+      for index in 0..3 {
+        let a = Vec3::from(positions[triangle[index] as usize]);
+        let b = Vec3::from(positions[triangle[(index + 1) % 3] as usize]);
+        // The chunks without neighbor data end one sample before on z
+        if (a.x - plane).abs() < 1e-4 && (b.x - plane).abs() < 1e-4 && a.z < 7.001 && b.z < 7.001 {
+          let offset = Vec3::new(offset, 0.0, 0.0);
+          segments.push([a + offset, b + offset]);
+        }
+      }
+    }
+
+    segments
+  }
+
+  fn contains_segment(segments: &[[Vec3; 2]], [a, b]: [Vec3; 2]) -> bool {
+    segments.iter().any(|[c, d]| {
+      ((a - *c).length() < 1e-3 && (b - *d).length() < 1e-3)
+        || ((a - *d).length() < 1e-3 && (b - *c).length() < 1e-3)
+    })
+  }
+
+  // Segments of the triangles with both ends on the plane x = `plane`, without the ones shared by
+  // two triangles of the same cell
+  fn boundary_on_plane(triangles: &[[[f32; 3]; 3]], plane: f32) -> Vec<[Vec3; 2]> {
+    let mut segments: Vec<[Vec3; 2]> = vec![];
+    for triangle in triangles.iter() {
+      for index in 0..3 {
+        let a = Vec3::from(triangle[index]);
+        let b = Vec3::from(triangle[(index + 1) % 3]);
+        if (a.x - plane).abs() < 1e-4 && (b.x - plane).abs() < 1e-4 {
+          match segments
+            .iter()
+            .position(|segment| contains_segment(&[*segment], [a, b]))
+          {
+            Some(index) => {
+              segments.remove(index);
+            }
+            None => segments.push([a, b]),
+          }
+        }
+      }
+    }
+    segments
+  }
+
+  fn assert_same_segments(mut first: Vec<[Vec3; 2]>, second: Vec<[Vec3; 2]>) {
+    assert_eq!(first.len(), second.len());
+    for segment in second.iter() {
+      let index = first
+        .iter()
+        .position(|other| contains_segment(&[*other], *segment))
+        .unwrap();
+      first.remove(index);
+    }
+  }
+
+  // The regular cell from x = `x` to `x + size`, the corners on the side `face` of the cell come
+  // from `front` and the other ones are air when their bit of `back` is set
+  fn regular_cell(x: f32, size: f32, face: usize, front: [[f32; 2]; 2], back: usize) -> GridCell {
+    let mut cell = GridCell {
+      value: [0.0; 8],
+      point: [[0.0, 0.0, 0.0].into(); 8],
+    };
+    for (index, corner) in CORNERS.iter().enumerate() {
+      let [cx, cy, cz] = *corner;
+      cell.value[index] = if cx == face {
+        front[cy][cz]
+      } else if back & (1 << (cy + cz * 2)) != 0 {
+        -1.0
+      } else {
+        1.0
+      };
+      cell.point[index] = [x + cx as f32 * size, cy as f32 * size, cz as f32 * size].into();
+    }
+    cell
+  }
+
+  #[test]
+  fn transition_seams_test_mod_marching_cubes() {
+    let triangles = |cell: &GridCell| -> Vec<[[f32; 3]; 3]> {
+      cell
+        .polygonize(0.0)
+        .into_iter()
+        .map(|(triangle, _, _)| triangle)
+        .collect()
+    };
+
+    // Every sign of the high resolution face, with the high resolution face at x = 0, the low
+    // resolution face at x = 1 and the regular cells on both sides
+    for signs in 0..1 << 9 {
+      let value = |sample: usize| {
+        if signs & (1 << sample) != 0 {
+          -1.0
+        } else {
+          1.0
+        }
+      };
+      let mut cell = TransitionCell {
+        value: [0.0; 13],
+        point: [Vec3::zero(); 13],
+      };
+      for sample in 0..9 {
+        cell.value[sample] = value(sample);
+        cell.point[sample] = Vec3::new(0.0, (sample % 3) as f32, (sample / 3) as f32);
+      }
+      for (index, &sample) in LOW_RESOLUTION.iter().enumerate() {
+        cell.value[9 + index] = value(sample);
+        cell.point[9 + index] = cell.point[sample] + Vec3::new(1.0, 0.0, 0.0);
+      }
+      let transition: Vec<_> = cell
+        .polygonize(0.0, true)
+        .into_iter()
+        .map(|(triangle, _, _)| triangle)
+        .collect();
+
+      // The low resolution cell in the chunk, whatever its other samples are
+      let low = boundary_on_plane(&transition, 1.0);
+      let front = [[value(0), value(6)], [value(2), value(8)]];
+      for back in 0..16 {
+        let regular = triangles(&regular_cell(1.0, 2.0, 0, front, back));
+        assert_same_segments(low.clone(), boundary_on_plane(&regular, 1.0));
+      }
+
+      // The 4 high resolution cells of the neighbor
+      let high = boundary_on_plane(&transition, 0.0);
+      for u in 0..2 {
+        for v in 0..2 {
+          let square: Vec<_> = high
+            .iter()
+            .filter(|[a, b]| {
+              let middle = (*a + *b) / 2.0;
+              middle.y > u as f32
+                && middle.y < u as f32 + 1.0
+                && middle.z > v as f32
+                && middle.z < v as f32 + 1.0
+            })
+            .copied()
+            .collect();
+          let front = [
+            [value(u + v * 3), value(u + (v + 1) * 3)],
+            [value(u + 1 + v * 3), value(u + 1 + (v + 1) * 3)],
+          ];
+          let offset = Vec3::new(0.0, u as f32, v as f32);
+          for back in 0..16 {
+            let regular: Vec<_> = triangles(&regular_cell(-1.0, 1.0, 1, front, back))
+              .into_iter()
+              .map(|triangle| {
+                let moved = |point: [f32; 3]| (Vec3::from(point) + offset).into();
+                [moved(triangle[0]), moved(triangle[1]), moved(triangle[2])]
+              })
+              .collect();
+            assert_same_segments(square.clone(), boundary_on_plane(&regular, 0.0));
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn transition_test_mod_marching_cubes() {
+    let mut world = World::<Chunk>::new([8, 16, 8]);
+    for x in 0..16 {
+      for y in 0..16 {
+        for z in 0..16 {
+          world.set(x, y, z, terrain(x as f32, y as f32, z as f32));
+        }
+      }
+    }
+    world.update_neighbor_data([0, 0, 0]);
+
+    // The first chunk has half the resolution of the second one
+    let low = BakerType::bake::<Chunk, Texture2d, Mesh>(
+      world.get_chunk([0, 0, 0]).unwrap(),
+      &BakerOptions {
+        level_of_detail: 1,
+        lod_transitions: [false, true, false, false, false, false],
+        ..Default::default()
+      },
+    )
+    .unwrap()
+    .unwrap();
+
+    let high = BakerType::bake::<Chunk, Texture2d, Mesh>(
+      world.get_chunk([1, 0, 0]).unwrap(),
+      &BakerOptions::default(),
+    )
+    .unwrap()
+    .unwrap();
+
+    let low_segments = segments_on_plane(&low, 8.0, 0.0);
+    let high_segments = segments_on_plane(&high, 0.0, 8.0);
+
+    // Both sides of the seam have the same edges
+    assert!(!high_segments.is_empty());
+    assert_eq!(low_segments.len(), high_segments.len());
+    for segment in high_segments.iter() {
+      assert!(contains_segment(&low_segments, *segment));
+    }
+
+    // The surface is a height field, so all the normals point up, including the transition cells
+    for normal in low.get_normals().iter() {
+      assert!(normal[1] > 0.0);
+    }
+  }
 }
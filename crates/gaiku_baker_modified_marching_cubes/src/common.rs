@@ -1,9 +1,7 @@
-use gaiku_common::mint::Vector3;
+use gaiku_common::{mint::Vector3, transition::interpolate};
 use glam::Vec3;
 use std::collections::HashMap;
 
-pub(crate) const EPSILON: f32 = 1e-4;
-
 use super::tables::{
   CORNER_TABLE, EDGE_TABLE, ORDINARY_EDGE_LEN, SPECIAL_BARYS, SPECIAL_EDGES, SPECIAL_EDGE_LEN,
   TRIANGLE_TABLE, UV_TABLE,
@@ -17,33 +15,13 @@ pub(crate) struct GridCell {
 
 impl GridCell {
   fn lerp(&self, index1: usize, index2: usize, isolevel: f32) -> [f32; 3] {
-    let mut index1 = index1;
-    let mut index2 = index2;
-
-    if self.value[index2] < self.value[index1] {
-      std::mem::swap(&mut index1, &mut index2);
-    }
-
-    let point1: Vec3 = self.point[index1].into();
-    let point2: Vec3 = self.point[index2].into();
-
-    if (point1 - point2).length() > EPSILON {
-      let value1 = self.value[index1] as f32;
-      let value2 = self.value[index2] as f32;
-
-      if (isolevel - value1).abs() <= EPSILON {
-        point1.into()
-      } else if (isolevel - value2).abs() <= EPSILON {
-        point2.into()
-      } else if isolevel < value1 || isolevel > value2 {
-        unreachable!();
-      } else {
-        let weight = (isolevel - value1) / (value2 - value1);
-        (point1 * weight + point2 * (1. - weight)).into()
-      }
-    } else {
-      self.point[index1].into()
-    }
+    interpolate(
+      self.point[index1].into(),
+      self.value[index1],
+      self.point[index2].into(),
+      self.value[index2],
+      isolevel,
+    )
   }
 
   #[allow(clippy::type_complexity, clippy::many_single_char_names)]
@@ -489,6 +489,7 @@ impl Edge {
   }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct Face {
   indices: [i8; 3],
@@ -687,9 +688,9 @@ impl NGon {
       }
     }
 
-    return vec![NGon {
+    vec![NGon {
       verts: self.verts.clone(),
-    }];
+    }]
   }
 
   // Read up on Polygon triangulation
@@ -776,7 +777,6 @@ fn main() {
   ];
 
   let values: [[bool; 8]; 256] = (0..256)
-    .into_iter()
     .map(|cube_index| {
       [
         (cube_index & 1) == 0,
@@ -0,0 +1,130 @@
+use gaiku_common::{dual_grid::DualGrid, prelude::*, Result};
+use glam::Vec3;
+
+/// Implementation of the naive surface nets terrain generation, it places one vertex per cell
+/// that crosses the surface and connects the vertices of the 4 cells around every edge that
+/// crosses it. The vertices are shared by the quads, with smooth normals from the gradient of the
+/// samples.
+///
+/// With neighbor data the chunk also places the vertices of the cells between it and its
+/// neighbors, and connects the edges that start on its samples, so the chunks join without gaps.
+pub struct SurfaceNetsBaker;
+
+impl Baker for SurfaceNetsBaker {
+  type Value = f32;
+  type AtlasValue = u8;
+
+  // The quads connect the vertices of the cells around each edge
+  const REGION_MARGIN: usize = 2;
+
+  fn bake<C, T, M>(chunk: &C, options: &BakerOptions<T>) -> Result<Option<M>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable,
+    T: Texturify2d,
+    M: Meshify,
+  {
+    let isovalue = options.isovalue;
+    let mut grid = DualGrid::new(chunk);
+
+    // One vertex for each cell that crosses the surface, at the average of the intersections
+    for cell in grid.cells(chunk, isovalue) {
+      let mut sum = Vec3::zero();
+      let mut count = 0;
+      for crossing in cell.crossings(isovalue) {
+        sum += crossing.point;
+        count += 1;
+      }
+
+      let origin = Vec3::new(
+        cell.origin[0] as f32,
+        cell.origin[1] as f32,
+        cell.origin[2] as f32,
+      );
+      let vertex = sum / count as f32;
+      grid.set_vertex(cell.origin, origin + vertex, cell.gradient(vertex));
+    }
+
+    // One quad for each edge that crosses the surface, connecting the 4 cells around it
+    Ok(grid.build::<C, T, M>(chunk, options))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use gaiku_common::{chunk::Chunk, mesh::Mesh, rebake, texture::Texture2d};
+  type BakerType = SurfaceNetsBaker;
+
+  #[test]
+  fn simple_test_surface_nets() {
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 3, 3, 3);
+
+    chunk.set(1, 1, 1, 1.);
+    chunk.set_atlas(1, 1, 1, 0);
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    let positions_count = mesh.get_positions().len();
+    let indices_count = mesh.get_indices().len();
+
+    // One quad for each of the 6 edges that leave the voxel, sharing the vertices of the 8 cells
+    assert_eq!(indices_count, 36);
+    assert_eq!(positions_count, 8);
+
+    // All the normals point away from the voxel
+    for (index, normal) in mesh.get_normals().iter().enumerate() {
+      let position = Vec3::from(mesh.get_positions()[index]);
+      let outwards = position - Vec3::new(1.0, 1.0, 1.0);
+      assert!(Vec3::from(*normal).dot(outwards) > 0.0);
+    }
+  }
+
+  #[test]
+  fn smooth_normals_test_surface_nets() {
+    let options = BakerOptions::default();
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 12, 12, 12);
+    let center = Vec3::new(5.5, 5.5, 5.5);
+
+    for x in 0..12 {
+      for y in 0..12 {
+        for z in 0..12 {
+          let distance = (Vec3::new(x as f32, y as f32, z as f32) - center).length();
+          chunk.set(x, y, z, 4.0 - distance);
+        }
+      }
+    }
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    // The normals follow the sphere instead of the faces of the quads
+    for (position, normal) in mesh.get_positions().iter().zip(mesh.get_normals()) {
+      let outwards = (Vec3::from(*position) - center).normalize();
+      assert!(Vec3::from(*normal).dot(outwards) > 0.95);
+    }
+
+    // Every vertex is shared by the quads around it
+    let mut uses = vec![0; mesh.get_positions().len()];
+    for &index in mesh.get_indices().iter() {
+      uses[index as usize] += 1;
+    }
+    assert!(uses.iter().all(|&uses| uses >= 3));
+  }
+
+  #[test]
+  fn bake_region_test_surface_nets() {
+    rebake::test_support::check_bake_region::<BakerType>(&BakerOptions::default());
+  }
+
+  #[test]
+  fn seams_test_surface_nets() {
+    rebake::test_support::check_seams::<BakerType>(&BakerOptions::default());
+  }
+}
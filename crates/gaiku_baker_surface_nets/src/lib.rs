@@ -0,0 +1,3 @@
+mod baker;
+
+pub use self::baker::SurfaceNetsBaker;
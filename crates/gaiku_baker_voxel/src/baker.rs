@@ -1,8 +1,24 @@
-use super::common::*;
-use gaiku_common::{prelude::*, Result};
+use super::{
+  common::*,
+  greedy::bake_greedy,
+  occlusion::{face_levels, occlusion_at},
+};
+use gaiku_common::{prelude::*, rebake, Result};
 
 use std::{convert::TryInto, marker::PhantomData};
 
+// Offset of the corners of a cell, in the same order as the `GridCell`
+const CORNERS: [[usize; 3]; 8] = [
+  [0, 0, 0],
+  [1, 0, 0],
+  [1, 1, 0],
+  [0, 1, 0],
+  [0, 0, 1],
+  [1, 0, 1],
+  [1, 1, 1],
+  [0, 1, 1],
+];
+
 pub struct VoxelBaker;
 
 impl VoxelBaker {
@@ -33,9 +49,16 @@ impl VoxelBaker {
 
     let isovalue = options.isovalue;
 
-    let x_limit = chunk_width as usize - 1;
-    let y_limit = chunk_height as usize - 1;
-    let z_limit = chunk_depth as usize - 1;
+    if options.greedy_meshing {
+      bake_greedy(chunk, options, &mut builder);
+      return Ok(builder.build::<M>());
+    }
+
+    // With the neighbor data we can also generate the cells between this chunk and the next one
+    let border = if chunk.has_neighbor_data() { 0 } else { 1 };
+    let x_limit = chunk_width as usize - border;
+    let y_limit = chunk_height as usize - border;
+    let z_limit = chunk_depth as usize - border;
 
     for x in 0..x_limit {
       let x = x as Coord;
@@ -63,14 +86,14 @@ impl VoxelBaker {
 
           let grid = GridCell {
             value: [
-              chunk.get(x, y, z) as f32,
-              chunk.get(x + 1, y, z) as f32,
-              chunk.get(x + 1, y + 1, z) as f32,
-              chunk.get(x, y + 1, z) as f32,
-              chunk.get(x, y, z + 1) as f32,
-              chunk.get(x + 1, y, z + 1) as f32,
-              chunk.get(x + 1, y + 1, z + 1) as f32,
-              chunk.get(x, y + 1, z + 1) as f32,
+              chunk.get(x, y, z),
+              chunk.get(x + 1, y, z),
+              chunk.get(x + 1, y + 1, z),
+              chunk.get(x, y + 1, z),
+              chunk.get(x, y, z + 1),
+              chunk.get(x + 1, y, z + 1),
+              chunk.get(x + 1, y + 1, z + 1),
+              chunk.get(x, y + 1, z + 1),
             ],
             point: [
               [fx + 0.0, fy + 0.0, fz + 0.0].into(),
@@ -86,53 +109,98 @@ impl VoxelBaker {
 
           let polys = grid.polygonize(isovalue);
 
-          for (vertex, face_uvs, corner) in polys {
-            let normal = compute_normal(&vertex);
-
-            // Get atlas at this corner_idx
-            let atlas = match corner {
-              0 => chunk.get_atlas(x, y, z),
-              1 => chunk.get_atlas(x + 1, y, z),
-              2 => chunk.get_atlas(x + 1, y + 1, z),
-              3 => chunk.get_atlas(x, y + 1, z),
-              4 => chunk.get_atlas(x, y, z + 1),
-              5 => chunk.get_atlas(x + 1, y, z + 1),
-              6 => chunk.get_atlas(x + 1, y + 1, z + 1),
-              7 => chunk.get_atlas(x, y + 1, z + 1),
-              _ => unreachable!(),
+          // The table splits every face in the triangles `[0, 1, 2]` and `[2, 1, 3]`, with
+          // occlusion they are put back together so the builder can choose the diagonal
+          for quad in polys.chunks(2) {
+            let (triangle, triangle_uvs, corner) = quad[0];
+            let (other, other_uvs, _) = quad[1];
+            let face = [triangle[0], triangle[1], other[2], triangle[2]];
+            let face_uvs = [
+              triangle_uvs[0],
+              triangle_uvs[1],
+              other_uvs[2],
+              triangle_uvs[2],
+            ];
+            let normal = compute_normal(&triangle);
+
+            // The corner of the table can be on either side of the face, the solid voxel is the
+            // one behind the normal
+            let axis = (0..3)
+              .max_by(|&a, &b| normal[a].abs().partial_cmp(&normal[b].abs()).unwrap())
+              .unwrap();
+            let positive = normal[axis] > 0.0;
+            let corner = CORNERS[corner as usize];
+            let mut solid = [x + corner[0], y + corner[1], z + corner[2]];
+            solid[axis] = [x, y, z][axis] + if positive { 0 } else { 1 };
+
+            let atlas = chunk.get_atlas(solid[0], solid[1], solid[2]);
+
+            // Put the table uvs into the atlas uv space
+            let texture_uvs = |uvs: &[[f32; 2]]| {
+              options.texture.as_ref().map(|texture| {
+                // Get the atlas corners
+                // 3-2
+                // 0-1
+                let atlas_uvs = texture.get_uv(atlas);
+                let atlas_origin = atlas_uvs.0;
+                let atlas_dimensions = [
+                  atlas_uvs.2[0] - atlas_uvs.0[0],
+                  atlas_uvs.2[1] - atlas_uvs.0[1],
+                ];
+
+                uvs
+                  .iter()
+                  .map(|uv| {
+                    [
+                      atlas_origin[0] + uv[0] * atlas_dimensions[0],
+                      atlas_origin[1] + uv[1] * atlas_dimensions[1],
+                    ]
+                  })
+                  .collect::<Vec<[f32; 2]>>()
+              })
             };
 
-            let uvs = if let Some(texture) = &options.texture {
-              // Get the atlas corners
-              // 3-2
-              // 0-1
-              let uvs = texture.get_uv(atlas);
-
-              let atlas_origin = uvs.0;
-              let atlas_dimensions = [uvs.2[0] - uvs.0[0], uvs.2[1] - uvs.0[1]];
-              // Put face uvs into atlas uv space
-              let final_uvs: [[f32; 2]; 3] = face_uvs
-                .iter()
-                .map(|uv| {
+            if options.ambient_occlusion {
+              let levels = face_levels(chunk, isovalue, solid, axis, positive);
+              let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+              let mut occlusion = [0.0; 4];
+              for (occlusion, vertex) in occlusion.iter_mut().zip(face.iter()) {
+                *occlusion = occlusion_at(
+                  levels,
                   [
-                    atlas_origin[0] + uv[0] * atlas_dimensions[0],
-                    atlas_origin[1] + uv[1] * atlas_dimensions[1],
-                  ]
-                })
-                .collect::<Vec<[f32; 2]>>()
-                .try_into()
-                .unwrap();
-              Some(final_uvs)
-            } else {
-              None
-            };
+                    vertex[u_axis] - solid[u_axis] as f32 + 0.5,
+                    vertex[v_axis] - solid[v_axis] as f32 + 0.5,
+                  ],
+                );
+              }
 
-            builder.add_triangle(
-              vertex,       // triangle
-              Some(normal), // normal
-              uvs,          // uv
-              atlas.into(), // atlas
-            );
+              let uvs: Option<[[f32; 2]; 4]> =
+                texture_uvs(&face_uvs).map(|uvs| uvs.try_into().unwrap());
+              builder.add_face([0, 1, 2, 3].map(|i| Vertex {
+                position: face[i],
+                normal: Some(normal),
+                uv: uvs.map(|uvs| uvs[i]),
+                color: options.color(atlas),
+                occlusion: Some(occlusion[i]),
+                atlas_index: atlas.into(),
+                ..Default::default()
+              }));
+            } else {
+              // Without occlusion the diagonal doesn't matter, the triangles of the table are
+              // kept as they are
+              for &(triangle, triangle_uvs, _) in quad {
+                let uvs: Option<[[f32; 2]; 3]> =
+                  texture_uvs(&triangle_uvs).map(|uvs| uvs.try_into().unwrap());
+                builder.add_triangle([0, 1, 2].map(|i| Vertex {
+                  position: triangle[i],
+                  normal: Some(compute_normal(&triangle)),
+                  uv: uvs.map(|uvs| uvs[i]),
+                  color: options.color(atlas),
+                  atlas_index: atlas.into(),
+                  ..Default::default()
+                }));
+              }
+            }
           }
         }
       }
@@ -142,11 +210,13 @@ impl VoxelBaker {
   }
 }
 
-// TODO: Optimize, don't create faces between chunks if there's a non empty voxel
 impl Baker for VoxelBaker {
   type Value = f32;
   type AtlasValue = u8;
 
+  // The ambient occlusion of the faces reads the voxels around them
+  const REGION_MARGIN: usize = 2;
+
   fn bake<C, T, M>(chunk: &C, options: &BakerOptions<T>) -> Result<Option<M>>
   where
     C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable,
@@ -159,12 +229,31 @@ impl Baker for VoxelBaker {
       Self::bake_with_builder::<C, T, M, NoTreeBuilder>(chunk, options, Default::default())
     }
   }
+
+  /// The greedy meshing merges faces across the whole chunk, so it's baked again entirely.
+  fn bake_region<C, T, M>(
+    chunk: &C,
+    region: &DirtyRegion,
+    mesh: Option<M>,
+    options: &BakerOptions<T>,
+  ) -> Result<Option<M>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable,
+    T: Texturify2d,
+    M: Meshify,
+  {
+    if options.greedy_meshing {
+      Self::bake(chunk, options)
+    } else {
+      rebake::bake_region::<Self, C, T, M>(chunk, region, mesh, options)
+    }
+  }
 }
 
 #[cfg(test)]
 mod test {
   use super::*;
-  use gaiku_common::{chunk::Chunk, mesh::Mesh, texture::Texture2d};
+  use gaiku_common::{chunk::Chunk, mesh::Mesh, texture::Texture2d, world::World};
   type BakerType = VoxelBaker;
 
   #[test]
@@ -188,4 +277,294 @@ mod test {
     assert_eq!(indices_count, 144);
     assert_eq!(positions_count, 78);
   }
+
+  #[test]
+  fn neighbor_test_voxel() {
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 3, 3, 3);
+    let mut neighbor = Chunk::new([3.0, 0.0, 0.0], 3, 3, 3);
+
+    neighbor.set(0, 1, 1, 1.);
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options).unwrap();
+    assert!(mesh.is_none());
+
+    assert!(chunk.update_neighbor_data(&neighbor));
+
+    let mesh: Mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    assert!(mesh
+      .get_positions()
+      .iter()
+      .all(|position| position[0] >= 2.0 && position[0] <= 3.0));
+  }
+
+  #[test]
+  fn greedy_test_voxel() {
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      greedy_meshing: true,
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 16, 3, 16);
+
+    for x in 0..16 {
+      for z in 0..16 {
+        chunk.set(x, 1, z, 1.);
+      }
+    }
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    // Just the top and the bottom quads
+    assert_eq!(mesh.get_indices().len(), 12);
+    assert_eq!(mesh.get_positions().len(), 8);
+    assert!(mesh.get_uvs().contains(&[15.0, 15.0]));
+
+    // Different atlas indices can't be merged
+    chunk.set_atlas(0, 1, 0, 1);
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    assert!(mesh.get_indices().len() > 12);
+  }
+
+  #[test]
+  fn greedy_atlas_test_voxel() {
+    let texture = TextureAtlas2d::<Texture2d>::new(16);
+    let (origin, _, end, _) = texture.get_uv(2);
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      greedy_meshing: true,
+      texture: Some(texture),
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 6, 3, 6);
+
+    for x in 0..6 {
+      for z in 0..6 {
+        chunk.set(x, 1, z, 1.);
+        chunk.set_atlas(x, 1, z, 2);
+      }
+    }
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    // The uvs repeat over the merged quad, inside of the tile of the atlas index
+    assert_eq!(mesh.get_indices().len(), 12);
+    assert!(mesh.get_uvs().contains(&[5.0, 5.0]));
+    assert_eq!(mesh.get_tiles().len(), mesh.get_positions().len());
+    assert!(mesh
+      .get_tiles()
+      .iter()
+      .all(|tile| *tile == [origin[0], origin[1], end[0] - origin[0], end[1] - origin[1]]));
+
+    // Without texture there are no tiles
+    let options = BakerOptions {
+      texture: None,
+      ..options
+    };
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+    assert!(mesh.get_tiles().is_empty());
+  }
+
+  #[test]
+  fn occlusion_test_voxel() {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 5, 4, 5);
+
+    for x in 0..5 {
+      for z in 0..5 {
+        chunk.set(x, 1, z, 1.);
+      }
+    }
+    chunk.set(2, 2, 2, 1.);
+
+    for &greedy_meshing in [false, true].iter() {
+      let options = BakerOptions {
+        remove_duplicate_verts: true,
+        greedy_meshing,
+        ..Default::default()
+      };
+
+      let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+        .unwrap()
+        .unwrap();
+      assert!(mesh.get_occlusions().is_empty());
+
+      let options = BakerOptions {
+        ambient_occlusion: true,
+        ..options
+      };
+
+      let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+        .unwrap()
+        .unwrap();
+      assert_eq!(mesh.get_occlusions().len(), mesh.get_positions().len());
+
+      let floor = mesh
+        .get_positions()
+        .iter()
+        .zip(mesh.get_normals())
+        .zip(mesh.get_occlusions())
+        .filter(|((position, normal), _)| position[1] == 1.5 && normal[1] > 0.5);
+
+      for ((position, _), &occlusion) in floor {
+        if position[0] <= 0.5 || position[0] >= 3.5 || position[2] <= 0.5 || position[2] >= 3.5 {
+          // Far from the block
+          assert_eq!(occlusion, 1.0);
+        } else if (position[0] == 1.5 || position[0] == 2.5)
+          && (position[2] == 1.5 || position[2] == 2.5)
+        {
+          // The corners of the block, only one voxel occludes them
+          assert!((occlusion - 2.0 / 3.0).abs() < 1e-4);
+        }
+      }
+
+      // Nothing occludes the top of the block
+      assert!(mesh
+        .get_positions()
+        .iter()
+        .zip(mesh.get_occlusions())
+        .filter(|(position, _)| position[1] == 2.5)
+        .all(|(_, &occlusion)| occlusion == 1.0));
+    }
+  }
+
+  #[test]
+  fn greedy_occlusion_test_voxel() {
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      greedy_meshing: true,
+      ambient_occlusion: true,
+      ..Default::default()
+    };
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 8, 4, 8);
+
+    // A floor with a wall along x, the floor next to the wall is darker towards it
+    for x in 0..8 {
+      for z in 0..8 {
+        chunk.set(x, 1, z, 1.);
+      }
+      chunk.set(x, 2, 0, 1.);
+    }
+
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+    let positions = mesh.get_positions();
+    let occlusions = mesh.get_occlusions();
+
+    // The faces with different occlusion on their corners are not merged
+    let mut shaded = 0;
+    for triangle in mesh.get_indices().chunks(3) {
+      let corners: Vec<_> = triangle.iter().map(|&index| index as usize).collect();
+      let uniform = corners
+        .iter()
+        .all(|&index| occlusions[index] == occlusions[corners[0]]);
+      if !uniform {
+        shaded += 1;
+        let first = positions[corners[0]];
+        for &index in corners.iter() {
+          let position = positions[index];
+          for (coord, first) in position.iter().zip(first.iter()) {
+            assert!((coord - first).abs() <= 1.0);
+          }
+        }
+      }
+    }
+    assert!(shaded > 0);
+  }
+
+  #[test]
+  fn occlusion_neighbor_test_voxel() {
+    let options = BakerOptions {
+      remove_duplicate_verts: true,
+      ambient_occlusion: true,
+      ..Default::default()
+    };
+    let mut world = World::<Chunk>::new([4, 4, 4]);
+
+    // A floor across both chunks and a block on the last column of the first one
+    for x in 0..8 {
+      for z in 0..4 {
+        world.set(x, 1, z, 1.);
+      }
+    }
+    world.set(3, 2, 2, 1.);
+    world.update_neighbor_data([1, 0, 0]);
+
+    let chunk = world.get_chunk([1, 0, 0]).unwrap();
+    let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(chunk, &options)
+      .unwrap()
+      .unwrap();
+
+    // The block occludes the start of the floor next to it
+    let border: Vec<_> = mesh
+      .get_positions()
+      .iter()
+      .zip(mesh.get_normals())
+      .zip(mesh.get_occlusions())
+      .filter(|((position, normal), _)| position[1] == 1.5 && normal[1] > 0.5)
+      .filter(|((position, _), _)| position[0] == 0.0 && position[2] == 2.0)
+      .map(|(_, &occlusion)| occlusion)
+      .collect();
+    assert!(!border.is_empty());
+    assert!(border.iter().all(|&occlusion| occlusion < 1.0));
+  }
+
+  #[test]
+  fn color_test_voxel() {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 4, 3, 3);
+
+    chunk.set(1, 1, 1, 1.);
+    chunk.set_atlas(1, 1, 1, 1);
+    chunk.set(2, 1, 1, 1.);
+    chunk.set_atlas(2, 1, 1, 2);
+
+    let palette = vec![[0, 0, 0, 255], [255, 0, 0, 255], [0, 0, 255, 255]];
+
+    for &greedy_meshing in [false, true].iter() {
+      let options = BakerOptions {
+        greedy_meshing,
+        palette: Some(palette.clone()),
+        ..Default::default()
+      };
+
+      let mesh = BakerType::bake::<Chunk, Texture2d, Mesh>(&chunk, &options)
+        .unwrap()
+        .unwrap();
+
+      assert_eq!(mesh.get_colors().len(), mesh.get_positions().len());
+
+      // Each voxel keeps its own color
+      for (position, color) in mesh.get_positions().iter().zip(mesh.get_colors()) {
+        if position[0] < 1.5 {
+          assert_eq!(*color, palette[1]);
+        } else if position[0] > 1.5 {
+          assert_eq!(*color, palette[2]);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn bake_region_test_voxel() {
+    rebake::test_support::check_bake_region::<BakerType>(&BakerOptions {
+      ambient_occlusion: true,
+      ..Default::default()
+    });
+  }
 }
@@ -32,8 +32,8 @@ impl GridCell {
     let point2: Vec3 = self.point[index2].into();
 
     if (point1 - point2).length() > EPSILON {
-      let value1 = self.value[index1] as f32;
-      let value2 = self.value[index2] as f32;
+      let value1 = self.value[index1];
+      let value2 = self.value[index2];
 
       if (isovalue - value1).abs() <= EPSILON {
         point1.into()
@@ -0,0 +1,229 @@
+use super::occlusion::{face_levels, occlusion_at};
+use gaiku_common::prelude::*;
+use glam::Vec3;
+
+// A face in the slice mask: the atlas index, if it points to the positive side of the axis and the
+// occlusion levels of its corners
+type MaskFace = Option<(u8, bool, [u8; 4])>;
+
+/// Generates the same surface as the table based voxel baker, but merging the coplanar faces that
+/// share the same atlas index into the biggest possible quads.
+///
+/// UVs are generated in voxel units, `[0, 0]` to `[quad width, quad height]`, so the texture
+/// repeats once per voxel over the merged quad. With a `texture` every vertex also gets the tile
+/// of its atlas index, and the shader samples `tile origin + fract(uv) * tile size` to repeat the
+/// tile instead of the whole atlas.
+///
+/// With `ambient_occlusion` only the faces with the same occlusion level on all their corners are
+/// merged, the faces with different levels keep their own quad so the occlusion is interpolated
+/// over a single voxel, and not stretched over the merged quad.
+pub(crate) fn bake_greedy<C, T, MB>(chunk: &C, options: &BakerOptions<T>, builder: &mut MB)
+where
+  C: Chunkify<f32> + Atlasify<u8> + Sizable,
+  T: Texturify2d,
+  MB: MeshBuilder,
+{
+  let isovalue = options.isovalue;
+  let ambient_occlusion = options.ambient_occlusion;
+
+  // With the neighbor data we can also generate the cells between this chunk and the next one
+  let border = if chunk.has_neighbor_data() { 0 } else { 1 };
+  let limits = [
+    chunk.width() as usize - border,
+    chunk.height() as usize - border,
+    chunk.depth() as usize - border,
+  ];
+
+  for axis in 0..3 {
+    let u_axis = (axis + 1) % 3;
+    let v_axis = (axis + 2) % 3;
+    let (u_len, v_len) = (limits[u_axis] + 1, limits[v_axis] + 1);
+
+    // Each slice is the plane between the samples `slice` and `slice + 1` on the axis
+    for slice in 0..limits[axis] {
+      let mut mask: Vec<MaskFace> = vec![None; u_len * v_len];
+
+      for v in 0..v_len {
+        for u in 0..u_len {
+          let mut back = [0; 3];
+          back[axis] = slice;
+          back[u_axis] = u;
+          back[v_axis] = v;
+          let mut front = back;
+          front[axis] += 1;
+
+          let back_air = chunk.is_air(back[0], back[1], back[2], isovalue);
+          let front_air = chunk.is_air(front[0], front[1], front[2], isovalue);
+
+          let (solid, positive) = match (back_air, front_air) {
+            (false, true) => (back, true),
+            (true, false) => (front, false),
+            _ => continue,
+          };
+          let levels = if ambient_occlusion {
+            face_levels(chunk, isovalue, solid, axis, positive)
+          } else {
+            [3; 4]
+          };
+
+          mask[u + v * u_len] = Some((
+            chunk.get_atlas(solid[0], solid[1], solid[2]),
+            positive,
+            levels,
+          ));
+        }
+      }
+
+      for v in 0..v_len {
+        let mut u = 0;
+        while u < u_len {
+          let face = mask[u + v * u_len];
+          let mergeable = match face {
+            Some((_, _, levels)) => levels.iter().all(|&level| level == levels[0]),
+            None => {
+              u += 1;
+              continue;
+            }
+          };
+
+          let mut width = 1;
+          while mergeable && u + width < u_len && mask[u + width + v * u_len] == face {
+            width += 1;
+          }
+
+          let mut height = 1;
+          'grow: while mergeable && v + height < v_len {
+            for du in 0..width {
+              if mask[u + du + (v + height) * u_len] != face {
+                break 'grow;
+              }
+            }
+            height += 1;
+          }
+
+          for dv in 0..height {
+            for du in 0..width {
+              mask[u + du + (v + dv) * u_len] = None;
+            }
+          }
+
+          let (atlas, positive, levels) = face.unwrap();
+          let u_range = [clamp_start(u), clamp_end(u + width - 1, limits[u_axis])];
+          let v_range = [clamp_start(v), clamp_end(v + height - 1, limits[v_axis])];
+
+          // Either a single face or merged faces with the same level on every corner, so the
+          // levels are interpolated over the whole quad before clamping it to the chunk
+          let occlusion = if ambient_occlusion {
+            let corner = |u_position: f32, v_position: f32| {
+              occlusion_at(
+                levels,
+                [
+                  (u_position - u as f32 + 0.5) / width as f32,
+                  (v_position - v as f32 + 0.5) / height as f32,
+                ],
+              )
+            };
+            Some([
+              corner(u_range[0], v_range[0]),
+              corner(u_range[1], v_range[0]),
+              corner(u_range[1], v_range[1]),
+              corner(u_range[0], v_range[1]),
+            ])
+          } else {
+            None
+          };
+
+          let tile = options.texture.as_ref().map(|texture| {
+            // Get the atlas corners
+            // 3-2
+            // 0-1
+            let uvs = texture.get_uv(atlas);
+            [uvs.0[0], uvs.0[1], uvs.2[0] - uvs.0[0], uvs.2[1] - uvs.0[1]]
+          });
+
+          add_quad(
+            builder,
+            [axis, u_axis, v_axis],
+            slice as f32 + 0.5,
+            u_range,
+            v_range,
+            positive,
+            options.color(atlas),
+            occlusion,
+            tile,
+            atlas,
+          );
+
+          u += width;
+        }
+      }
+    }
+  }
+}
+
+// The voxel extends half a unit around its sample, but the surface is clipped to the chunk cells
+fn clamp_start(sample: usize) -> f32 {
+  (sample as f32 - 0.5).max(0.0)
+}
+
+fn clamp_end(sample: usize, limit: usize) -> f32 {
+  (sample as f32 + 0.5).min(limit as f32)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_quad<MB>(
+  builder: &mut MB,
+  [axis, u_axis, v_axis]: [usize; 3],
+  depth: f32,
+  [u_start, u_end]: [f32; 2],
+  [v_start, v_end]: [f32; 2],
+  positive: bool,
+  color: Option<[u8; 4]>,
+  mut occlusion: Option<[f32; 4]>,
+  tile: Option<[f32; 4]>,
+  atlas: u8,
+) where
+  MB: MeshBuilder,
+{
+  let point = |u: f32, v: f32| {
+    let mut point = [0.0; 3];
+    point[axis] = depth;
+    point[u_axis] = u;
+    point[v_axis] = v;
+    point
+  };
+
+  let mut normal = [0.0; 3];
+  normal[axis] = if positive { 1.0 } else { -1.0 };
+
+  let (width, height) = (u_end - u_start, v_end - v_start);
+  let mut face = [
+    point(u_start, v_start),
+    point(u_end, v_start),
+    point(u_end, v_end),
+    point(u_start, v_end),
+  ];
+  let mut uvs = [[0.0, 0.0], [width, 0.0], [width, height], [0.0, height]];
+
+  // The face needs to be counter-clockwise when looking at it from the normal side
+  let winding = (Vec3::from(face[1]) - Vec3::from(face[0]))
+    .cross(Vec3::from(face[3]) - Vec3::from(face[0]))
+    .dot(normal.into());
+  if winding < 0.0 {
+    face.reverse();
+    uvs.reverse();
+    if let Some(occlusion) = &mut occlusion {
+      occlusion.reverse();
+    }
+  }
+
+  builder.add_face([0, 1, 2, 3].map(|i| Vertex {
+    position: face[i],
+    normal: Some(normal),
+    uv: Some(uvs[i]),
+    color,
+    occlusion: occlusion.map(|occlusion| occlusion[i]),
+    tile,
+    atlas_index: atlas.into(),
+  }));
+}
@@ -1,6 +1,8 @@
 mod baker;
 /// Implementation of a naive cubical voxel terrain generation.
 mod common;
+mod greedy;
+mod occlusion;
 mod tables;
 
 pub use self::baker::VoxelBaker;
@@ -0,0 +1,63 @@
+use gaiku_common::prelude::*;
+
+// Corners of a face on its `u` and `v` axes, in the same order the faces are built
+const FACE_CORNERS: [[isize; 2]; 4] = [[-1, -1], [1, -1], [1, 1], [-1, 1]];
+
+/// Occlusion level of the corners of the face of the `solid` voxel that points to the `axis`, from
+/// `0` (fully occluded) to `3` (not occluded).
+///
+/// Each corner checks the two voxels on its sides and the one on its diagonal, all of them in front
+/// of the face. The voxels outside of the chunk (and its neighbor data) don't occlude.
+pub(crate) fn face_levels<C>(
+  chunk: &C,
+  isovalue: f32,
+  solid: [usize; 3],
+  axis: usize,
+  positive: bool,
+) -> [u8; 4]
+where
+  C: Chunkify<f32>,
+{
+  let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+  let mut front = [solid[0] as isize, solid[1] as isize, solid[2] as isize];
+  front[axis] += if positive { 1 } else { -1 };
+
+  // The voxels before the chunk are read with `usize::MAX` from the neighbor data
+  let neighbor_data = chunk.has_neighbor_data();
+  let occludes = |u: isize, v: isize| {
+    let mut point = front;
+    point[u_axis] += u;
+    point[v_axis] += v;
+    (neighbor_data || point.iter().all(|&coord| coord >= 0))
+      && !chunk.is_air(
+        point[0] as usize,
+        point[1] as usize,
+        point[2] as usize,
+        isovalue,
+      )
+  };
+
+  let mut levels = [0; 4];
+  for (level, [u, v]) in levels.iter_mut().zip(FACE_CORNERS.iter()) {
+    let (side_u, side_v) = (occludes(*u, 0), occludes(0, *v));
+    // Both sides close the corner, it doesn't matter what's on the diagonal
+    *level = if side_u && side_v {
+      0
+    } else {
+      3 - side_u as u8 - side_v as u8 - occludes(*u, *v) as u8
+    };
+  }
+
+  levels
+}
+
+/// Occlusion of a point of the face, `[0.0, 0.0]` to `[1.0, 1.0]` on its `u` and `v` axes,
+/// interpolated from the corner levels. `1.0` means not occluded.
+pub(crate) fn occlusion_at(levels: [u8; 4], [u, v]: [f32; 2]) -> f32 {
+  let (u, v) = (u.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+  let light = |corner: usize| levels[corner] as f32 / 3.0;
+
+  let bottom = light(0) * (1.0 - u) + light(1) * u;
+  let top = light(3) * (1.0 - u) + light(2) * u;
+  bottom * (1.0 - v) + top * v
+}
@@ -26,9 +26,9 @@ fn add_to_tables(
   uvs: [[f32; 2]; 4],
   corner_idx: i8,
   edge_table: &mut [u32; 256],
-  triangle_table: &mut Vec<Vec<i8>>,
-  uv_table: &mut Vec<Vec<[f32; 2]>>,
-  corner_table: &mut Vec<Vec<i8>>,
+  triangle_table: &mut [Vec<i8>],
+  uv_table: &mut [Vec<[f32; 2]>],
+  corner_table: &mut [Vec<i8>],
 ) {
   if a != b {
     for v in verts.iter() {
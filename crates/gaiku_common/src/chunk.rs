@@ -1,17 +1,139 @@
+mod apron;
 #[allow(clippy::module_inception)]
 mod chunk;
+mod palette_chunk;
 mod sparse_chunk;
 
 pub use chunk::Chunk;
+pub use palette_chunk::PaletteChunk;
 pub use sparse_chunk::SparseChunk;
 
+use crate::{atlas::Atlasify, boxify::*};
+
 /// Base common denominator across all the chunk implementations used.
 pub trait Chunkify<T> {
   fn is_air(&self, x: usize, y: usize, z: usize, isovalue: f32) -> bool;
   fn get(&self, x: usize, y: usize, z: usize) -> T;
+
+  /// Returns `true` when the chunk holds a copy of the border samples of its neighbors (see
+  /// `Apronify`), in that case `get` and `is_air` also accept two samples past the border on every
+  /// side as coordinates, `-2` and `-1` (as `usize::MAX - 1` and `usize::MAX`) and `width` and
+  /// `width + 1` on x, so the bakers can generate the cells between this chunk and the next one
+  /// and look around the samples of those cells.
+  fn has_neighbor_data(&self) -> bool {
+    false
+  }
+
+  /// Returns `true` when the neighbor data holds the samples of the neighbor at `offset`, its
+  /// chunk coordinate relative to this one, from `-1` to `1` on each axis. The samples of the
+  /// missing neighbors read as air, so the bakers that need real values past the border, like
+  /// the heights of a heightmap, check each side.
+  fn has_neighbor(&self, _offset: [i32; 3]) -> bool {
+    false
+  }
+
+  /// The gradient of the values at the sample, when the chunk provides it. It points to where the
+  /// values increase, from the air into the solid. The bakers that need hermite data use it as the
+  /// surface normal, and estimate it with finite differences when it's `None`.
+  fn get_gradient(&self, _x: usize, _y: usize, _z: usize) -> Option<[f32; 3]> {
+    None
+  }
+}
+
+/// Defines a chunk that keeps a copy of the samples of its 26 neighbors that touch its border, the
+/// apron or neighbor data, two samples thick on every side.
+///
+/// The samples of the neighbors on the positive sides let the bakers generate the cells between
+/// the chunks, the ones on the negative sides are read for the values that look around a sample,
+/// like the normals or the ambient occlusion. Samples missing in the apron read as air.
+pub trait Apronify {
+  /// Copies the samples of `neighbor` that touch the chunk, where `offset` is the chunk coordinate
+  /// of the neighbor relative to this one, from `-1` to `1` on each axis.
+  ///
+  /// Returns `false` when `neighbor` is not one of the 26 neighbors. The samples that change are
+  /// marked as dirty, with the size of the chunk as the coordinate past it, or `0` before it.
+  fn update_apron<C>(&mut self, offset: [i32; 3], neighbor: &C) -> bool
+  where
+    C: Chunkify<f32> + Atlasify<u8> + Sizable;
+
+  /// Removes the samples of the neighbor at `offset`, like when it's unloaded, they read as air
+  /// again. The samples that change are marked as dirty like in `update_apron`. Returns `false`
+  /// when the neighbor data doesn't have that neighbor.
+  fn remove_apron(&mut self, offset: [i32; 3]) -> bool;
+
+  /// Same as `update_apron`, with the offset of the neighbor found from the position and size of
+  /// both chunks. Returns `false` when `neighbor` doesn't touch this chunk.
+  fn update_neighbor_data<C>(&mut self, neighbor: &C) -> bool
+  where
+    Self: Boxify + Sized,
+    C: Chunkify<f32> + Atlasify<u8> + Boxify,
+  {
+    let position = self.position();
+    let size = [self.width(), self.height(), self.depth()];
+    let neighbor_size = [neighbor.width(), neighbor.height(), neighbor.depth()];
+    let mut offset = [0; 3];
+
+    for (axis, offset) in offset.iter_mut().enumerate() {
+      let delta = neighbor.position()[axis] - position[axis];
+      if delta.abs() < 1e-4 {
+        *offset = 0;
+      } else if (delta - size[axis] as f32).abs() < 1e-4 {
+        *offset = 1;
+      } else if (delta + neighbor_size[axis] as f32).abs() < 1e-4 {
+        *offset = -1;
+      } else {
+        return false;
+      }
+    }
+
+    self.update_apron(offset, neighbor)
+  }
 }
 
 /// Defines a mutable chunk.
 pub trait ChunkifyMut<T> {
   fn set(&mut self, x: usize, y: usize, z: usize, value: T);
 }
+
+/// Box of samples of a chunk, from `min` to `max` included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyRegion {
+  pub min: [usize; 3],
+  pub max: [usize; 3],
+}
+
+impl DirtyRegion {
+  /// The region of a single sample.
+  pub fn new(sample: [usize; 3]) -> Self {
+    Self {
+      min: sample,
+      max: sample,
+    }
+  }
+
+  /// Grows the region to contain the sample.
+  pub fn include(&mut self, sample: [usize; 3]) {
+    for (axis, &value) in sample.iter().enumerate() {
+      self.min[axis] = self.min[axis].min(value);
+      self.max[axis] = self.max[axis].max(value);
+    }
+  }
+
+  /// Grows the region to contain the other one.
+  pub fn merge(&mut self, other: &DirtyRegion) {
+    self.include(other.min);
+    self.include(other.max);
+  }
+
+  pub fn contains(&self, sample: [usize; 3]) -> bool {
+    (0..3).all(|axis| self.min[axis] <= sample[axis] && sample[axis] <= self.max[axis])
+  }
+}
+
+/// Defines a chunk that keeps track of the samples changed since it was baked, so only the cells
+/// around them need to be baked again with `Baker::bake_region`.
+pub trait Dirtify {
+  /// Box of the samples changed since the last `clear_dirty`, `None` when nothing changed.
+  fn dirty_region(&self) -> Option<DirtyRegion>;
+  fn clear_dirty(&mut self);
+}
@@ -0,0 +1,224 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{atlas::Atlasify, boxify::*, chunk::Chunkify};
+
+/// Samples of the apron on each side of the chunk, enough for the central differences of the
+/// samples of the first cells past the border.
+pub(crate) const THICKNESS: usize = 2;
+
+/// Copy of the samples of the 26 neighbors of a chunk that touch its border: a shell `THICKNESS`
+/// samples thick around the chunk, from `-2` to the size of the chunk plus `1` on each axis.
+///
+/// The negative coordinates are passed wrapped, `-1` is `usize::MAX`, what
+/// `0_usize.wrapping_sub(1)` returns.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct Apron {
+  samples: Vec<(u8, f32)>,
+  // One bit for each of the 27 offsets copied, see `neighbor_bit`
+  neighbors: u32,
+}
+
+impl Apron {
+  pub fn is_empty(&self) -> bool {
+    self.samples.is_empty()
+  }
+
+  /// Returns `true` when the samples of the neighbor at `offset` were copied.
+  pub fn has_neighbor(&self, offset: [i32; 3]) -> bool {
+    Self::neighbor_bit(offset).is_some_and(|bit| self.neighbors & bit != 0)
+  }
+
+  /// The sample of the apron, `None` inside of the chunk, past the apron or before any update.
+  pub fn get(&self, size: [usize; 3], x: usize, y: usize, z: usize) -> Option<(u8, f32)> {
+    Self::index(size, x, y, z).and_then(|index| self.samples.get(index).copied())
+  }
+
+  /// Copies the samples of `neighbor` that touch the chunk, where `offset` is its coordinate
+  /// relative to the chunk, from `-1` to `1` on each axis.
+  ///
+  /// `changed` is called with every sample that changes, clamped to `0` before the chunk and to
+  /// its size past it. Returns `false` when `neighbor` is not one of the 26 neighbors.
+  pub fn update<C, F>(
+    &mut self,
+    size: [usize; 3],
+    offset: [i32; 3],
+    neighbor: &C,
+    changed: F,
+  ) -> bool
+  where
+    C: Chunkify<f32> + Atlasify<u8> + Sizable,
+    F: FnMut([usize; 3]),
+  {
+    let bit = match Self::neighbor_bit(offset) {
+      Some(bit) if offset != [0, 0, 0] => bit,
+      _ => return false,
+    };
+
+    if self.samples.is_empty() {
+      self.samples = vec![(0, -1.); Self::len(size)];
+    }
+    self.neighbors |= bit;
+
+    let neighbor_size = [
+      neighbor.width() as usize,
+      neighbor.height() as usize,
+      neighbor.depth() as usize,
+    ];
+    self.fill(size, offset, neighbor_size, changed, |[x, y, z]| {
+      (neighbor.get_atlas(x, y, z), neighbor.get(x, y, z))
+    });
+
+    true
+  }
+
+  /// Sets back to air the samples of the neighbor at `offset`, like when it's unloaded. `changed`
+  /// is called like in `update`. Returns `false` when the neighbor wasn't copied.
+  pub fn remove<F>(&mut self, size: [usize; 3], offset: [i32; 3], changed: F) -> bool
+  where
+    F: FnMut([usize; 3]),
+  {
+    if !self.has_neighbor(offset) {
+      return false;
+    }
+
+    self.neighbors &= !Self::neighbor_bit(offset).unwrap_or(0);
+    // Every sample of the side, whatever the size of the neighbor was
+    self.fill(size, offset, [usize::MAX; 3], changed, |_| (0, -1.));
+    if self.neighbors == 0 {
+      self.samples = vec![];
+    }
+
+    true
+  }
+
+  // Writes the samples of the side at `offset`, reading them from the neighbor coordinates
+  fn fill<F, V>(
+    &mut self,
+    size: [usize; 3],
+    offset: [i32; 3],
+    neighbor_size: [usize; 3],
+    mut changed: F,
+    value_at: V,
+  ) where
+    F: FnMut([usize; 3]),
+    V: Fn([usize; 3]) -> (u8, f32),
+  {
+    // The first sample of the chunk and of the neighbor on each axis, and how many of them
+    let mut start = [0; 3];
+    let mut local_start = [0; 3];
+    let mut count = [0; 3];
+    for axis in 0..3 {
+      match offset[axis] {
+        -1 => {
+          count[axis] = neighbor_size[axis].min(THICKNESS);
+          start[axis] = 0_usize.wrapping_sub(count[axis]);
+          local_start[axis] = neighbor_size[axis] - count[axis];
+        }
+        0 => count[axis] = size[axis].min(neighbor_size[axis]),
+        _ => {
+          start[axis] = size[axis];
+          count[axis] = neighbor_size[axis].min(THICKNESS);
+        }
+      }
+    }
+
+    for i in 0..count[0] {
+      for j in 0..count[1] {
+        for k in 0..count[2] {
+          let [x, y, z] = [
+            start[0].wrapping_add(i),
+            start[1].wrapping_add(j),
+            start[2].wrapping_add(k),
+          ];
+
+          if let Some(index) = Self::index(size, x, y, z) {
+            let value = value_at([local_start[0] + i, local_start[1] + j, local_start[2] + k]);
+            if self.samples[index] != value {
+              self.samples[index] = value;
+              let clamp = |coord: usize, size: usize| {
+                if coord > size + THICKNESS {
+                  0
+                } else {
+                  coord.min(size)
+                }
+              };
+              changed([clamp(x, size[0]), clamp(y, size[1]), clamp(z, size[2])]);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  // Stored as the slabs on x, followed by the slabs on y without the samples already on the x
+  // slabs, and the slabs on z without the ones on the x and y slabs
+  fn index(size: [usize; 3], x: usize, y: usize, z: usize) -> Option<usize> {
+    let [width, height, depth] = size;
+    let t = THICKNESS;
+    // Moved by the thickness, so the first sample of the apron is `0`
+    let (x, y, z) = (x.wrapping_add(t), y.wrapping_add(t), z.wrapping_add(t));
+    let (x_slab, y_slab, z_slab) = (
+      (height + 2 * t) * (depth + 2 * t),
+      width * (depth + 2 * t),
+      width * height,
+    );
+    // Layer of the slab, from `0` to `2 * t` on both sides
+    let layer = |coord: usize, size: usize| if coord < t { coord } else { coord - size };
+    let inside = |coord: usize, size: usize| coord >= t && coord < size + t;
+
+    if x >= width + 2 * t || y >= height + 2 * t || z >= depth + 2 * t {
+      None
+    } else if !inside(x, width) {
+      Some(layer(x, width) * x_slab + y + z * (height + 2 * t))
+    } else if !inside(y, height) {
+      Some(2 * t * x_slab + layer(y, height) * y_slab + (x - t) + z * width)
+    } else if !inside(z, depth) {
+      Some(2 * t * (x_slab + y_slab) + layer(z, depth) * z_slab + (x - t) + (y - t) * width)
+    } else {
+      None
+    }
+  }
+
+  fn neighbor_bit(offset: [i32; 3]) -> Option<u32> {
+    if offset.iter().all(|offset| offset.abs() <= 1) {
+      Some(1 << ((offset[0] + 1) + (offset[1] + 1) * 3 + (offset[2] + 1) * 9))
+    } else {
+      None
+    }
+  }
+
+  fn len([width, height, depth]: [usize; 3]) -> usize {
+    let t = THICKNESS;
+    2 * t * ((height + 2 * t) * (depth + 2 * t) + width * (depth + 2 * t) + width * height)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn check_index() {
+    let size = [4, 5, 6];
+    let t = THICKNESS;
+    let mut indices = vec![];
+    for x in 0..4 + 2 * t {
+      for y in 0..5 + 2 * t {
+        for z in 0..6 + 2 * t {
+          let (x, y, z) = (x.wrapping_sub(t), y.wrapping_sub(t), z.wrapping_sub(t));
+          indices.extend(Apron::index(size, x, y, z));
+        }
+      }
+    }
+
+    // Every sample of the shell has its own index
+    indices.sort_unstable();
+    assert_eq!(indices, (0..Apron::len(size)).collect::<Vec<_>>());
+    assert_eq!(Apron::len(size), 8 * 9 * 10 - 4 * 5 * 6);
+    assert_eq!(Apron::index(size, 1, 2, 3), None);
+    assert_eq!(Apron::index(size, 6, 0, 0), None);
+    assert_eq!(Apron::index(size, 0_usize.wrapping_sub(3), 0, 0), None);
+  }
+}
@@ -4,10 +4,12 @@ use serde::{Deserialize, Serialize};
 use crate::{
   atlas::{Atlasify, AtlasifyMut},
   boxify::*,
-  chunk::{Chunkify, ChunkifyMut},
+  chunk::{apron::Apron, Apronify, Chunkify, ChunkifyMut, Dirtify, DirtyRegion},
 };
 
 /// Provides a `Chunkify` implementation with index and value support `(u8, u8)`.
+///
+/// It only owns plain data, so it's `Send` and `Sync` and can be baked from several threads.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Chunk {
@@ -16,6 +18,9 @@ pub struct Chunk {
   height: u16,
   depth: u16,
   values: Vec<(u8, f32)>,
+  apron: Apron,
+  #[cfg_attr(feature = "serde", serde(skip))]
+  dirty: Option<DirtyRegion>,
 }
 
 impl Chunk {
@@ -23,13 +28,31 @@ impl Chunk {
     x + y * self.width as usize + z * self.width as usize * self.height as usize
   }
 
-  pub fn values(&self) -> &Vec<(u8, f32)> {
-    &self.values
+  fn get_value(&self, x: usize, y: usize, z: usize) -> (u8, f32) {
+    if x < self.width as usize && y < self.height as usize && z < self.depth as usize {
+      self.values[self.index(x, y, z)]
+    } else {
+      self.apron.get(self.size(), x, y, z).unwrap_or((0, -1.))
+    }
+  }
+
+  fn size(&self) -> [usize; 3] {
+    [
+      self.width as usize,
+      self.height as usize,
+      self.depth as usize,
+    ]
   }
 
-  // TODO: This will add  the neighbor data at the border of the chunk, so we can calculate correctly  the normals, heights, etc without need to worry to query each time to get that data
-  pub fn update_neighbor_data(&self, _neighbor: &Chunk) {
-    unimplemented!();
+  fn mark_dirty(&mut self, sample: [usize; 3]) {
+    match &mut self.dirty {
+      Some(region) => region.include(sample),
+      None => self.dirty = Some(DirtyRegion::new(sample)),
+    }
+  }
+
+  pub fn values(&self) -> &Vec<(u8, f32)> {
+    &self.values
   }
 }
 
@@ -41,6 +64,8 @@ impl Boxify for Chunk {
       height,
       depth,
       values: vec![(0, -1.); depth as usize * height as usize * width as usize],
+      apron: Apron::default(),
+      dirty: None,
     }
   }
 }
@@ -48,34 +73,83 @@ impl Boxify for Chunk {
 impl Chunkify<f32> for Chunk {
   fn is_air(&self, x: usize, y: usize, z: usize, isovalue: f32) -> bool {
     if x >= self.width as usize || y >= self.height as usize || z >= self.depth as usize {
-      true
+      // Past the border only the neighbor data can be solid
+      self
+        .apron
+        .get(self.size(), x, y, z)
+        .map_or(true, |(_, value)| value - isovalue < 1e-4)
     } else {
       self.get(x, y, z) - isovalue < 1e-4
     }
   }
 
   fn get(&self, x: usize, y: usize, z: usize) -> f32 {
-    self.values[self.index(x, y, z)].1
+    self.get_value(x, y, z).1
+  }
+
+  fn has_neighbor_data(&self) -> bool {
+    !self.apron.is_empty()
+  }
+
+  fn has_neighbor(&self, offset: [i32; 3]) -> bool {
+    self.apron.has_neighbor(offset)
   }
 }
 
 impl ChunkifyMut<f32> for Chunk {
   fn set(&mut self, x: usize, y: usize, z: usize, value: f32) {
     let index = self.index(x, y, z);
-    self.values[index] = (self.values[index].0, value);
+    if self.values[index].1 != value {
+      self.values[index].1 = value;
+      self.mark_dirty([x, y, z]);
+    }
   }
 }
 
 impl Atlasify<u8> for Chunk {
   fn get_atlas(&self, x: usize, y: usize, z: usize) -> u8 {
-    self.values[self.index(x, y, z)].0
+    self.get_value(x, y, z).0
   }
 }
 
 impl AtlasifyMut<u8> for Chunk {
   fn set_atlas(&mut self, x: usize, y: usize, z: usize, value: u8) {
     let index = self.index(x, y, z);
-    self.values[index] = (value, self.values[index].1);
+    if self.values[index].0 != value {
+      self.values[index].0 = value;
+      self.mark_dirty([x, y, z]);
+    }
+  }
+}
+
+impl Apronify for Chunk {
+  fn update_apron<C>(&mut self, offset: [i32; 3], neighbor: &C) -> bool
+  where
+    C: Chunkify<f32> + Atlasify<u8> + Sizable,
+  {
+    let mut apron = std::mem::take(&mut self.apron);
+    let updated = apron.update(self.size(), offset, neighbor, |sample| {
+      self.mark_dirty(sample)
+    });
+    self.apron = apron;
+    updated
+  }
+
+  fn remove_apron(&mut self, offset: [i32; 3]) -> bool {
+    let mut apron = std::mem::take(&mut self.apron);
+    let removed = apron.remove(self.size(), offset, |sample| self.mark_dirty(sample));
+    self.apron = apron;
+    removed
+  }
+}
+
+impl Dirtify for Chunk {
+  fn dirty_region(&self) -> Option<DirtyRegion> {
+    self.dirty
+  }
+
+  fn clear_dirty(&mut self) {
+    self.dirty = None;
   }
 }
 
@@ -111,6 +185,14 @@ impl Sizable for Chunk {
 mod test {
   use super::*;
 
+  #[test]
+  fn check_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Chunk>();
+    assert_send_sync::<crate::chunk::SparseChunk>();
+    assert_send_sync::<crate::chunk::PaletteChunk>();
+  }
+
   #[test]
   fn check_index() {
     let chunk = Chunk::new([0.0, 0.0, 0.0], 4, 4, 4);
@@ -121,4 +203,106 @@ mod test {
     let index = chunk.index(1, 2, 3);
     assert_eq!(index, 69);
   }
+
+  #[test]
+  #[allow(clippy::float_cmp)]
+  fn check_neighbor_data() {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 4, 4, 4);
+    let mut right = Chunk::new([4.0, 0.0, 0.0], 4, 4, 4);
+    let mut corner = Chunk::new([4.0, 4.0, 4.0], 4, 4, 4);
+    let mut left = Chunk::new([-4.0, 0.0, 0.0], 4, 4, 4);
+    let mut below = Chunk::new([-4.0, -2.0, 0.0], 4, 2, 4);
+    let far = Chunk::new([8.0, 0.0, 0.0], 4, 4, 4);
+    let inside = Chunk::new([0.0, 0.0, 0.0], 4, 4, 4);
+
+    right.set(0, 2, 3, 1.);
+    right.set_atlas(0, 2, 3, 5);
+    corner.set(0, 0, 0, 0.5);
+    left.set(3, 1, 2, 2.);
+    left.set(2, 1, 2, 3.);
+    below.set(3, 1, 0, 4.);
+
+    assert!(!chunk.has_neighbor_data());
+    // Without neighbor data the samples out of bounds are air, whatever the isovalue
+    assert!(chunk.is_air(4, 0, 0, -2.));
+    assert!(!chunk.update_neighbor_data(&far));
+    assert!(!chunk.update_neighbor_data(&inside));
+    assert!(!chunk.has_neighbor_data());
+
+    assert!(chunk.update_neighbor_data(&right));
+    assert!(chunk.update_neighbor_data(&corner));
+    assert!(chunk.has_neighbor_data());
+    assert!(chunk.has_neighbor([1, 0, 0]));
+    assert!(chunk.has_neighbor([1, 1, 1]));
+    assert!(!chunk.has_neighbor([0, 1, 0]));
+    assert!(!chunk.has_neighbor([-1, 0, 0]));
+
+    assert_eq!(chunk.get(4, 2, 3), 1.);
+    assert_eq!(chunk.get_atlas(4, 2, 3), 5);
+    assert_eq!(chunk.get(4, 4, 4), 0.5);
+    assert_eq!(chunk.get(4, 0, 0), -1.);
+    assert!(chunk.is_air(2, 4, 2, 0.));
+    assert!(chunk.is_air(5, 0, 0, 0.));
+
+    // Only the samples that changed
+    assert_eq!(
+      chunk.dirty_region(),
+      Some(DirtyRegion {
+        min: [4, 2, 3],
+        max: [4, 4, 4],
+      })
+    );
+
+    // The negative neighbors, only the samples touching the chunk are copied
+    chunk.clear_dirty();
+    assert!(chunk.update_neighbor_data(&left));
+    assert!(chunk.update_apron([-1, -1, 0], &below));
+    let before = usize::MAX;
+    assert_eq!(chunk.get(before, 1, 2), 2.);
+    assert_eq!(chunk.get(before, before, 0), 4.);
+    assert!(chunk.is_air(before, 1, 1, 0.));
+    assert!(chunk.is_air(before, before, before, 0.));
+
+    let mut sparse = crate::chunk::SparseChunk::with_size(4, 4, 4);
+    assert!(sparse.update_apron([-1, 0, 0], &left));
+    assert!(sparse.has_neighbor_data());
+    assert_eq!(sparse.get(before, 1, 2), 2.);
+    assert_eq!(
+      chunk.dirty_region(),
+      Some(DirtyRegion {
+        min: [0, 0, 0],
+        max: [0, 1, 2],
+      })
+    );
+  }
+
+  #[test]
+  fn check_dirty_region() {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 4, 4, 4);
+    assert_eq!(chunk.dirty_region(), None);
+
+    // Writing the same value doesn't change anything
+    chunk.set(1, 2, 3, -1.);
+    chunk.set_atlas(1, 2, 3, 0);
+    assert_eq!(chunk.dirty_region(), None);
+
+    chunk.set(1, 2, 3, 1.);
+    chunk.set_atlas(3, 0, 2, 4);
+    assert_eq!(
+      chunk.dirty_region(),
+      Some(DirtyRegion {
+        min: [1, 0, 2],
+        max: [3, 2, 3],
+      })
+    );
+
+    chunk.clear_dirty();
+    assert_eq!(chunk.dirty_region(), None);
+
+    let mut chunk = crate::chunk::SparseChunk::with_size(4, 4, 4);
+    chunk.set(2, 2, 2, -1.);
+    assert_eq!(chunk.dirty_region(), None);
+    chunk.set_atlas(2, 2, 2, 1);
+    assert_eq!(chunk.dirty_region(), Some(DirtyRegion::new([2, 2, 2])));
+  }
 }
@@ -0,0 +1,444 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+  atlas::{Atlasify, AtlasifyMut},
+  boxify::*,
+  chunk::{apron::Apron, Apronify, Chunkify, ChunkifyMut, Dirtify, DirtyRegion},
+};
+
+/// Provides a `Chunkify` implementation that stores each distinct `(atlas, value)` pair once in a
+/// palette, and the index of the pair of each sample packed in as few bits as the palette needs.
+///
+/// The chunks with a single pair, like the empty ones, only store the palette. It's meant for the
+/// chunks with few distinct samples like the voxel models or the flat terrain, the densities with a
+/// different value on each sample are better stored in a `Chunk`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PaletteChunk {
+  position: [f32; 3],
+  width: u16,
+  height: u16,
+  depth: u16,
+  palette: Vec<(u8, f32)>,
+  // Index of each used pair of the palette by the bits of the pair, so the `NaN`s are found too.
+  // With `free` it's rebuilt from the palette when they don't match, like after deserializing the
+  // chunk
+  #[cfg_attr(feature = "serde", serde(skip))]
+  lookup: HashMap<(u8, u32), usize>,
+  // Samples using each pair of the palette, the unused pairs are replaced by the next new ones
+  counts: Vec<usize>,
+  // Indices of the unused pairs of the palette
+  #[cfg_attr(feature = "serde", serde(skip))]
+  free: Vec<usize>,
+  // Bits of each index, a power of two so they don't cross the words, `0` while all the samples
+  // are the same
+  bits: usize,
+  indices: Vec<u64>,
+  apron: Apron,
+  #[cfg_attr(feature = "serde", serde(skip))]
+  dirty: Option<DirtyRegion>,
+}
+
+impl PaletteChunk {
+  /// Copies the samples of another chunk.
+  pub fn from_chunk<C>(chunk: &C) -> Self
+  where
+    C: Chunkify<f32> + Atlasify<u8> + Boxify,
+  {
+    let mut palette_chunk = Self::new(
+      chunk.position(),
+      chunk.width(),
+      chunk.height(),
+      chunk.depth(),
+    );
+
+    for x in 0..chunk.width() as usize {
+      for y in 0..chunk.height() as usize {
+        for z in 0..chunk.depth() as usize {
+          palette_chunk.set_value(x, y, z, (chunk.get_atlas(x, y, z), chunk.get(x, y, z)));
+        }
+      }
+    }
+
+    palette_chunk.dirty = None;
+    palette_chunk
+  }
+
+  /// The distinct `(atlas, value)` pairs of the samples, it can also contain unused pairs left by
+  /// the last changes.
+  pub fn palette(&self) -> &Vec<(u8, f32)> {
+    &self.palette
+  }
+
+  fn size(&self) -> [usize; 3] {
+    [
+      self.width as usize,
+      self.height as usize,
+      self.depth as usize,
+    ]
+  }
+
+  fn mark_dirty(&mut self, sample: [usize; 3]) {
+    match &mut self.dirty {
+      Some(region) => region.include(sample),
+      None => self.dirty = Some(DirtyRegion::new(sample)),
+    }
+  }
+
+  fn len(&self) -> usize {
+    self.width as usize * self.height as usize * self.depth as usize
+  }
+
+  fn index(&self, x: usize, y: usize, z: usize) -> usize {
+    x + y * self.width as usize + z * self.width as usize * self.height as usize
+  }
+
+  fn palette_index(&self, index: usize) -> usize {
+    if self.bits == 0 {
+      return 0;
+    }
+
+    let bit = index * self.bits;
+    let mask = (1 << self.bits) - 1;
+    ((self.indices[bit / 64] >> (bit % 64)) & mask) as usize
+  }
+
+  fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+    let bit = index * self.bits;
+    let mask = (1 << self.bits) - 1;
+    let word = &mut self.indices[bit / 64];
+    *word = (*word & !(mask << (bit % 64))) | ((palette_index as u64) << (bit % 64));
+  }
+
+  // Packs the indices again with more bits per index
+  fn repack(&mut self, bits: usize) {
+    let indices: Vec<usize> = (0..self.len()).map(|i| self.palette_index(i)).collect();
+
+    self.bits = bits;
+    self.indices = vec![0; (indices.len() * bits).div_ceil(64)];
+    for (index, palette_index) in indices.into_iter().enumerate() {
+      self.set_palette_index(index, palette_index);
+    }
+  }
+
+  fn get_value(&self, x: usize, y: usize, z: usize) -> (u8, f32) {
+    if x < self.width as usize && y < self.height as usize && z < self.depth as usize {
+      self.palette[self.palette_index(self.index(x, y, z))]
+    } else {
+      self.apron.get(self.size(), x, y, z).unwrap_or((0, -1.))
+    }
+  }
+
+  fn set_value(&mut self, x: usize, y: usize, z: usize, value: (u8, f32)) {
+    let key = |pair: (u8, f32)| (pair.0, pair.1.to_bits());
+
+    let index = self.index(x, y, z);
+    let previous = self.palette_index(index);
+    if key(self.palette[previous]) == key(value) {
+      return;
+    }
+
+    if self.lookup.len() + self.free.len() != self.palette.len() {
+      self.lookup.clear();
+      self.free.clear();
+      for (palette_index, (&pair, &count)) in
+        self.palette.iter().zip(self.counts.iter()).enumerate()
+      {
+        if count > 0 {
+          self.lookup.insert(key(pair), palette_index);
+        } else {
+          self.free.push(palette_index);
+        }
+      }
+    }
+
+    self.counts[previous] -= 1;
+    if self.counts[previous] == 0 {
+      self.lookup.remove(&key(self.palette[previous]));
+      self.free.push(previous);
+    }
+
+    let palette_index = match self.lookup.get(&key(value)) {
+      Some(&palette_index) => palette_index,
+      None => {
+        let palette_index = match self.free.pop() {
+          Some(unused) => {
+            self.palette[unused] = value;
+            unused
+          }
+          None => {
+            self.palette.push(value);
+            self.counts.push(0);
+            self.palette.len() - 1
+          }
+        };
+        self.lookup.insert(key(value), palette_index);
+        palette_index
+      }
+    };
+    self.counts[palette_index] += 1;
+
+    if self.counts[palette_index] == self.len() {
+      // Back to a single pair
+      self.palette = vec![value];
+      self.lookup = HashMap::new();
+      self.lookup.insert(key(value), 0);
+      self.counts = vec![self.len()];
+      self.free = vec![];
+      self.bits = 0;
+      self.indices = vec![];
+    } else {
+      if self.palette.len() > 1 << self.bits {
+        self.repack((self.bits * 2).max(1));
+      }
+      self.set_palette_index(index, palette_index);
+    }
+
+    self.mark_dirty([x, y, z]);
+  }
+}
+
+impl Boxify for PaletteChunk {
+  fn new(position: [f32; 3], width: u16, height: u16, depth: u16) -> Self {
+    Self {
+      position,
+      width,
+      height,
+      depth,
+      palette: vec![(0, -1.)],
+      lookup: HashMap::new(),
+      counts: vec![width as usize * height as usize * depth as usize],
+      free: vec![],
+      bits: 0,
+      indices: vec![],
+      apron: Apron::default(),
+      dirty: None,
+    }
+  }
+}
+
+impl Chunkify<f32> for PaletteChunk {
+  fn is_air(&self, x: usize, y: usize, z: usize, isovalue: f32) -> bool {
+    if x >= self.width as usize || y >= self.height as usize || z >= self.depth as usize {
+      // Past the border only the neighbor data can be solid
+      self
+        .apron
+        .get(self.size(), x, y, z)
+        .map_or(true, |(_, value)| value - isovalue < 1e-4)
+    } else {
+      self.get(x, y, z) - isovalue < 1e-4
+    }
+  }
+
+  fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+    self.get_value(x, y, z).1
+  }
+
+  fn has_neighbor_data(&self) -> bool {
+    !self.apron.is_empty()
+  }
+
+  fn has_neighbor(&self, offset: [i32; 3]) -> bool {
+    self.apron.has_neighbor(offset)
+  }
+}
+
+impl ChunkifyMut<f32> for PaletteChunk {
+  fn set(&mut self, x: usize, y: usize, z: usize, value: f32) {
+    let atlas = self.get_atlas(x, y, z);
+    self.set_value(x, y, z, (atlas, value));
+  }
+}
+
+impl Atlasify<u8> for PaletteChunk {
+  fn get_atlas(&self, x: usize, y: usize, z: usize) -> u8 {
+    self.get_value(x, y, z).0
+  }
+}
+
+impl AtlasifyMut<u8> for PaletteChunk {
+  fn set_atlas(&mut self, x: usize, y: usize, z: usize, value: u8) {
+    let density = self.get(x, y, z);
+    self.set_value(x, y, z, (value, density));
+  }
+}
+
+impl Apronify for PaletteChunk {
+  fn update_apron<C>(&mut self, offset: [i32; 3], neighbor: &C) -> bool
+  where
+    C: Chunkify<f32> + Atlasify<u8> + Sizable,
+  {
+    let mut apron = std::mem::take(&mut self.apron);
+    let updated = apron.update(self.size(), offset, neighbor, |sample| {
+      self.mark_dirty(sample)
+    });
+    self.apron = apron;
+    updated
+  }
+
+  fn remove_apron(&mut self, offset: [i32; 3]) -> bool {
+    let mut apron = std::mem::take(&mut self.apron);
+    let removed = apron.remove(self.size(), offset, |sample| self.mark_dirty(sample));
+    self.apron = apron;
+    removed
+  }
+}
+
+impl Dirtify for PaletteChunk {
+  fn dirty_region(&self) -> Option<DirtyRegion> {
+    self.dirty
+  }
+
+  fn clear_dirty(&mut self) {
+    self.dirty = None;
+  }
+}
+
+impl Positionable for PaletteChunk {
+  fn with_position(position: [f32; 3]) -> Self {
+    Self::new(position, 16, 16, 16)
+  }
+
+  fn position(&self) -> [f32; 3] {
+    self.position
+  }
+}
+
+impl Sizable for PaletteChunk {
+  fn with_size(width: u16, height: u16, depth: u16) -> Self {
+    Self::new([0.0, 0.0, 0.0], width, height, depth)
+  }
+
+  fn depth(&self) -> u16 {
+    self.depth
+  }
+
+  fn height(&self) -> u16 {
+    self.height
+  }
+
+  fn width(&self) -> u16 {
+    self.width
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::chunk::Chunk;
+
+  #[test]
+  #[allow(clippy::float_cmp)]
+  fn check_palette() {
+    let mut chunk = PaletteChunk::new([0.0, 0.0, 0.0], 16, 16, 16);
+    assert!(chunk.is_air(3, 4, 5, 0.));
+    assert_eq!(chunk.bits, 0);
+    assert!(chunk.indices.is_empty());
+
+    // A solid floor of two materials
+    for x in 0..16 {
+      for z in 0..16 {
+        chunk.set(x, 0, z, 1.);
+        chunk.set_atlas(x, 0, z, (x % 2) as u8 + 1);
+      }
+    }
+    assert_eq!(chunk.bits, 2);
+    assert_eq!(chunk.indices.len(), 16 * 16 * 16 * 2 / 64);
+    assert_eq!(chunk.get(4, 0, 7), 1.);
+    assert_eq!(chunk.get_atlas(4, 0, 7), 1);
+    assert_eq!(chunk.get_atlas(5, 0, 7), 2);
+    assert!(chunk.is_air(4, 1, 7, 0.));
+    assert_eq!(
+      chunk.dirty_region(),
+      Some(DirtyRegion {
+        min: [0, 0, 0],
+        max: [15, 0, 15],
+      })
+    );
+
+    // Filling it goes back to a single pair
+    for x in 0..16 {
+      for y in 0..16 {
+        for z in 0..16 {
+          chunk.set(x, y, z, 0.5);
+          chunk.set_atlas(x, y, z, 3);
+        }
+      }
+    }
+    assert_eq!(chunk.palette(), &vec![(3, 0.5)]);
+    assert_eq!(chunk.bits, 0);
+    assert!(chunk.indices.is_empty());
+
+    // The neighbor data is read past the border
+    let mut right = Chunk::new([16.0, 0.0, 0.0], 16, 16, 16);
+    right.set(0, 3, 4, 2.);
+    assert!(chunk.update_neighbor_data(&right));
+    assert!(chunk.has_neighbor_data());
+    assert_eq!(chunk.get(16, 3, 4), 2.);
+    assert!(chunk.is_air(16, 3, 5, 0.));
+  }
+
+  #[test]
+  #[allow(clippy::float_cmp)]
+  fn check_same_as_chunk() {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 8, 6, 4);
+    let mut palette_chunk = PaletteChunk::new([0.0, 0.0, 0.0], 8, 6, 4);
+
+    // Enough distinct values to grow the indices a few times, some of them overwritten
+    let mut seed = 7_u32;
+    for _ in 0..2000 {
+      seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+      let (x, y, z) = (
+        (seed >> 8) as usize % 8,
+        (seed >> 12) as usize % 6,
+        (seed >> 16) as usize % 4,
+      );
+      let value = (seed >> 20) as f32 % 40.0 - 20.0;
+      let atlas = (seed >> 28) as u8;
+
+      chunk.set(x, y, z, value);
+      palette_chunk.set(x, y, z, value);
+      if atlas < 4 {
+        chunk.set_atlas(x, y, z, atlas);
+        palette_chunk.set_atlas(x, y, z, atlas);
+      }
+    }
+
+    assert!(palette_chunk.bits >= 4);
+    // The unused pairs are replaced instead of growing the palette
+    assert!(palette_chunk.palette().len() <= 8 * 6 * 4);
+    // Every pair is either used and found by the lookup, or free
+    assert_eq!(
+      palette_chunk.lookup.len() + palette_chunk.free.len(),
+      palette_chunk.palette().len()
+    );
+    for (palette_index, &(atlas, value)) in palette_chunk.palette().iter().enumerate() {
+      if palette_chunk.counts[palette_index] > 0 {
+        assert_eq!(
+          palette_chunk.lookup[&(atlas, value.to_bits())],
+          palette_index
+        );
+      } else {
+        assert!(palette_chunk.free.contains(&palette_index));
+      }
+    }
+    for x in 0..8 {
+      for y in 0..6 {
+        for z in 0..4 {
+          assert_eq!(palette_chunk.get(x, y, z), chunk.get(x, y, z));
+          assert_eq!(palette_chunk.get_atlas(x, y, z), chunk.get_atlas(x, y, z));
+        }
+      }
+    }
+
+    let copy = PaletteChunk::from_chunk(&chunk);
+    assert_eq!(copy.dirty_region(), None);
+    for (index, &(atlas, value)) in chunk.values().iter().enumerate() {
+      let (x, y, z) = (index % 8, index / 8 % 6, index / 48);
+      assert_eq!(copy.get(x, y, z), value);
+      assert_eq!(copy.get_atlas(x, y, z), atlas);
+    }
+  }
+}
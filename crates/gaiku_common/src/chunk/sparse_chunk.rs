@@ -6,10 +6,12 @@ use std::collections::HashMap;
 use crate::{
   atlas::{Atlasify, AtlasifyMut},
   boxify::*,
-  chunk::{Chunkify, ChunkifyMut},
+  chunk::{apron::Apron, Apronify, Chunkify, ChunkifyMut, Dirtify, DirtyRegion},
 };
 
 /// Provides a `Chunkify` implementation with a hashmap and `u8` position based on x, y and z axis with `u8` value.
+///
+/// Reading it never mutates the hashmap, so it's `Sync` too.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SparseChunk {
@@ -17,6 +19,42 @@ pub struct SparseChunk {
   height: u16,
   depth: u16,
   data: HashMap<(usize, usize, usize), (u8, f32)>,
+  apron: Apron,
+  #[cfg_attr(feature = "serde", serde(skip))]
+  dirty: Option<DirtyRegion>,
+}
+
+impl SparseChunk {
+  fn size(&self) -> [usize; 3] {
+    [
+      self.width as usize,
+      self.height as usize,
+      self.depth as usize,
+    ]
+  }
+
+  fn get_value(&self, x: usize, y: usize, z: usize) -> Option<(u8, f32)> {
+    let [width, height, depth] = self.size();
+    if x < width && y < height && z < depth {
+      self.data.get(&(x, y, z)).copied()
+    } else {
+      self.apron.get(self.size(), x, y, z)
+    }
+  }
+
+  fn mark_dirty(&mut self, sample: [usize; 3]) {
+    match &mut self.dirty {
+      Some(region) => region.include(sample),
+      None => self.dirty = Some(DirtyRegion::new(sample)),
+    }
+  }
+
+  fn update(&mut self, x: usize, y: usize, z: usize, value: (u8, f32)) {
+    let previous = self.data.insert((x, y, z), value);
+    if previous.unwrap_or((0, -1.)) != value {
+      self.mark_dirty([x, y, z]);
+    }
+  }
 }
 
 impl Chunkify<f32> for SparseChunk {
@@ -25,13 +63,21 @@ impl Chunkify<f32> for SparseChunk {
   }
 
   fn get(&self, x: usize, y: usize, z: usize) -> f32 {
-    self.data.get(&(x, y, z)).map(|d| d.1).unwrap_or(-1.)
+    self.get_value(x, y, z).map(|d| d.1).unwrap_or(-1.)
+  }
+
+  fn has_neighbor_data(&self) -> bool {
+    !self.apron.is_empty()
+  }
+
+  fn has_neighbor(&self, offset: [i32; 3]) -> bool {
+    self.apron.has_neighbor(offset)
   }
 }
 
 impl Atlasify<u8> for SparseChunk {
   fn get_atlas(&self, x: usize, y: usize, z: usize) -> u8 {
-    self.data.get(&(x, y, z)).map(|d| d.0).unwrap_or(0)
+    self.get_value(x, y, z).map(|d| d.0).unwrap_or(0)
   }
 }
 
@@ -42,6 +88,8 @@ impl Sizable for SparseChunk {
       height,
       depth,
       data: HashMap::new(),
+      apron: Apron::default(),
+      dirty: None,
     }
   }
 
@@ -61,13 +109,44 @@ impl Sizable for SparseChunk {
 impl ChunkifyMut<f32> for SparseChunk {
   fn set(&mut self, x: usize, y: usize, z: usize, value: f32) {
     let atlas = self.get_atlas(x, y, z);
-    self.data.insert((x, y, z), (atlas, value));
+    self.update(x, y, z, (atlas, value));
   }
 }
 
 impl AtlasifyMut<u8> for SparseChunk {
   fn set_atlas(&mut self, x: usize, y: usize, z: usize, atlas: u8) {
     let value = self.get(x, y, z);
-    self.data.insert((x, y, z), (atlas, value));
+    self.update(x, y, z, (atlas, value));
+  }
+}
+
+impl Apronify for SparseChunk {
+  fn update_apron<C>(&mut self, offset: [i32; 3], neighbor: &C) -> bool
+  where
+    C: Chunkify<f32> + Atlasify<u8> + Sizable,
+  {
+    let mut apron = std::mem::take(&mut self.apron);
+    let updated = apron.update(self.size(), offset, neighbor, |sample| {
+      self.mark_dirty(sample)
+    });
+    self.apron = apron;
+    updated
+  }
+
+  fn remove_apron(&mut self, offset: [i32; 3]) -> bool {
+    let mut apron = std::mem::take(&mut self.apron);
+    let removed = apron.remove(self.size(), offset, |sample| self.mark_dirty(sample));
+    self.apron = apron;
+    removed
+  }
+}
+
+impl Dirtify for SparseChunk {
+  fn dirty_region(&self) -> Option<DirtyRegion> {
+    self.dirty
+  }
+
+  fn clear_dirty(&mut self) {
+    self.dirty = None;
   }
 }
@@ -0,0 +1,364 @@
+use glam::Vec3;
+
+use crate::{
+  atlas::Atlasify, boxify::*, chunk::Chunkify, mesh::Meshify, texture::Texturify2d, BakerOptions,
+};
+
+const EPSILON: f32 = 1e-4;
+
+/// Corners of a cell, same order used by the other bakers.
+pub const CORNERS: [[usize; 3]; 8] = [
+  [0, 0, 0],
+  [1, 0, 0],
+  [1, 1, 0],
+  [0, 1, 0],
+  [0, 0, 1],
+  [1, 0, 1],
+  [1, 1, 1],
+  [0, 1, 1],
+];
+
+/// Edges of a cell as pairs of corners.
+pub const EDGES: [[usize; 2]; 12] = [
+  [0, 1],
+  [1, 2],
+  [2, 3],
+  [3, 0],
+  [4, 5],
+  [5, 6],
+  [6, 7],
+  [7, 4],
+  [0, 4],
+  [1, 5],
+  [2, 6],
+  [3, 7],
+];
+
+/// Cell between 8 samples that crosses the surface.
+pub struct Cell {
+  /// Coordinate of the first corner, `-1` is the cell before the chunk.
+  pub origin: [isize; 3],
+  values: [f32; 8],
+  air: [bool; 8],
+}
+
+/// Intersection of the surface with an edge of a `Cell`.
+pub struct Crossing {
+  /// Samples at both ends of the edge, the ones before the chunk are `usize::MAX`.
+  pub samples: [[usize; 3]; 2],
+  /// Intersection point relative to the cell origin.
+  pub point: Vec3,
+  /// Position of the intersection between the two samples, from `0` to `1`.
+  pub weight: f32,
+}
+
+impl Cell {
+  /// Gradient of the trilinear interpolation of the corners at the `point` relative to the cell
+  /// origin. It only reads the corners, so the cells shared by two chunks get the same gradient.
+  pub fn gradient(&self, point: Vec3) -> Vec3 {
+    let mut gradient = Vec3::zero();
+    for (corner, value) in CORNERS.iter().zip(self.values.iter()) {
+      // Weight of the corner on each axis and its derivative
+      let mut weights = [0.0; 3];
+      let mut derivatives = [0.0; 3];
+      for axis in 0..3 {
+        if corner[axis] == 1 {
+          weights[axis] = point[axis];
+          derivatives[axis] = 1.0;
+        } else {
+          weights[axis] = 1.0 - point[axis];
+          derivatives[axis] = -1.0;
+        }
+      }
+
+      gradient += Vec3::new(
+        derivatives[0] * weights[1] * weights[2],
+        weights[0] * derivatives[1] * weights[2],
+        weights[0] * weights[1] * derivatives[2],
+      ) * *value;
+    }
+    gradient
+  }
+
+  /// The edges of the cell that cross the surface.
+  pub fn crossings(&self, isovalue: f32) -> impl Iterator<Item = Crossing> + '_ {
+    EDGES
+      .iter()
+      .filter(move |[a, b]| self.air[*a] != self.air[*b])
+      .map(move |&[a, b]| {
+        let delta = self.values[b] - self.values[a];
+        let weight = if delta.abs() > EPSILON {
+          ((isovalue - self.values[a]) / delta).clamp(0.0, 1.0)
+        } else {
+          0.5
+        };
+
+        let point = |corner: usize| {
+          Vec3::new(
+            CORNERS[corner][0] as f32,
+            CORNERS[corner][1] as f32,
+            CORNERS[corner][2] as f32,
+          )
+        };
+        let sample = |corner: usize| {
+          let mut sample = [0; 3];
+          for (axis, sample) in sample.iter_mut().enumerate() {
+            *sample = (self.origin[axis] + CORNERS[corner][axis] as isize) as usize;
+          }
+          sample
+        };
+
+        Crossing {
+          samples: [sample(a), sample(b)],
+          point: point(a) + (point(b) - point(a)) * weight,
+          weight,
+        }
+      })
+  }
+}
+
+/// Grid of the cells between the samples of a chunk, the dual of the sample grid, with one vertex
+/// per cell that crosses the surface. The vertices are connected with one quad per edge of the
+/// sample grid that crosses the surface, and shared by the quads of their cell.
+///
+/// With neighbor data it also covers the cells between the chunk and its neighbors, and only the
+/// edges that start on the samples of the chunk make quads, so each edge belongs to a single
+/// chunk and the chunks join without gaps.
+pub struct DualGrid {
+  // First and last cell on each axis, the last one is excluded
+  start: isize,
+  limits: [isize; 3],
+  // Position and normal of the vertex of each cell
+  vertices: Vec<Option<(Vec3, Vec3)>>,
+}
+
+impl DualGrid {
+  pub fn new<C>(chunk: &C) -> Self
+  where
+    C: Chunkify<f32> + Sizable,
+  {
+    let (start, end) = if chunk.has_neighbor_data() {
+      (-1, 0)
+    } else {
+      (0, -1)
+    };
+    let limits = [
+      chunk.width() as isize + end,
+      chunk.height() as isize + end,
+      chunk.depth() as isize + end,
+    ];
+    let count: isize = limits.iter().map(|limit| limit - start).product();
+
+    Self {
+      start,
+      limits,
+      vertices: vec![None; count as usize],
+    }
+  }
+
+  /// The cells of the grid that cross the surface.
+  pub fn cells<'a, C>(&self, chunk: &'a C, isovalue: f32) -> impl Iterator<Item = Cell> + 'a
+  where
+    C: Chunkify<f32>,
+  {
+    let (start, limits) = (self.start, self.limits);
+
+    (start..limits[0])
+      .flat_map(move |x| (start..limits[1]).map(move |y| (x, y)))
+      .flat_map(move |(x, y)| (start..limits[2]).map(move |z| [x, y, z]))
+      .filter_map(move |origin| {
+        let mut values = [0.0; 8];
+        let mut air = [false; 8];
+        for (i, corner) in CORNERS.iter().enumerate() {
+          // The samples before the chunk are read with `usize::MAX`
+          let [x, y, z] = [
+            (origin[0] + corner[0] as isize) as usize,
+            (origin[1] + corner[1] as isize) as usize,
+            (origin[2] + corner[2] as isize) as usize,
+          ];
+          values[i] = chunk.get(x, y, z);
+          air[i] = chunk.is_air(x, y, z, isovalue);
+        }
+
+        let solid = air.iter().filter(|&&air| !air).count();
+        if solid > 0 && solid < air.len() {
+          Some(Cell {
+            origin,
+            values,
+            air,
+          })
+        } else {
+          None
+        }
+      })
+  }
+
+  /// Places the vertex of the `cell`, in chunk coordinates. The `gradient` of the values at the
+  /// vertex, like `Cell::gradient`, gives its normal, pointing to the air.
+  pub fn set_vertex(&mut self, cell: [isize; 3], vertex: Vec3, gradient: Vec3) {
+    let index = self.index(cell);
+    self.vertices[index] = Some((vertex, -gradient));
+  }
+
+  /// Connects the vertices with quads, each vertex is shared by all the quads around it and has
+  /// the smooth normal given in `set_vertex`, or the average of the quads when it's zero. The
+  /// vertices take the atlas of the most solid corner of their cell, with a `texture` the uvs are
+  /// the position on the plane the normal faces most, in voxel units, and the mesh tiles hold the
+  /// atlas tile to repeat like in the greedy voxel meshes.
+  pub fn build<C, T, M>(&self, chunk: &C, options: &BakerOptions<T>) -> Option<M>
+  where
+    C: Chunkify<f32> + Atlasify<u8> + Sizable,
+    T: Texturify2d,
+    M: Meshify,
+  {
+    let is_air =
+      |[x, y, z]: [isize; 3]| chunk.is_air(x as usize, y as usize, z as usize, options.isovalue);
+
+    // Index of the cell vertices in the mesh, only the ones used by a quad are added
+    let mut mesh_indices = vec![None; self.vertices.len()];
+    let mut cells = vec![];
+    let mut indices = vec![];
+    let mut quad_normals = vec![];
+
+    // The edge starts at `sample` and goes along `axis`, the quad connects the 4 cells around it
+    for axis in 0..3 {
+      let u_axis = (axis + 1) % 3;
+      let v_axis = (axis + 2) % 3;
+
+      for x in 0..self.limits[0] {
+        for y in 0..self.limits[1] {
+          for z in 0..self.limits[2] {
+            let sample = [x, y, z];
+            if sample[u_axis] <= self.start || sample[v_axis] <= self.start {
+              continue;
+            }
+
+            let mut next = sample;
+            next[axis] += 1;
+
+            let sample_air = is_air(sample);
+            if sample_air == is_air(next) {
+              continue;
+            }
+
+            let cell = |du: isize, dv: isize| {
+              let mut cell = sample;
+              cell[u_axis] -= du;
+              cell[v_axis] -= dv;
+              cell
+            };
+
+            let mut quad = [cell(1, 1), cell(0, 1), cell(0, 0), cell(1, 0)];
+            if quad
+              .iter()
+              .any(|&cell| self.vertices[self.index(cell)].is_none())
+            {
+              continue;
+            }
+
+            // The quad is counter-clockwise around `axis`, flip it when the surface faces the
+            // opposite direction
+            if sample_air {
+              quad.reverse();
+            }
+
+            let quad = quad.map(|cell| {
+              let index = self.index(cell);
+              *mesh_indices[index].get_or_insert_with(|| {
+                cells.push(cell);
+                quad_normals.push(Vec3::zero());
+                cells.len() - 1
+              })
+            });
+            let position =
+              |corner: usize| self.vertices[self.index(cells[quad[corner]])].unwrap().0;
+            let normal = (position(2) - position(0)).cross(position(3) - position(1));
+            for &index in quad.iter() {
+              quad_normals[index] += normal;
+            }
+
+            for &corner in [0, 1, 3, 1, 2, 3].iter() {
+              indices.push(quad[corner] as u32);
+            }
+          }
+        }
+      }
+    }
+
+    if indices.is_empty() {
+      return None;
+    }
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut colors = vec![];
+    let mut tiles = vec![];
+    for (&cell, quad_normal) in cells.iter().zip(quad_normals.iter()) {
+      let (position, normal) = self.vertices[self.index(cell)].unwrap();
+      let normal = if normal.length() > EPSILON {
+        normal.normalize()
+      } else {
+        quad_normal.normalize()
+      };
+
+      // The most solid corner, the corners before the chunk are read with `usize::MAX`
+      let corner = CORNERS
+        .iter()
+        .map(|corner| {
+          [
+            (cell[0] + corner[0] as isize) as usize,
+            (cell[1] + corner[1] as isize) as usize,
+            (cell[2] + corner[2] as isize) as usize,
+          ]
+        })
+        .max_by(|a, b| {
+          chunk
+            .get(a[0], a[1], a[2])
+            .total_cmp(&chunk.get(b[0], b[1], b[2]))
+        })
+        .unwrap();
+      let atlas = chunk.get_atlas(corner[0], corner[1], corner[2]);
+
+      if let Some(texture) = &options.texture {
+        let axis = (0..3)
+          .max_by(|&a, &b| normal[a].abs().total_cmp(&normal[b].abs()))
+          .unwrap();
+        uvs.push([position[(axis + 1) % 3], position[(axis + 2) % 3]]);
+
+        // Get the atlas corners
+        // 3-2
+        // 0-1
+        let (origin, _, end, _) = texture.get_uv(atlas);
+        tiles.push([origin[0], origin[1], end[0] - origin[0], end[1] - origin[1]]);
+      }
+
+      positions.push(position.into());
+      normals.push(normal.into());
+      colors.extend(options.color(atlas));
+    }
+
+    Some(M::with(
+      indices,
+      positions,
+      normals,
+      uvs,
+      colors,
+      vec![],
+      tiles,
+    ))
+  }
+
+  fn index(&self, cell: [isize; 3]) -> usize {
+    let size = [
+      (self.limits[0] - self.start) as usize,
+      (self.limits[1] - self.start) as usize,
+    ];
+    let [x, y, z] = [
+      (cell[0] - self.start) as usize,
+      (cell[1] - self.start) as usize,
+      (cell[2] - self.start) as usize,
+    ];
+    x + y * size[0] + z * size[0] * size[1]
+  }
+}
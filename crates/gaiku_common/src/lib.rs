@@ -2,7 +2,7 @@
 //!
 //! The idea behind this crate is to offer a common interop interface to
 //! work with different file formats and mesh generators, based on voxels.
-use std::fs::read;
+use std::fs::{read, write};
 
 pub use anyhow::Result;
 pub use mint;
@@ -10,7 +10,7 @@ pub use mint;
 use crate::{
   atlas::{Atlasify, AtlasifyMut},
   boxify::*,
-  chunk::{Chunkify, ChunkifyMut},
+  chunk::{Chunkify, ChunkifyMut, DirtyRegion},
   mesh::Meshify,
   texture::{TextureAtlas2d, Texturify2d},
 };
@@ -23,23 +23,38 @@ mod boundary;
 pub mod boxify;
 /// Chunk implementation, also offers all traits used internally to build the chunk object.
 pub mod chunk;
+/// Dual grid of the cells between the samples, shared by the surface nets and dual contouring
+/// bakers.
+pub mod dual_grid;
 /// Mesh related traits/implementation, also offers some utils like MeshBuilder.
 pub mod mesh;
 /// Texture related traits/implementation.
 pub mod texture;
+/// Transition cells between chunks baked with different levels of detail, shared by the marching
+/// cubes bakers.
+pub mod transition;
 // For the mesh builders that help convert faces into a mesh
 pub mod meshbuilder;
+/// Color quantization, to fit the colors of the files in the atlas.
+pub mod quantization;
+/// Incremental baking of the changed regions of the chunks.
+pub mod rebake;
+/// Observer based chunk streaming for the `World`.
+pub mod streaming;
+/// World container that owns chunks keyed by their chunk coordinates.
+pub mod world;
 
 /// `use gaiku_common::prelude::*;` to import common traits and utils.
 pub mod prelude {
   pub use crate::{
     atlas::{Atlasify, AtlasifyMut},
     boxify::*,
-    chunk::{Chunkify, ChunkifyMut},
+    chunk::{Apronify, Chunkify, ChunkifyMut, Dirtify, DirtyRegion},
     mesh::Meshify,
     meshbuilder::*,
     texture::{TextureAtlas2d, Texturify2d},
-    Baker, BakerOptions, FileFormat,
+    Baker, BakerOptions, CoordinateSystem, FileFormat, FileFormatOptions, FileFormatWriter,
+    HeightMapOptions, PaletteOverflow,
   };
 }
 
@@ -50,12 +65,29 @@ where
 {
   /// The isovalue of the surface to render.
   pub isovalue: f32,
-  /// Unused
+  /// The chunk is baked using one of every `2^level_of_detail` samples on each axis, `0` is the
+  /// full resolution. Only used by the marching cubes bakers.
   pub level_of_detail: usize,
+  /// Sides of the chunk that border a chunk baked with one `level_of_detail` less, in the order
+  /// -x, +x, -y, +y, -z, +z. Transition cells are generated on them to close the seams, the
+  /// positive sides need the neighbor data. Only used by the marching cubes bakers.
+  pub lod_transitions: [bool; 6],
   /// Texture to use for uv mapping to the atlas
   pub texture: Option<TextureAtlas2d<T>>,
+  /// Colors indexed by the atlas index, stored as vertex colors so flat colored meshes don't need
+  /// a texture. `TextureAtlas2d::palette` returns them for the atlases of the format readers.
+  pub palette: Option<Vec<[u8; 4]>>,
   /// Removing duplicate verts can be expense. Enable this when required
   pub remove_duplicate_verts: bool,
+  /// Merge the coplanar faces that share the same atlas index into bigger quads, the UVs are
+  /// generated in voxel units so the texture repeats over the merged quad. With a `texture` the
+  /// mesh tiles hold the atlas tile to repeat. Only used by the voxel baker.
+  pub greedy_meshing: bool,
+  /// Darken the corners of the faces touching other voxels (classic per-vertex ambient occlusion)
+  /// and store it in the mesh occlusions. Only used by the voxel baker.
+  pub ambient_occlusion: bool,
+  /// Scale of the heightmaps. Only used by the heightmap baker.
+  pub heightmap: HeightMapOptions,
 }
 
 impl<T> Default for BakerOptions<T>
@@ -65,9 +97,58 @@ where
   fn default() -> Self {
     Self {
       isovalue: 0.,
-      level_of_detail: 1,
+      level_of_detail: 0,
+      lod_transitions: [false; 6],
       texture: None,
+      palette: None,
       remove_duplicate_verts: false,
+      greedy_meshing: false,
+      ambient_occlusion: false,
+      heightmap: HeightMapOptions::default(),
+    }
+  }
+}
+
+impl<T> BakerOptions<T>
+where
+  T: Texturify2d,
+{
+  /// Vertex color for the atlas index, `None` without `palette` or when it's not in it.
+  pub fn color(&self, atlas_index: u8) -> Option<[u8; 4]> {
+    self
+      .palette
+      .as_ref()
+      .and_then(|palette| palette.get(atlas_index as usize).copied())
+  }
+}
+
+/// Maps the heightmap samples to the mesh, the chunk x and y axes are the mesh x and z axes and the
+/// samples are the height on the y axis.
+#[derive(Clone, Debug)]
+pub struct HeightMapOptions {
+  /// Height between the lowest and the highest values of the `value_range`.
+  pub vertical_scale: f32,
+  /// Height of the lowest value of the `value_range`.
+  pub base_height: f32,
+  /// Horizontal distance between the samples.
+  pub cell_size: f32,
+  /// Sample values mapped to `base_height` and `base_height + vertical_scale`, the default matches
+  /// the `0.0` to `255.0` values of the heightmap readers. The values outside of it are
+  /// extrapolated.
+  pub value_range: [f32; 2],
+  /// Depth of the vertical skirts hanging from the borders of the chunks, they hide the cracks
+  /// between chunks baked with different resolutions or without neighbor data.
+  pub skirt_depth: Option<f32>,
+}
+
+impl Default for HeightMapOptions {
+  fn default() -> Self {
+    Self {
+      vertical_scale: 30.0,
+      base_height: 0.0,
+      cell_size: 1.0,
+      value_range: [0.0, 255.0],
+      skirt_depth: None,
     }
   }
 }
@@ -77,11 +158,118 @@ pub trait Baker {
   type Value;
   type AtlasValue;
 
+  /// Cells around the changed samples that `bake_region` bakes again, enough to reach all the
+  /// triangles that depend on them.
+  const REGION_MARGIN: usize = 1;
+
   fn bake<C, T, M>(chunk: &C, options: &BakerOptions<T>) -> Result<Option<M>>
   where
     C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable,
     T: Texturify2d,
     M: Meshify;
+
+  /// Bakes again only the cells around the `region` of samples changed since the `mesh` was baked
+  /// from the chunk with the same options, and splices them into it. See `rebake::bake_region`.
+  fn bake_region<C, T, M>(
+    chunk: &C,
+    region: &DirtyRegion,
+    mesh: Option<M>,
+    options: &BakerOptions<T>,
+  ) -> Result<Option<M>>
+  where
+    Self: Sized,
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable,
+    T: Texturify2d,
+    M: Meshify,
+  {
+    rebake::bake_region::<Self, C, T, M>(chunk, region, mesh, options)
+  }
+
+  /// Bakes the chunks in parallel on the rayon thread pool, returning the meshes paired with the
+  /// position of their chunk in the same order, the chunks without surface are skipped.
+  ///
+  /// The bakers only read the chunks, so any chunk that is `Sync` (like `Chunk` and
+  /// `SparseChunk`) can be baked this way.
+  #[cfg(feature = "parallel")]
+  fn bake_many<C, T, M>(chunks: &[C], options: &BakerOptions<T>) -> Result<Vec<(M, [f32; 3])>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable + Positionable + Sync,
+    T: Texturify2d + Sync,
+    M: Meshify + Send,
+  {
+    use rayon::prelude::*;
+
+    chunks
+      .par_iter()
+      .filter_map(|chunk| match Self::bake::<C, T, M>(chunk, options) {
+        Ok(Some(mesh)) => Some(Ok((mesh, chunk.position()))),
+        Ok(None) => None,
+        Err(error) => Some(Err(error)),
+      })
+      .collect()
+  }
+}
+
+/// Options to customize the `FileFormat` behaviour
+#[derive(Clone, Debug, Default)]
+pub struct FileFormatOptions {
+  /// Splits the model in cubic chunks of `chunk_size` voxels, aligned to the multiples of the size
+  /// and skipping the empty ones, instead of a single chunk for the whole model. `Some(16)` matches
+  /// the goxel blocks. Used by the gox reader, and by the raw, r16 and tiff readers that tile the
+  /// heightmaps in squares of `chunk_size` samples.
+  pub chunk_size: Option<u16>,
+  /// Axes of the chunks, the voxel models are converted from the axes of their files when they
+  /// are loaded and back when they are saved. The heightmaps are 2d data and keep their axes.
+  pub coordinate_system: CoordinateSystem,
+  /// What to do when the file has more colors than the 256 of the atlas. Only used by the gox
+  /// reader, the other formats can't have more.
+  pub palette_overflow: PaletteOverflow,
+}
+
+/// How the readers handle the files with more colors than the atlas can have.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PaletteOverflow {
+  /// The file fails to load.
+  #[default]
+  Error,
+  /// The colors are reduced with `quantization::quantize`, the file fails to load when its error
+  /// is above `max_error`.
+  Quantize { max_error: f32 },
+}
+
+/// Up axis and handedness of the voxel positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CoordinateSystem {
+  /// The default of gaiku and most game engines.
+  #[default]
+  YUpRightHanded,
+  YUpLeftHanded,
+  /// Used by goxel and MagicaVoxel.
+  ZUpRightHanded,
+  ZUpLeftHanded,
+}
+
+impl CoordinateSystem {
+  /// Converts the voxel at `position` in the `ZUpRightHanded` axes to this system. Each voxel is
+  /// the unit cube from its position, so the flipped axes move it to `-position - 1`.
+  pub fn from_z_up_right_handed(&self, [x, y, z]: [i32; 3]) -> [i32; 3] {
+    match self {
+      CoordinateSystem::YUpRightHanded => [x, z, -y - 1],
+      CoordinateSystem::YUpLeftHanded => [x, z, y],
+      CoordinateSystem::ZUpRightHanded => [x, y, z],
+      CoordinateSystem::ZUpLeftHanded => [x, -y - 1, z],
+    }
+  }
+
+  /// Converts the voxel at `position` in this system to the `ZUpRightHanded` axes.
+  pub fn to_z_up_right_handed(&self, [x, y, z]: [i32; 3]) -> [i32; 3] {
+    match self {
+      CoordinateSystem::YUpRightHanded => [x, -z - 1, y],
+      CoordinateSystem::YUpLeftHanded => [x, z, y],
+      CoordinateSystem::ZUpRightHanded => [x, y, z],
+      CoordinateSystem::ZUpLeftHanded => [x, -y - 1, z],
+    }
+  }
 }
 
 /// FileFormat is a trait used to define a {file extension} to chunk converter
@@ -89,47 +277,115 @@ pub trait FileFormat {
   type Value;
   type AtlasValue;
 
-  fn load<C, T>(bytes: Vec<u8>) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  fn load_with_options<C, T>(
+    bytes: Vec<u8>,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
   where
     C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
     T: Texturify2d;
 
-  fn read<C, T>(file: &str) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  fn load<C, T>(bytes: Vec<u8>) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    Self::load_with_options::<C, T>(bytes, &FileFormatOptions::default())
+  }
+
+  fn read_with_options<C, T>(
+    file: &str,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
   where
     C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
     T: Texturify2d,
   {
     let bytes = read(file)?;
-    Self::load::<C, T>(bytes)
+    Self::load_with_options::<C, T>(bytes, options)
   }
-}
 
-/*
-pub struct Gaiku {
-  terrain: Octree,
+  fn read<C, T>(file: &str) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    Self::read_with_options::<C, T>(file, &FileFormatOptions::default())
+  }
 }
 
-impl Gaiku {
-  pub fn new(data: Vec<Chunk>, size: [f32; 3]) -> Self {
-    let mut terrain = Octree::new(size, 8);
-
-    for chunk in data {
-      terrain.insert(&chunk);
-    }
+/// FileFormatWriter is the counterpart of `FileFormat`, used to convert the chunks back to a {file
+/// extension}
+pub trait FileFormatWriter: FileFormat {
+  /// Serializes the chunks, the colors of their atlas indexes are taken from the first pixel of
+  /// each tile of the `atlas` (see `TextureAtlas2d::palette`).
+  fn save_with_options<C, T>(
+    chunks: &[C],
+    atlas: Option<&TextureAtlas2d<T>>,
+    options: &FileFormatOptions,
+  ) -> Result<Vec<u8>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Boxify,
+    T: Texturify2d;
 
-    Self { terrain }
+  fn save<C, T>(chunks: &[C], atlas: Option<&TextureAtlas2d<T>>) -> Result<Vec<u8>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    Self::save_with_options::<C, T>(chunks, atlas, &FileFormatOptions::default())
   }
 
-  pub fn query(&self, boundary: &Boundary) -> Vec<Chunk> {
-    self.terrain.query(boundary)
+  fn write_with_options<C, T>(
+    file: &str,
+    chunks: &[C],
+    atlas: Option<&TextureAtlas2d<T>>,
+    options: &FileFormatOptions,
+  ) -> Result<()>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    let bytes = Self::save_with_options::<C, T>(chunks, atlas, options)?;
+    write(file, bytes)?;
+    Ok(())
   }
 
-  pub fn get_chunk(&self, point: &Vector3<f32>) -> Option<Chunk> {
-    self.terrain.get_leaf(point)
+  fn write<C, T>(file: &str, chunks: &[C], atlas: Option<&TextureAtlas2d<T>>) -> Result<()>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    Self::write_with_options::<C, T>(file, chunks, atlas, &FileFormatOptions::default())
   }
+}
 
-  pub fn set_chunk(&mut self, chunk: &Chunk) -> bool {
-    self.terrain.set_leaf(chunk)
+#[cfg(all(test, feature = "parallel"))]
+mod test {
+  use super::*;
+  use crate::{chunk::Chunk, mesh::Mesh, rebake::test_support::PointBaker, texture::Texture2d};
+
+  #[test]
+  fn check_bake_many() {
+    let chunks: Vec<Chunk> = (0..16)
+      .map(|index| {
+        let mut chunk = Chunk::new([index as f32 * 4.0, 0.0, 0.0], 4, 4, 4);
+        // The even chunks are left empty
+        for x in 0..(index % 2) * (index / 2 + 1) {
+          chunk.set(x % 4, x / 4, 0, 1.0);
+        }
+        chunk
+      })
+      .collect();
+
+    let options = BakerOptions::<Texture2d>::default();
+    let meshes = PointBaker::bake_many::<Chunk, Texture2d, Mesh>(&chunks, &options).unwrap();
+
+    assert_eq!(meshes.len(), 8);
+    for (index, (mesh, position)) in meshes.iter().enumerate() {
+      let chunk = index * 2 + 1;
+      assert_eq!(*position, [chunk as f32 * 4.0, 0.0, 0.0]);
+      assert_eq!(mesh.get_indices().len(), (chunk / 2 + 1) * 3);
+    }
   }
 }
-*/
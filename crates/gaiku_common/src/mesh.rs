@@ -6,35 +6,41 @@ pub trait Meshify {
     positions: Vec<[f32; 3]>,
     normals: Vec<[f32; 3]>,
     uvs: Vec<[f32; 2]>,
+    colors: Vec<[u8; 4]>,
+    occlusions: Vec<f32>,
+    tiles: Vec<[f32; 4]>,
   ) -> Self;
   fn get_indices(&self) -> &Vec<u32>;
   fn get_normals(&self) -> &Vec<[f32; 3]>;
   fn get_positions(&self) -> &Vec<[f32; 3]>;
   fn get_uvs(&self) -> &Vec<[f32; 2]>;
+  /// RGBA color of each vertex.
+  fn get_colors(&self) -> &Vec<[u8; 4]>;
+  /// Ambient light that reaches each vertex, `0.0` fully occluded and `1.0` not occluded.
+  fn get_occlusions(&self) -> &Vec<f32>;
+  /// Atlas tile of each vertex, its origin and size in uv space (`[u, v, width, height]`), for
+  /// the meshes which uvs repeat the tile instead of pointing into the atlas.
+  fn get_tiles(&self) -> &Vec<[f32; 4]>;
   fn set_indices(&mut self, indices: Vec<u32>);
   fn set_normals(&mut self, normals: Vec<[f32; 3]>);
   fn set_positions(&mut self, positions: Vec<[f32; 3]>);
   fn set_uvs(&mut self, uvs: Vec<[f32; 2]>);
+  fn set_colors(&mut self, colors: Vec<[u8; 4]>);
+  fn set_occlusions(&mut self, occlusions: Vec<f32>);
+  fn set_tiles(&mut self, tiles: Vec<[f32; 4]>);
 }
 
-/// Provides a `Meshify` implementation width indices, normals, positions and uvs.
-#[derive(Debug)]
+/// Provides a `Meshify` implementation width indices, normals, positions, uvs, colors, occlusions
+/// and tiles.
+#[derive(Debug, Default)]
 pub struct Mesh {
   indices: Vec<u32>,
   normals: Vec<[f32; 3]>,
   positions: Vec<[f32; 3]>,
   uvs: Vec<[f32; 2]>,
-}
-
-impl Default for Mesh {
-  fn default() -> Self {
-    Self {
-      indices: vec![],
-      normals: vec![],
-      positions: vec![],
-      uvs: vec![],
-    }
-  }
+  colors: Vec<[u8; 4]>,
+  occlusions: Vec<f32>,
+  tiles: Vec<[f32; 4]>,
 }
 
 impl Meshify for Mesh {
@@ -47,12 +53,18 @@ impl Meshify for Mesh {
     positions: Vec<[f32; 3]>,
     normals: Vec<[f32; 3]>,
     uvs: Vec<[f32; 2]>,
+    colors: Vec<[u8; 4]>,
+    occlusions: Vec<f32>,
+    tiles: Vec<[f32; 4]>,
   ) -> Self {
     Mesh {
       indices,
       positions,
       normals,
       uvs,
+      colors,
+      occlusions,
+      tiles,
     }
   }
 
@@ -72,6 +84,18 @@ impl Meshify for Mesh {
     &self.uvs
   }
 
+  fn get_colors(&self) -> &Vec<[u8; 4]> {
+    &self.colors
+  }
+
+  fn get_occlusions(&self) -> &Vec<f32> {
+    &self.occlusions
+  }
+
+  fn get_tiles(&self) -> &Vec<[f32; 4]> {
+    &self.tiles
+  }
+
   fn set_indices(&mut self, indices: Vec<u32>) {
     self.indices = indices;
   }
@@ -87,6 +111,18 @@ impl Meshify for Mesh {
   fn set_uvs(&mut self, uvs: Vec<[f32; 2]>) {
     self.uvs = uvs;
   }
+
+  fn set_colors(&mut self, colors: Vec<[u8; 4]>) {
+    self.colors = colors;
+  }
+
+  fn set_occlusions(&mut self, occlusions: Vec<f32>) {
+    self.occlusions = occlusions;
+  }
+
+  fn set_tiles(&mut self, tiles: Vec<[f32; 4]>) {
+    self.tiles = tiles;
+  }
   /*
     /// This will generate a texture from the
     /// mesh vertex colors and update the UV map
@@ -1,9 +1,9 @@
 // This is similar to a grid based method
 // except that we use a hashmap so we can
 // skip empty nodes
-use super::MeshBuilder;
+use super::{build_mesh, MeshBuilder, Vertex};
 use crate::{boundary::Boundary, mesh::Meshify};
-use glam::{Vec2, Vec3};
+use glam::Vec3;
 use std::collections::{hash_map::Values, HashMap};
 
 const EPSILON: f32 = 1e-4;
@@ -27,27 +27,14 @@ impl MeshBuilder for HashMapBuilder {
     }
   }
 
-  /// Inserts the vertice (position, normal, uv and atlas_index) if doesn't exists
-  /// and create a new indice for the current data, otherwise retrieves the index of
-  /// the input data and inserts the existing index.
-  fn add(
-    &mut self,
-    position: [f32; 3],
-    normal: Option<[f32; 3]>,
-    uv: Option<[f32; 2]>,
-    atlas_index: u16,
-  ) {
-    let p: Vec3 = position.into();
+  /// Inserts the vertex if doesn't exists and create a new indice for the current data,
+  /// otherwise retrieves the index of the input data and inserts the existing index.
+  fn add(&mut self, vertex: Vertex) {
+    let p: Vec3 = vertex.position.into();
     let delta: Vec3 = [EPSILON, EPSILON, EPSILON].into();
     let boundary = Boundary::new(&(p - delta), &(p + delta));
 
-    let new = MeshBuilderData::new(
-      position.into(),
-      normal.map(|d| d.into()),
-      uv.map(|d| d.into()),
-      atlas_index,
-      self.current_index,
-    );
+    let new = MeshBuilderData::new(vertex, self.current_index);
 
     let found_index = {
       let mut found = None;
@@ -83,17 +70,7 @@ impl MeshBuilder for HashMapBuilder {
       let verts: Vec<_> = unsorted_verts.into_iter().map(|data| data.1).collect();
 
       let indices = self.indices.clone();
-      let positions: Vec<_> = verts.iter().map(|d| d.position.into()).collect();
-      let normals: Vec<_> = verts
-        .iter()
-        .filter_map(|d| d.normal.map(|d| d.into()))
-        .collect();
-      let uvs: Vec<_> = verts
-        .iter()
-        .filter_map(|d| d.uv.map(|d| d.into()))
-        .collect();
-
-      Some(M::with(indices, positions, normals, uvs))
+      Some(build_mesh(indices, verts.iter().map(|d| &d.vertex)))
     } else {
       None
     }
@@ -102,58 +79,24 @@ impl MeshBuilder for HashMapBuilder {
 
 #[derive(Clone, Debug)]
 struct MeshBuilderData {
+  // Of the vertex, to find it in the cache
   position: Vec3,
-  normal: Option<Vec3>,
-  uv: Option<Vec2>,
-  atlas_index: u16,
+  vertex: Vertex,
   index: u32,
 }
 
 impl PartialEq for MeshBuilderData {
   fn eq(&self, other: &Self) -> bool {
-    let pos = (self.position - other.position).length() <= EPSILON;
-    if !pos {
-      return false;
-    }
-
-    let normal = match (self.normal, other.normal) {
-      (None, None) => true,
-      (None, Some(_)) => false,
-      (Some(_), None) => false,
-      (Some(a), Some(b)) => (a - b).length() <= EPSILON,
-    };
-    if !normal {
-      return false;
-    }
-
-    let uv = match (self.uv, other.uv) {
-      (None, None) => true,
-      (None, Some(_)) => false,
-      (Some(_), None) => false,
-      (Some(a), Some(b)) => (a - b).length() <= EPSILON,
-    };
-    if !uv {
-      return false;
-    }
-
-    self.atlas_index == other.atlas_index
+    self.vertex.approx_eq(&other.vertex)
   }
 }
 impl Eq for MeshBuilderData {}
 
 impl MeshBuilderData {
-  fn new(
-    position: Vec3,
-    normal: Option<Vec3>,
-    uv: Option<Vec2>,
-    atlas_index: u16,
-    index: u32,
-  ) -> Self {
+  fn new(vertex: Vertex, index: u32) -> Self {
     MeshBuilderData {
-      position,
-      normal,
-      uv,
-      atlas_index,
+      position: vertex.position.into(),
+      vertex,
       index,
     }
   }
@@ -196,11 +139,11 @@ impl HashMapTree {
       .push(leaf);
   }
 
-  fn iter(&self) -> HashMapTreeIter {
+  fn iter(&self) -> HashMapTreeIter<'_> {
     HashMapTreeIter::new(self)
   }
 
-  fn find_within(&self, boundary: Boundary) -> HashMapTreeRangeIter {
+  fn find_within(&self, boundary: Boundary) -> HashMapTreeRangeIter<'_> {
     HashMapTreeRangeIter::new(self, boundary)
   }
 }
@@ -310,19 +253,31 @@ mod test {
     let mut tree = HashMapBuilder::create([0.0, 0.0, 0.0], [4.0, 4.0, 4.0]);
 
     for _ in 0..10 {
-      tree.add([0., 0., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [0., 0., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([1., 0., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [1., 0., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([1., 1., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [1., 1., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([0., 1., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [0., 1., 0.],
+        ..Default::default()
+      });
     }
 
     assert_eq!(tree.cache.iter().count(), 4);
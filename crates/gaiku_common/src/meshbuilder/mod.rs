@@ -11,46 +11,105 @@ pub type DefaultMeshBuilder = OctMeshBuilder;
 
 use crate::Meshify;
 
+/// Vertex inserted in the builders, with its position and optional attributes. The builders
+/// that remove the duplicated vertices merge the ones with the same data, see `approx_eq`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vertex {
+  pub position: [f32; 3],
+  pub normal: Option<[f32; 3]>,
+  pub uv: Option<[f32; 2]>,
+  pub color: Option<[u8; 4]>,
+  /// Ambient occlusion, `1.0` is not occluded.
+  pub occlusion: Option<f32>,
+  /// Origin and size of the tile of the atlas, for the uvs that repeat over a merged face.
+  pub tile: Option<[f32; 4]>,
+  pub atlas_index: u16,
+}
+
+impl Vertex {
+  /// Returns `true` when both vertices have the same attributes, with the floats compared with a
+  /// small tolerance.
+  pub fn approx_eq(&self, other: &Self) -> bool {
+    fn close(a: &[f32], b: &[f32]) -> bool {
+      a.iter()
+        .zip(b.iter())
+        .all(|(a, b)| (a - b).abs() <= EPSILON)
+    }
+    fn close_option<const N: usize>(a: Option<[f32; N]>, b: Option<[f32; N]>) -> bool {
+      match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => close(&a, &b),
+        _ => false,
+      }
+    }
+
+    close(&self.position, &other.position)
+      && close_option(self.normal, other.normal)
+      && close_option(self.uv, other.uv)
+      && self.color == other.color
+      && close_option(self.occlusion.map(|a| [a]), other.occlusion.map(|b| [b]))
+      && close_option(self.tile, other.tile)
+      && self.atlas_index == other.atlas_index
+  }
+}
+
+const EPSILON: f32 = 1e-4;
+
+/// Mesh with the `vertices` in the order of their indices, the attributes that some vertices
+/// don't have are skipped.
+fn build_mesh<'a, M, I>(indices: Vec<u32>, vertices: I) -> M
+where
+  M: Meshify,
+  I: Iterator<Item = &'a Vertex> + Clone,
+{
+  M::with(
+    indices,
+    vertices.clone().map(|vertex| vertex.position).collect(),
+    vertices
+      .clone()
+      .filter_map(|vertex| vertex.normal)
+      .collect(),
+    vertices.clone().filter_map(|vertex| vertex.uv).collect(),
+    vertices.clone().filter_map(|vertex| vertex.color).collect(),
+    vertices
+      .clone()
+      .filter_map(|vertex| vertex.occlusion)
+      .collect(),
+    vertices.filter_map(|vertex| vertex.tile).collect(),
+  )
+}
+
 pub trait MeshBuilder {
   /// Crates a new mesh centered at a position and size.
   fn create(center: [f32; 3], size: [f32; 3]) -> Self;
 
-  /// Inserts the vertice (position, normal, uv and atlas_index)
-  fn add(
-    &mut self,
-    position: [f32; 3],
-    normal: Option<[f32; 3]>,
-    uv: Option<[f32; 2]>,
-    atlas_index: u16,
-  );
+  /// Inserts the vertex.
+  fn add(&mut self, vertex: Vertex);
 
   /// Inserts the triangle and generate the index if needed, otherwise use an existing index.
   /// The triangle data is expected to be counter-clockwise.
-  fn add_triangle(
-    &mut self,
-    triangle: [[f32; 3]; 3],
-    normal: Option<[f32; 3]>,
-    uv: Option<[[f32; 2]; 3]>,
-    atlas_index: u16,
-  ) {
-    for (i, vertex) in triangle.iter().enumerate() {
-      self.add(*vertex, normal, uv.map(|uv| uv[i]), atlas_index);
+  fn add_triangle(&mut self, triangle: [Vertex; 3]) {
+    for vertex in triangle.iter() {
+      self.add(*vertex);
     }
   }
 
   /// Inserts the face (generates 2  triangles) and generate the index if needed,
   /// otherwise use an existing index. The face data is expected to be counter-clockwise.
-  fn add_face(
-    &mut self,
-
-    face: [[f32; 3]; 4],
-    normal: Option<[f32; 3]>,
-    uv: Option<[[f32; 2]; 4]>,
-    atlas_index: u16,
-  ) {
-    [[0, 1, 3], [1, 2, 3]].iter().for_each(|triangle| {
+  ///
+  /// With occlusion the face is split through the diagonal of the brightest corners, so a single
+  /// occluded corner is interpolated the same way whatever the orientation of the face is.
+  fn add_face(&mut self, face: [Vertex; 4]) {
+    let occlusion = |i: usize| face[i].occlusion.unwrap_or(1.0);
+    let triangles = if occlusion(0) + occlusion(2) > occlusion(1) + occlusion(3) {
+      [[0, 1, 2], [0, 2, 3]]
+    } else {
+      [[0, 1, 3], [1, 2, 3]]
+    };
+
+    triangles.iter().for_each(|triangle| {
       triangle.iter().for_each(|i| {
-        self.add(face[*i], normal, uv.map(|uv| uv[*i]), atlas_index);
+        self.add(face[*i]);
       });
     });
   }
@@ -1,80 +1,20 @@
-use super::MeshBuilder;
+use super::{build_mesh, MeshBuilder, Vertex};
 use crate::mesh::Meshify;
 use std::convert::TryInto;
 
 pub struct NoTreeBuilder {
-  data: Vec<MeshBuilderData>,
+  data: Vec<Vertex>,
 }
 
-const EPSILON: f32 = 1e-4;
-
-#[derive(Clone, Debug)]
-struct MeshBuilderData {
-  position: [f32; 3],
-  normal: Option<[f32; 3]>,
-  uv: Option<[f32; 2]>,
-  atlas_index: u16,
-}
-
-impl PartialEq for MeshBuilderData {
-  fn eq(&self, other: &Self) -> bool {
-    let pos = (self.position[0] - other.position[0]).abs() <= EPSILON
-      && (self.position[1] - other.position[1]).abs() <= EPSILON
-      && (self.position[2] - other.position[2]).abs() <= EPSILON;
-    if !pos {
-      return false;
-    }
-
-    let normal = match (self.normal, other.normal) {
-      (None, None) => true,
-      (None, Some(_)) => false,
-      (Some(_), None) => false,
-      (Some(a), Some(b)) => {
-        (a[0] - b[0]).abs() <= EPSILON
-          && (a[1] - b[1]).abs() <= EPSILON
-          && (a[2] - b[2]).abs() <= EPSILON
-      }
-    };
-    if !normal {
-      return false;
-    }
-
-    let uv = match (self.uv, other.uv) {
-      (None, None) => true,
-      (None, Some(_)) => false,
-      (Some(_), None) => false,
-      (Some(a), Some(b)) => (a[0] - b[0]).abs() <= EPSILON && (a[1] - b[1]).abs() <= EPSILON,
-    };
-    if !uv {
-      return false;
-    }
-
-    self.atlas_index == other.atlas_index
-  }
-}
-impl Eq for MeshBuilderData {}
-
 impl MeshBuilder for NoTreeBuilder {
   /// Crates a new mesh centered at a position and size.
   fn create(_: [f32; 3], _: [f32; 3]) -> Self {
     Self { data: vec![] }
   }
 
-  /// Inserts the vertice (position, normal, uv and atlas_index)
-  fn add(
-    &mut self,
-    position: [f32; 3],
-    normal: Option<[f32; 3]>,
-    uv: Option<[f32; 2]>,
-    atlas_index: u16,
-  ) {
-    let new = MeshBuilderData {
-      position,
-      normal,
-      uv,
-      atlas_index,
-    };
-    self.data.push(new);
+  /// Inserts the vertex, every vertex gets its own index.
+  fn add(&mut self, vertex: Vertex) {
+    self.data.push(vertex);
   }
 
   fn build<M>(&self) -> Option<M>
@@ -86,14 +26,9 @@ impl MeshBuilder for NoTreeBuilder {
       // All at once (this is faster then inceremental instertion)
 
       let indices: Vec<u32> = (0..self.data.len())
-        .into_iter()
         .map(|i| i.try_into().unwrap())
         .collect();
-      let positions: Vec<_> = self.data.iter().map(|d| d.position).collect();
-      let normals: Vec<_> = self.data.iter().filter_map(|d| d.normal).collect();
-      let uvs: Vec<_> = self.data.iter().filter_map(|d| d.uv).collect();
-
-      Some(M::with(indices, positions, normals, uvs))
+      Some(build_mesh(indices, self.data.iter()))
     } else {
       None
     }
@@ -109,21 +44,59 @@ mod test {
     let mut tree = NoTreeBuilder::create([0.0, 0.0, 0.0], [4.0, 4.0, 4.0]);
 
     for _ in 0..10 {
-      tree.add([0., 0., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [0., 0., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([1., 0., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [1., 0., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([1., 1., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [1., 1., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([0., 1., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [0., 1., 0.],
+        ..Default::default()
+      });
     }
 
     assert_eq!(tree.data.len(), 40);
   }
+
+  #[test]
+  fn test_notree_face_occlusion() {
+    let face = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]];
+
+    // The occluded corner is only used by one of the triangles
+    for corner in 0..4 {
+      let mut occlusion = [1.0; 4];
+      occlusion[corner] = 0.0;
+
+      let mut tree = NoTreeBuilder::create([0.0, 0.0, 0.0], [4.0, 4.0, 4.0]);
+      tree.add_face([0, 1, 2, 3].map(|i| Vertex {
+        position: face[i],
+        occlusion: Some(occlusion[i]),
+        ..Default::default()
+      }));
+
+      let uses = tree
+        .data
+        .iter()
+        .filter(|data| data.position == face[corner])
+        .count();
+      assert_eq!(uses, 1);
+      assert_eq!(tree.data.len(), 6);
+    }
+  }
 }
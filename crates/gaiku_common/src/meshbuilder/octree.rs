@@ -1,6 +1,6 @@
-use super::MeshBuilder;
+use super::{build_mesh, MeshBuilder, Vertex};
 use crate::{boundary::Boundary, mesh::Meshify};
-use glam::{Vec2, Vec3};
+use glam::Vec3;
 
 const EPSILON: f32 = 1e-4;
 
@@ -34,27 +34,14 @@ impl MeshBuilder for OctMeshBuilder {
     }
   }
 
-  /// Inserts the vertice (position, normal, uv and atlas_index) if doesn't exists
-  /// and create a new indice for the current data, otherwise retrieves the index of
-  /// the input data and inserts the existing index.
-  fn add(
-    &mut self,
-    position: [f32; 3],
-    normal: Option<[f32; 3]>,
-    uv: Option<[f32; 2]>,
-    atlas_index: u16,
-  ) {
-    let p: Vec3 = position.into();
+  /// Inserts the vertex if doesn't exists and create a new indice for the current data,
+  /// otherwise retrieves the index of the input data and inserts the existing index.
+  fn add(&mut self, vertex: Vertex) {
+    let p: Vec3 = vertex.position.into();
     let delta: Vec3 = [EPSILON, EPSILON, EPSILON].into();
     let boundary = Boundary::new(&(p - delta), &(p + delta));
 
-    let new = MeshBuilderData::new(
-      position.into(),
-      normal.map(|d| d.into()),
-      uv.map(|d| d.into()),
-      atlas_index,
-      self.current_index,
-    );
+    let new = MeshBuilderData::new(vertex, self.current_index);
 
     let found_index = {
       let mut found = None;
@@ -77,7 +64,7 @@ impl MeshBuilder for OctMeshBuilder {
         }
         InsertResult::FailedInsert => panic!("Failed to insert"),
         InsertResult::OutOfBounds => {
-          panic!("Out of bounds {:?} in {:?}", position, self.cache.boundary)
+          panic!("Out of bounds {:?} in {:?}", p, self.cache.boundary)
         }
       };
     }
@@ -99,17 +86,7 @@ impl MeshBuilder for OctMeshBuilder {
       let verts: Vec<_> = unsorted_verts.into_iter().map(|data| data.1).collect();
 
       let indices = self.indices.clone();
-      let positions: Vec<_> = verts.iter().map(|d| d.position.into()).collect();
-      let normals: Vec<_> = verts
-        .iter()
-        .filter_map(|d| d.normal.map(|d| d.into()))
-        .collect();
-      let uvs: Vec<_> = verts
-        .iter()
-        .filter_map(|d| d.uv.map(|d| d.into()))
-        .collect();
-
-      Some(M::with(indices, positions, normals, uvs))
+      Some(build_mesh(indices, verts.iter().map(|d| &d.vertex)))
     } else {
       None
     }
@@ -124,58 +101,24 @@ impl Default for OctMeshBuilder {
 
 #[derive(Clone, Debug)]
 struct MeshBuilderData {
+  // Of the vertex, to find it in the cache
   position: Vec3,
-  normal: Option<Vec3>,
-  uv: Option<Vec2>,
-  atlas_index: u16,
+  vertex: Vertex,
   index: u32,
 }
 
 impl PartialEq for MeshBuilderData {
   fn eq(&self, other: &Self) -> bool {
-    let pos = (self.position - other.position).length() <= EPSILON;
-    if !pos {
-      return false;
-    }
-
-    let normal = match (self.normal, other.normal) {
-      (None, None) => true,
-      (None, Some(_)) => false,
-      (Some(_), None) => false,
-      (Some(a), Some(b)) => (a - b).length() <= EPSILON,
-    };
-    if !normal {
-      return false;
-    }
-
-    let uv = match (self.uv, other.uv) {
-      (None, None) => true,
-      (None, Some(_)) => false,
-      (Some(_), None) => false,
-      (Some(a), Some(b)) => (a - b).length() <= EPSILON,
-    };
-    if !uv {
-      return false;
-    }
-
-    self.atlas_index == other.atlas_index
+    self.vertex.approx_eq(&other.vertex)
   }
 }
 impl Eq for MeshBuilderData {}
 
 impl MeshBuilderData {
-  fn new(
-    position: Vec3,
-    normal: Option<Vec3>,
-    uv: Option<Vec2>,
-    atlas_index: u16,
-    index: u32,
-  ) -> Self {
+  fn new(vertex: Vertex, index: u32) -> Self {
     MeshBuilderData {
-      position,
-      normal,
-      uv,
-      atlas_index,
+      position: vertex.position.into(),
+      vertex,
       index,
     }
   }
@@ -253,11 +196,11 @@ impl MeshBuilderOctree {
     }
   }
 
-  fn iter(&self) -> OctreeIter {
+  fn iter(&self) -> OctreeIter<'_> {
     OctreeIter::new(self)
   }
 
-  fn find_within(&self, boundary: Boundary) -> OctreeRangeIter {
+  fn find_within(&self, boundary: Boundary) -> OctreeRangeIter<'_> {
     OctreeRangeIter::new(self, boundary)
   }
 }
@@ -364,6 +307,7 @@ impl std::fmt::Debug for MeshBuilderOctree {
   }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Eq, Hash, PartialEq)]
 struct Position(i32, i32, i32);
 
@@ -449,10 +393,10 @@ mod test {
       for y in 0..4 {
         for z in 0..4 {
           tree.insert(MeshBuilderData::new(
-            [x as f32, y as f32, z as f32].into(),
-            None,
-            None,
-            0,
+            Vertex {
+              position: [x as f32, y as f32, z as f32],
+              ..Default::default()
+            },
             0,
           ));
         }
@@ -475,10 +419,12 @@ mod test {
     );
 
     match tree.insert(MeshBuilderData::new(
-      [8.0, 8.0, 8.0].into(),
-      Some([0.0, 0.0, 0.0].into()),
-      Some([0.0, 0.0].into()),
-      0,
+      Vertex {
+        position: [8.0, 8.0, 8.0],
+        normal: Some([0.0, 0.0, 0.0]),
+        uv: Some([0.0, 0.0]),
+        ..Default::default()
+      },
       0,
     )) {
       InsertResult::Inserted => {}
@@ -500,10 +446,10 @@ mod test {
     );
 
     match tree.insert(MeshBuilderData::new(
-      [8.0, 8.0, 8.0].into(),
-      None,
-      None,
-      0,
+      Vertex {
+        position: [8.0, 8.0, 8.0],
+        ..Default::default()
+      },
       0,
     )) {
       InsertResult::Inserted => {}
@@ -514,10 +460,10 @@ mod test {
     }
 
     match tree.insert(MeshBuilderData::new(
-      [16.0, 16.0, 16.0].into(),
-      None,
-      None,
-      0,
+      Vertex {
+        position: [16.0, 16.0, 16.0],
+        ..Default::default()
+      },
       0,
     )) {
       InsertResult::Inserted => {}
@@ -535,19 +481,31 @@ mod test {
     let mut tree = OctMeshBuilder::create([0.0, 0.0, 0.0], [4.0, 4.0, 4.0]);
 
     for _ in 0..10 {
-      tree.add([0., 0., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [0., 0., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([1., 0., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [1., 0., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([1., 1., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [1., 1., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([0., 1., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [0., 1., 0.],
+        ..Default::default()
+      });
     }
 
     assert_eq!(tree.cache.iter().count(), 4);
@@ -1,4 +1,4 @@
-use super::MeshBuilder;
+use super::{build_mesh, MeshBuilder, Vertex};
 use crate::mesh::Meshify;
 use glam::Vec3;
 use rstar::{RTree, RTreeObject, AABB};
@@ -13,47 +13,13 @@ const EPSILON: f32 = 1e-4;
 
 #[derive(Clone, Debug)]
 struct MeshBuilderData {
-  position: [f32; 3],
-  normal: Option<[f32; 3]>,
-  uv: Option<[f32; 2]>,
-  atlas_index: u16,
+  vertex: Vertex,
   index: u32,
 }
 
 impl PartialEq for MeshBuilderData {
   fn eq(&self, other: &Self) -> bool {
-    let pos = (self.position[0] - other.position[0]).abs() <= EPSILON
-      && (self.position[1] - other.position[1]).abs() <= EPSILON
-      && (self.position[2] - other.position[2]).abs() <= EPSILON;
-    if !pos {
-      return false;
-    }
-
-    let normal = match (self.normal, other.normal) {
-      (None, None) => true,
-      (None, Some(_)) => false,
-      (Some(_), None) => false,
-      (Some(a), Some(b)) => {
-        (a[0] - b[0]).abs() <= EPSILON
-          && (a[1] - b[1]).abs() <= EPSILON
-          && (a[2] - b[2]).abs() <= EPSILON
-      }
-    };
-    if !normal {
-      return false;
-    }
-
-    let uv = match (self.uv, other.uv) {
-      (None, None) => true,
-      (None, Some(_)) => false,
-      (Some(_), None) => false,
-      (Some(a), Some(b)) => (a[0] - b[0]).abs() <= EPSILON && (a[1] - b[1]).abs() <= EPSILON,
-    };
-    if !uv {
-      return false;
-    }
-
-    self.atlas_index == other.atlas_index
+    self.vertex.approx_eq(&other.vertex)
   }
 }
 impl Eq for MeshBuilderData {}
@@ -63,14 +29,14 @@ impl RTreeObject for MeshBuilderData {
 
   fn envelope(&self) -> Self::Envelope {
     let corner_1 = [
-      self.position[0] - EPSILON,
-      self.position[1] - EPSILON,
-      self.position[2] - EPSILON,
+      self.vertex.position[0] - EPSILON,
+      self.vertex.position[1] - EPSILON,
+      self.vertex.position[2] - EPSILON,
     ];
     let corner_2 = [
-      self.position[0] + EPSILON,
-      self.position[1] + EPSILON,
-      self.position[2] + EPSILON,
+      self.vertex.position[0] + EPSILON,
+      self.vertex.position[1] + EPSILON,
+      self.vertex.position[2] + EPSILON,
     ];
     AABB::from_corners(corner_1, corner_2)
   }
@@ -113,25 +79,14 @@ impl MeshBuilder for RstarMeshBuilder {
     }
   }
 
-  /// Inserts the vertice (position, normal, uv and atlas_index)
-  fn add(
-    &mut self,
-    position: [f32; 3],
-    normal: Option<[f32; 3]>,
-    uv: Option<[f32; 2]>,
-    atlas_index: u16,
-  ) {
+  /// Inserts the vertex if doesn't exists and create a new indice for the current data,
+  /// otherwise retrieves the index of the input data and inserts the existing index.
+  fn add(&mut self, vertex: Vertex) {
     let index: u32 = self.tree.size().try_into().unwrap();
-    let new = MeshBuilderData {
-      position,
-      normal,
-      uv,
-      atlas_index,
-      index,
-    };
+    let new = MeshBuilderData { vertex, index };
     let found_index = {
       let mut found = None;
-      let p: Vec3 = position.into();
+      let p: Vec3 = vertex.position.into();
       let delta: Vec3 = [EPSILON, EPSILON, EPSILON].into();
       let envelope = AABB::from_corners((p - delta).into(), (p + delta).into());
       for item in self.tree.locate_in_envelope_intersecting(&envelope) {
@@ -166,11 +121,7 @@ impl MeshBuilder for RstarMeshBuilder {
       let verts: Vec<_> = unsorted_verts.into_iter().map(|data| data.1).collect();
 
       let indices = self.indices.clone();
-      let positions: Vec<_> = verts.iter().map(|d| d.position).collect();
-      let normals: Vec<_> = verts.iter().filter_map(|d| d.normal).collect();
-      let uvs: Vec<_> = verts.iter().filter_map(|d| d.uv).collect();
-
-      Some(M::with(indices, positions, normals, uvs))
+      Some(build_mesh(indices, verts.iter().map(|d| &d.vertex)))
     } else {
       None
     }
@@ -186,19 +137,31 @@ mod test {
     let mut tree = RstarMeshBuilder::create([0.0, 0.0, 0.0], [4.0, 4.0, 4.0]);
 
     for _ in 0..10 {
-      tree.add([0., 0., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [0., 0., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([1., 0., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [1., 0., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([1., 1., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [1., 1., 0.],
+        ..Default::default()
+      });
     }
 
     for _ in 0..10 {
-      tree.add([0., 1., 0.], None, None, 0);
+      tree.add(Vertex {
+        position: [0., 1., 0.],
+        ..Default::default()
+      });
     }
 
     assert_eq!(tree.tree.size(), 4);
@@ -0,0 +1,144 @@
+/// Colors reduced to fit in a palette.
+#[derive(Clone, Debug)]
+pub struct Quantization {
+  pub palette: Vec<[u8; 4]>,
+  /// Index in the `palette` of each of the original colors.
+  pub indexes: Vec<usize>,
+  /// Root mean square distance from the original colors (weighted by their count) to their palette
+  /// color, in the 0 to 255 units of the channels.
+  pub error: f32,
+}
+
+/// Reduces the `colors`, with the number of voxels that use each one, to `size` colors at most
+/// with the median cut algorithm. The colors that already fit are kept as they are.
+pub fn quantize(colors: &[([u8; 4], usize)], size: usize) -> Quantization {
+  if colors.len() <= size {
+    return Quantization {
+      palette: colors.iter().map(|(color, _)| *color).collect(),
+      indexes: (0..colors.len()).collect(),
+      error: 0.0,
+    };
+  }
+
+  let weight = |color: usize| colors[color].1.max(1);
+  let mut buckets: Vec<Vec<usize>> = vec![(0..colors.len()).collect()];
+
+  while buckets.len() < size {
+    // Splits the bucket with the widest channel
+    let widest = buckets
+      .iter()
+      .enumerate()
+      .filter(|(_, bucket)| bucket.len() > 1)
+      .flat_map(|(index, bucket)| {
+        (0..4).map(move |channel| {
+          let values = bucket.iter().map(|&color| colors[color].0[channel]);
+          let range = values.clone().max().unwrap() - values.min().unwrap();
+          (range, index, channel)
+        })
+      })
+      .max();
+
+    let (index, channel) = match widest {
+      Some((_, index, channel)) => (index, channel),
+      None => break,
+    };
+
+    let mut bucket = buckets.swap_remove(index);
+    bucket.sort_by_key(|&color| colors[color].0[channel]);
+
+    // Before the weighted median, leaving at least one color on each side
+    let total: usize = bucket.iter().map(|&color| weight(color)).sum();
+    let mut accumulated = 0;
+    let mut split = bucket.len() - 1;
+    for (position, &color) in bucket.iter().enumerate() {
+      accumulated += weight(color);
+      if accumulated * 2 >= total {
+        split = position.max(1);
+        break;
+      }
+    }
+
+    let other = bucket.split_off(split);
+    buckets.push(bucket);
+    buckets.push(other);
+  }
+
+  let mut palette = Vec::with_capacity(buckets.len());
+  let mut indexes = vec![0; colors.len()];
+  let mut squared_error = 0.0;
+  let mut total = 0.0;
+
+  for (index, bucket) in buckets.iter().enumerate() {
+    let bucket_total: f32 = bucket.iter().map(|&color| weight(color) as f32).sum();
+    let mut average = [0.0_f32; 4];
+    for &color in bucket.iter() {
+      for (average, value) in average.iter_mut().zip(colors[color].0.iter()) {
+        *average += *value as f32 * weight(color) as f32 / bucket_total;
+      }
+    }
+
+    let average = [
+      average[0].round() as u8,
+      average[1].round() as u8,
+      average[2].round() as u8,
+      average[3].round() as u8,
+    ];
+
+    for &color in bucket.iter() {
+      indexes[color] = index;
+      let distance: f32 = (0..4)
+        .map(|channel| (colors[color].0[channel] as f32 - average[channel] as f32).powi(2))
+        .sum();
+      squared_error += distance * weight(color) as f32;
+      total += weight(color) as f32;
+    }
+
+    palette.push(average);
+  }
+
+  Quantization {
+    palette,
+    indexes,
+    error: (squared_error / total).sqrt(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_quantize_fitting_colors() {
+    let colors = [([255, 0, 0, 255], 3), ([0, 0, 255, 255], 1)];
+    let quantization = quantize(&colors, 2);
+
+    assert_eq!(
+      quantization.palette,
+      vec![[255, 0, 0, 255], [0, 0, 255, 255]]
+    );
+    assert_eq!(quantization.indexes, vec![0, 1]);
+    assert_eq!(quantization.error, 0.0);
+  }
+
+  #[test]
+  fn test_quantize() {
+    let colors = [
+      ([0, 0, 0, 255], 1),
+      ([2, 0, 0, 255], 1),
+      ([200, 0, 0, 255], 3),
+      ([210, 0, 0, 255], 1),
+    ];
+    let quantization = quantize(&colors, 2);
+
+    // The dark and the red colors, the red averaged by their count
+    assert_eq!(quantization.palette.len(), 2);
+    assert_eq!(quantization.indexes[0], quantization.indexes[1]);
+    assert_eq!(quantization.indexes[2], quantization.indexes[3]);
+    assert_ne!(quantization.indexes[0], quantization.indexes[2]);
+    assert!(quantization.palette.contains(&[1, 0, 0, 255]));
+    assert!(quantization.palette.contains(&[203, 0, 0, 255]));
+
+    // sqrt((1 + 1 + 3 * 9 + 49) / 6)
+    assert!((quantization.error - 3.6056).abs() < 1e-3);
+  }
+}
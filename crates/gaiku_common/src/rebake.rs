@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+
+use crate::{
+  atlas::Atlasify,
+  boxify::*,
+  chunk::{Chunkify, DirtyRegion},
+  mesh::Meshify,
+  texture::Texturify2d,
+  Baker, BakerOptions, Result,
+};
+
+/// Bakes the cells around the `region` of samples with the baker `B` and splices them into the
+/// `mesh` baked before from the chunk, the default implementation of `Baker::bake_region`.
+///
+/// The triangles within `B::REGION_MARGIN` cells of the region are replaced by the ones baked
+/// from a window of the chunk a few cells bigger, so the cells on the borders of the window that
+/// don't match the ones of the whole chunk are left out. The result has the same triangles as
+/// baking the whole chunk, in a different order. It only works for the bakers that place the
+/// vertices in sample units, where the sample `[x, y, z]` is at the position `[x, y, z]`.
+pub fn bake_region<B, C, T, M>(
+  chunk: &C,
+  region: &DirtyRegion,
+  mesh: Option<M>,
+  options: &BakerOptions<T>,
+) -> Result<Option<M>>
+where
+  B: Baker,
+  C: Chunkify<B::Value> + Atlasify<B::AtlasValue> + Sizable,
+  T: Texturify2d,
+  M: Meshify,
+{
+  // The window starts on a cell of the level of detail, so both bakes use the same cells
+  let stride = 1 << options.level_of_detail;
+  let margin = B::REGION_MARGIN * stride;
+  let size = [
+    chunk.width() as usize,
+    chunk.height() as usize,
+    chunk.depth() as usize,
+  ];
+
+  let mut min = [0.0; 3];
+  let mut max = [0.0; 3];
+  let mut start = [0; 3];
+  let mut end = [0; 3];
+  for axis in 0..3 {
+    let low = region.min[axis].saturating_sub(margin);
+    let high = region.max[axis] + margin;
+    // Past the first sample too, where the bakers place the cells before the chunk
+    min[axis] = region.min[axis] as f32 - margin as f32;
+    max[axis] = high as f32;
+    start[axis] = low.saturating_sub(margin + stride) / stride * stride;
+    end[axis] = (high + margin + stride)
+      .min(size[axis] - 1)
+      .max(start[axis]);
+  }
+
+  let window = Window {
+    chunk,
+    chunk_size: size,
+    start,
+    size: [
+      end[0] - start[0] + 1,
+      end[1] - start[1] + 1,
+      end[2] - start[2] + 1,
+    ],
+  };
+  let patch = B::bake::<_, T, M>(&window, options)?;
+
+  let mut spliced = Splice::default();
+  if let Some(mesh) = &mesh {
+    spliced.append(mesh, [0.0; 3], |centroid| !contains(min, max, centroid));
+  }
+  if let Some(patch) = &patch {
+    let offset = [start[0] as f32, start[1] as f32, start[2] as f32];
+    spliced.append(patch, offset, |centroid| contains(min, max, centroid));
+  }
+
+  Ok(spliced.build())
+}
+
+// Inclusive, with some room for the rounding of the positions moved from the window
+fn contains(min: [f32; 3], max: [f32; 3], point: [f32; 3]) -> bool {
+  (0..3).all(|axis| min[axis] - 1e-3 <= point[axis] && point[axis] <= max[axis] + 1e-3)
+}
+
+/// Box of samples of a chunk, seen as a smaller chunk starting on its origin.
+struct Window<'a, C> {
+  chunk: &'a C,
+  chunk_size: [usize; 3],
+  start: [usize; 3],
+  size: [usize; 3],
+}
+
+impl<'a, C> Window<'a, C> {
+  // Wrapping, so the `-1` of the window is the sample before it
+  fn to_chunk(&self, x: usize, y: usize, z: usize) -> (usize, usize, usize) {
+    (
+      x.wrapping_add(self.start[0]),
+      y.wrapping_add(self.start[1]),
+      z.wrapping_add(self.start[2]),
+    )
+  }
+}
+
+impl<'a, C, V> Chunkify<V> for Window<'a, C>
+where
+  C: Chunkify<V>,
+{
+  fn is_air(&self, x: usize, y: usize, z: usize, isovalue: f32) -> bool {
+    let (x, y, z) = self.to_chunk(x, y, z);
+    self.chunk.is_air(x, y, z, isovalue)
+  }
+
+  fn get(&self, x: usize, y: usize, z: usize) -> V {
+    let (x, y, z) = self.to_chunk(x, y, z);
+    self.chunk.get(x, y, z)
+  }
+
+  // Around the window there are the other samples of the chunk, or its neighbor data
+  fn has_neighbor_data(&self) -> bool {
+    self.chunk.has_neighbor_data()
+  }
+
+  // The sides of the window inside of the chunk always have samples around
+  fn has_neighbor(&self, offset: [i32; 3]) -> bool {
+    let mut chunk_offset = [0; 3];
+    for axis in 0..3 {
+      let inside = match offset[axis] {
+        -1 => self.start[axis] > 0,
+        1 => self.start[axis] + self.size[axis] < self.chunk_size[axis],
+        _ => true,
+      };
+      if !inside {
+        chunk_offset[axis] = offset[axis];
+      }
+    }
+
+    chunk_offset == [0, 0, 0] || self.chunk.has_neighbor(chunk_offset)
+  }
+
+  fn get_gradient(&self, x: usize, y: usize, z: usize) -> Option<[f32; 3]> {
+    let (x, y, z) = self.to_chunk(x, y, z);
+    self.chunk.get_gradient(x, y, z)
+  }
+}
+
+impl<'a, C, A> Atlasify<A> for Window<'a, C>
+where
+  C: Atlasify<A>,
+{
+  fn get_atlas(&self, x: usize, y: usize, z: usize) -> A {
+    let (x, y, z) = self.to_chunk(x, y, z);
+    self.chunk.get_atlas(x, y, z)
+  }
+}
+
+impl<'a, C> Sizable for Window<'a, C> {
+  fn with_size(_width: u16, _height: u16, _depth: u16) -> Self {
+    unreachable!("the windows only exist borrowing a chunk")
+  }
+
+  fn width(&self) -> u16 {
+    self.size[0] as u16
+  }
+
+  fn height(&self) -> u16 {
+    self.size[1] as u16
+  }
+
+  fn depth(&self) -> u16 {
+    self.size[2] as u16
+  }
+}
+
+/// The triangles picked from several meshes, the vertices shared inside of each mesh are kept
+/// shared.
+#[derive(Default)]
+struct Splice {
+  indices: Vec<u32>,
+  positions: Vec<[f32; 3]>,
+  normals: Vec<[f32; 3]>,
+  uvs: Vec<[f32; 2]>,
+  colors: Vec<[u8; 4]>,
+  occlusions: Vec<f32>,
+  tiles: Vec<[f32; 4]>,
+}
+
+impl Splice {
+  /// Appends the triangles of the mesh moved by the offset, when `keep` accepts their centroid.
+  fn append<M, F>(&mut self, mesh: &M, offset: [f32; 3], keep: F)
+  where
+    M: Meshify,
+    F: Fn([f32; 3]) -> bool,
+  {
+    let positions = mesh.get_positions();
+    let position = |index: u32| {
+      let position = positions[index as usize];
+      [
+        position[0] + offset[0],
+        position[1] + offset[1],
+        position[2] + offset[2],
+      ]
+    };
+    // The attributes missing in the mesh are skipped
+    let has = |len: usize| len == positions.len();
+    let mut remap = HashMap::new();
+
+    for triangle in mesh.get_indices().chunks(3) {
+      let vertices = [
+        position(triangle[0]),
+        position(triangle[1]),
+        position(triangle[2]),
+      ];
+      let mut centroid = [0.0; 3];
+      for (axis, centroid) in centroid.iter_mut().enumerate() {
+        *centroid = vertices.iter().map(|vertex| vertex[axis]).sum::<f32>() / 3.0;
+      }
+      if !keep(centroid) {
+        continue;
+      }
+
+      for &index in triangle {
+        let next = self.positions.len() as u32;
+        let spliced = *remap.entry(index).or_insert(next);
+        if spliced == next {
+          let i = index as usize;
+          self.positions.push(position(index));
+          if has(mesh.get_normals().len()) {
+            self.normals.push(mesh.get_normals()[i]);
+          }
+          if has(mesh.get_uvs().len()) {
+            self.uvs.push(mesh.get_uvs()[i]);
+          }
+          if has(mesh.get_colors().len()) {
+            self.colors.push(mesh.get_colors()[i]);
+          }
+          if has(mesh.get_occlusions().len()) {
+            self.occlusions.push(mesh.get_occlusions()[i]);
+          }
+          if has(mesh.get_tiles().len()) {
+            self.tiles.push(mesh.get_tiles()[i]);
+          }
+        }
+        self.indices.push(spliced);
+      }
+    }
+  }
+
+  fn build<M>(self) -> Option<M>
+  where
+    M: Meshify,
+  {
+    if self.indices.is_empty() {
+      return None;
+    }
+
+    // Both meshes come from the same options, but an attribute only some vertices have is useless
+    let len = self.positions.len();
+    let complete = |attribute_len: usize| attribute_len == len;
+    Some(M::with(
+      self.indices,
+      self.positions,
+      if complete(self.normals.len()) {
+        self.normals
+      } else {
+        vec![]
+      },
+      if complete(self.uvs.len()) {
+        self.uvs
+      } else {
+        vec![]
+      },
+      if complete(self.colors.len()) {
+        self.colors
+      } else {
+        vec![]
+      },
+      if complete(self.occlusions.len()) {
+        self.occlusions
+      } else {
+        vec![]
+      },
+      if complete(self.tiles.len()) {
+        self.tiles
+      } else {
+        vec![]
+      },
+    ))
+  }
+}
+
+/// Fixtures and checks shared by the tests of the bakers, only built for the tests and with the
+/// `test-support` feature, which the baker crates enable in their dev-dependencies.
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support {
+  use super::*;
+  use crate::{
+    chunk::{Chunk, ChunkifyMut, Dirtify},
+    mesh::Mesh,
+    meshbuilder::{MeshBuilder, NoTreeBuilder, Vertex},
+    texture::Texture2d,
+    world::World,
+  };
+
+  /// Baker with a triangle on every solid sample, its height depends on the next sample on the x
+  /// axis like the cells of the real bakers.
+  pub struct PointBaker;
+
+  impl Baker for PointBaker {
+    type Value = f32;
+    type AtlasValue = u8;
+
+    fn bake<C, T, M>(chunk: &C, _options: &BakerOptions<T>) -> Result<Option<M>>
+    where
+      C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Sizable,
+      T: Texturify2d,
+      M: Meshify,
+    {
+      let mut builder = NoTreeBuilder::create([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]);
+      for x in 0..chunk.width() as usize {
+        for y in 0..chunk.height() as usize {
+          for z in 0..chunk.depth() as usize {
+            if !chunk.is_air(x, y, z, 0.0) {
+              let height = if x + 1 < chunk.width() as usize {
+                chunk.get(x + 1, y, z)
+              } else {
+                0.0
+              };
+              let (x, y, z) = (x as f32, y as f32, z as f32);
+              let triangle = [[x, y, z], [x + 0.5, y, z], [x, y + 0.5 + height, z]];
+              builder.add_triangle(triangle.map(|position| Vertex {
+                position,
+                normal: Some([0.0, 0.0, 1.0]),
+                ..Default::default()
+              }));
+            }
+          }
+        }
+      }
+
+      Ok(builder.build::<M>())
+    }
+  }
+
+  /// Density of a rolling terrain with the surface around `y = 6`.
+  pub fn terrain(x: f32, y: f32, z: f32) -> f32 {
+    6.3 - y + (x * 0.7).sin() * 1.5 + (z * 0.5).cos()
+  }
+
+  /// The triangles with the positions rounded, sorted so the meshes can be compared.
+  pub fn triangles<M>(mesh: &M) -> Vec<[[i32; 3]; 3]>
+  where
+    M: Meshify,
+  {
+    moved_triangles(mesh, [0.0; 3])
+  }
+
+  // The triangles of the mesh moved by the offset, before rounding them
+  fn moved_triangles<M>(mesh: &M, offset: [f32; 3]) -> Vec<[[i32; 3]; 3]>
+  where
+    M: Meshify,
+  {
+    let positions = mesh.get_positions();
+    let mut triangles: Vec<_> = mesh
+      .get_indices()
+      .chunks(3)
+      .map(|triangle| {
+        let mut vertices = [[0; 3]; 3];
+        for (vertex, &index) in vertices.iter_mut().zip(triangle) {
+          for axis in 0..3 {
+            vertex[axis] =
+              ((positions[index as usize][axis] + offset[axis]) * 100.0).round() as i32;
+          }
+        }
+        vertices
+      })
+      .collect();
+    triangles.sort_unstable();
+    triangles
+  }
+
+  /// Digs holes through a chunk of `terrain`, without and with neighbor data, and checks that
+  /// baking the changed region gives the same triangles as baking the whole chunk again.
+  pub fn check_bake_region<B>(options: &BakerOptions<Texture2d>)
+  where
+    B: Baker<Value = f32, AtlasValue = u8>,
+  {
+    let mut world = World::<Chunk>::new([16, 16, 16]);
+    let mut alone = Chunk::new([0.0, 0.0, 0.0], 16, 16, 16);
+    for x in -16..32 {
+      for y in 0..16 {
+        for z in 0..16 {
+          let value = terrain(x as f32, y as f32, z as f32);
+          world.set(x, y, z, value);
+          if (0..16).contains(&x) {
+            alone.set(x as usize, y as usize, z as usize, value);
+          }
+        }
+      }
+    }
+    world.update_neighbor_data([0, 0, 0]);
+    let with_neighbors = world.get_chunk([0, 0, 0]).unwrap().clone();
+
+    for mut chunk in [alone, with_neighbors] {
+      let mesh = B::bake::<Chunk, Texture2d, Mesh>(&chunk, options).unwrap();
+      chunk.clear_dirty();
+
+      // One in the middle and one through the first samples
+      for (x, z) in [(6..9, 7..11), (0..2, 2..4)].iter().cloned() {
+        for x in x {
+          for y in 3..9 {
+            for z in z.clone() {
+              chunk.set(x, y, z, -1.0);
+            }
+          }
+        }
+      }
+      let region = chunk.dirty_region().unwrap();
+
+      let full = B::bake::<Chunk, Texture2d, Mesh>(&chunk, options)
+        .unwrap()
+        .unwrap();
+      let spliced = B::bake_region(&chunk, &region, mesh, options)
+        .unwrap()
+        .unwrap();
+      assert_eq!(triangles(&spliced), triangles(&full));
+    }
+  }
+
+  /// Bakes two chunks of `terrain` next to each other with their neighbor data, and checks that
+  /// they have the same triangles as a single chunk covering both, away from its borders.
+  pub fn check_seams<B>(options: &BakerOptions<Texture2d>)
+  where
+    B: Baker<Value = f32, AtlasValue = u8>,
+  {
+    let mut world = World::<Chunk>::new([16, 16, 16]);
+    let mut whole = Chunk::new([0.0, 0.0, 0.0], 32, 16, 16);
+    for x in 0..32 {
+      for y in 0..16 {
+        for z in 0..16 {
+          let value = terrain(x as f32, y as f32, z as f32);
+          world.set(x as i32, y as i32, z as i32, value);
+          whole.set(x, y, z, value);
+        }
+      }
+    }
+    world.update_neighbor_data([0, 0, 0]);
+    world.update_neighbor_data([1, 0, 0]);
+
+    let inside = |triangle: &[[i32; 3]; 3]| {
+      let centroid = |axis: usize| triangle.iter().map(|vertex| vertex[axis]).sum::<i32>() / 3;
+      (200..=2900).contains(&centroid(0))
+        && (200..=1300).contains(&centroid(1))
+        && (200..=1300).contains(&centroid(2))
+    };
+
+    let mut seamed = vec![];
+    for coord in [[0, 0, 0], [1, 0, 0]].iter() {
+      let chunk = world.get_chunk(*coord).unwrap();
+      let mesh = B::bake::<Chunk, Texture2d, Mesh>(chunk, options)
+        .unwrap()
+        .unwrap();
+      seamed.extend(moved_triangles(&mesh, chunk.position()));
+    }
+    seamed.retain(inside);
+    seamed.sort_unstable();
+
+    let whole = B::bake::<Chunk, Texture2d, Mesh>(&whole, options)
+      .unwrap()
+      .unwrap();
+    let mut expected = triangles(&whole);
+    expected.retain(inside);
+
+    assert!(!expected.is_empty());
+    assert_eq!(seamed, expected);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    test_support::{triangles, PointBaker},
+    *,
+  };
+  use crate::{
+    chunk::{Chunk, ChunkifyMut, Dirtify},
+    mesh::Mesh,
+    texture::Texture2d,
+  };
+
+  #[test]
+  fn check_bake_region() {
+    let options = BakerOptions::<Texture2d>::default();
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 8, 8, 8);
+    for x in 0..8 {
+      for z in 0..8 {
+        chunk.set(x, 0, z, 1.0);
+        chunk.set(x, 1, z, 1.0);
+      }
+    }
+    let mesh = PointBaker::bake::<_, _, Mesh>(&chunk, &options).unwrap();
+    chunk.clear_dirty();
+
+    // The triangles of the samples before the changed ones change too
+    chunk.set(3, 1, 4, -1.0);
+    chunk.set(5, 3, 6, 2.0);
+    chunk.set(6, 1, 6, 0.5);
+    let region = chunk.dirty_region().unwrap();
+
+    let full = PointBaker::bake::<_, _, Mesh>(&chunk, &options)
+      .unwrap()
+      .unwrap();
+    let spliced = PointBaker::bake_region(&chunk, &region, mesh, &options)
+      .unwrap()
+      .unwrap();
+
+    assert_eq!(triangles(&spliced), triangles(&full));
+    assert_eq!(spliced.get_normals().len(), spliced.get_positions().len());
+
+    // Removing all the surface leaves no mesh
+    let empty = Chunk::new([0.0, 0.0, 0.0], 8, 8, 8);
+    let region = DirtyRegion {
+      min: [0, 0, 0],
+      max: [7, 7, 7],
+    };
+    assert!(
+      PointBaker::bake_region(&empty, &region, Some(full), &options)
+        .unwrap()
+        .is_none()
+    );
+  }
+}
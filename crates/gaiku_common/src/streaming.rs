@@ -0,0 +1,387 @@
+use std::collections::HashSet;
+
+use crate::{
+  boxify::*,
+  world::{ChunkCoord, World},
+  Result,
+};
+
+/// Events emitted by the `ChunkStreamer` on each update.
+#[derive(Debug)]
+pub enum StreamEvent<M> {
+  /// The chunk was loaded (or generated) and inserted into the world.
+  ChunkLoaded(ChunkCoord),
+  /// The chunk was baked, the mesh is `None` when the chunk has no surface.
+  ChunkBaked(ChunkCoord, Option<M>),
+  /// The chunk was removed from the world.
+  ChunkUnloaded(ChunkCoord),
+}
+
+/// Options to customize the `ChunkStreamer` behaviour
+#[derive(Debug, Clone)]
+pub struct StreamerOptions {
+  /// Chunks which center is within this distance of any observer are loaded.
+  pub load_radius: f32,
+  /// Chunks which center is further than this distance from every observer are unloaded,
+  /// should be greater than `load_radius` to avoid loading and unloading the same chunk.
+  pub unload_radius: f32,
+  /// Max number of chunks loaded on each update, the closest ones are loaded first.
+  pub max_loads_per_update: usize,
+  /// Max number of chunks baked on each update, the closest ones are baked first.
+  pub max_bakes_per_update: usize,
+}
+
+impl Default for StreamerOptions {
+  fn default() -> Self {
+    Self {
+      load_radius: 64.,
+      unload_radius: 80.,
+      max_loads_per_update: 8,
+      max_bakes_per_update: 8,
+    }
+  }
+}
+
+/// Decides which chunks of a `World` need to be loaded, baked and unloaded based on the
+/// position of one or more observers.
+///
+/// A chunk is only baked once all its 26 neighbors that are within the load radius are loaded, so
+/// the baker can use their border samples, and it is baked again when one of those neighbors is
+/// loaded or unloaded later.
+#[derive(Debug, Clone)]
+pub struct ChunkStreamer {
+  options: StreamerOptions,
+  baked: HashSet<ChunkCoord>,
+}
+
+impl ChunkStreamer {
+  pub fn new(options: StreamerOptions) -> Self {
+    Self {
+      options,
+      baked: HashSet::new(),
+    }
+  }
+
+  pub fn options(&self) -> &StreamerOptions {
+    &self.options
+  }
+
+  /// Returns `true` if the chunk was baked and no changes were made since.
+  pub fn is_baked(&self, coord: ChunkCoord) -> bool {
+    self.baked.contains(&coord)
+  }
+
+  /// Marks the chunk to be baked again on the next updates, i.e. after editing it.
+  pub fn mark_dirty(&mut self, coord: ChunkCoord) {
+    self.baked.remove(&coord);
+  }
+
+  /// Runs one step of the streaming.
+  ///
+  /// `load` is called with every chunk that needs to be loaded, returning `None` skips it until
+  /// the next update. `bake` is called with the world and the coordinate of every chunk that
+  /// needs to be baked, it's where the neighbor data should be updated. The observers with a
+  /// `NaN` or infinite coordinate are ignored.
+  pub fn update<C, M, L, B>(
+    &mut self,
+    world: &mut World<C>,
+    observers: &[[f32; 3]],
+    mut load: L,
+    mut bake: B,
+  ) -> Result<Vec<StreamEvent<M>>>
+  where
+    C: Boxify,
+    L: FnMut(ChunkCoord, [f32; 3]) -> Result<Option<C>>,
+    B: FnMut(&mut World<C>, ChunkCoord) -> Result<Option<M>>,
+  {
+    let mut events = vec![];
+    let observers: Vec<_> = observers
+      .iter()
+      .filter(|observer| observer.iter().all(|value| value.is_finite()))
+      .copied()
+      .collect();
+    let observers = &observers[..];
+
+    // Unload
+    let unload: Vec<_> = world
+      .coords()
+      .filter(|&&coord| self.distance(world, coord, observers) > self.options.unload_radius)
+      .copied()
+      .collect();
+
+    for coord in unload {
+      world.remove_chunk(coord);
+      self.baked.remove(&coord);
+
+      // The neighbors need to be baked again without the border samples
+      for neighbor in neighbors(coord) {
+        self.baked.remove(&neighbor);
+      }
+
+      events.push(StreamEvent::ChunkUnloaded(coord));
+    }
+
+    // Load
+    let wanted = self.wanted(world, observers);
+    let mut missing: Vec<_> = wanted
+      .iter()
+      .filter(|&&coord| !world.contains_chunk(coord))
+      .map(|&coord| (self.distance(world, coord, observers), coord))
+      .collect();
+    missing.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    for (_, coord) in missing.into_iter().take(self.options.max_loads_per_update) {
+      if let Some(chunk) = load(coord, world.chunk_position(coord))? {
+        world.insert_chunk(coord, chunk);
+
+        // The neighbors need to be baked again with the new border samples
+        for neighbor in neighbors(coord) {
+          self.baked.remove(&neighbor);
+        }
+
+        events.push(StreamEvent::ChunkLoaded(coord));
+      }
+    }
+
+    // Bake
+    let mut pending: Vec<_> = world
+      .coords()
+      .filter(|&&coord| !self.baked.contains(&coord))
+      .filter(|&&coord| {
+        neighbors(coord)
+          .iter()
+          .all(|neighbor| world.contains_chunk(*neighbor) || !wanted.contains(neighbor))
+      })
+      .map(|&coord| (self.distance(world, coord, observers), coord))
+      .collect();
+    pending.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    for (_, coord) in pending.into_iter().take(self.options.max_bakes_per_update) {
+      let mesh = bake(world, coord)?;
+      self.baked.insert(coord);
+      events.push(StreamEvent::ChunkBaked(coord, mesh));
+    }
+
+    Ok(events)
+  }
+
+  // Chunk coordinates within the load radius of any observer
+  fn wanted<C>(&self, world: &World<C>, observers: &[[f32; 3]]) -> HashSet<ChunkCoord> {
+    let radius = self.options.load_radius;
+    let mut result = HashSet::new();
+
+    for observer in observers.iter() {
+      let min = world.chunk_coord_at([
+        observer[0] - radius,
+        observer[1] - radius,
+        observer[2] - radius,
+      ]);
+      let max = world.chunk_coord_at([
+        observer[0] + radius,
+        observer[1] + radius,
+        observer[2] + radius,
+      ]);
+
+      for x in min[0]..=max[0] {
+        for y in min[1]..=max[1] {
+          for z in min[2]..=max[2] {
+            let coord = [x, y, z];
+            if distance(center(world, coord), *observer) <= radius {
+              result.insert(coord);
+            }
+          }
+        }
+      }
+    }
+
+    result
+  }
+
+  // Distance from the chunk center to the closest observer
+  fn distance<C>(&self, world: &World<C>, coord: ChunkCoord, observers: &[[f32; 3]]) -> f32 {
+    let center = center(world, coord);
+    observers
+      .iter()
+      .map(|observer| distance(center, *observer))
+      .fold(f32::INFINITY, f32::min)
+  }
+}
+
+impl Default for ChunkStreamer {
+  fn default() -> Self {
+    Self::new(StreamerOptions::default())
+  }
+}
+
+fn center<C>(world: &World<C>, coord: ChunkCoord) -> [f32; 3] {
+  let position = world.chunk_position(coord);
+  let size = world.chunk_size();
+  [
+    position[0] + size[0] as f32 / 2.0,
+    position[1] + size[1] as f32 / 2.0,
+    position[2] + size[2] as f32 / 2.0,
+  ]
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+  ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+// The 26 neighbors (the ones sharing a face, edge or corner)
+fn neighbors(coord: ChunkCoord) -> Vec<ChunkCoord> {
+  let mut result = vec![];
+  for x in -1..=1 {
+    for y in -1..=1 {
+      for z in -1..=1 {
+        if [x, y, z] != [0, 0, 0] {
+          result.push([coord[0] + x, coord[1] + y, coord[2] + z]);
+        }
+      }
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::chunk::Chunk;
+
+  fn update(
+    streamer: &mut ChunkStreamer,
+    world: &mut World<Chunk>,
+    observer: [f32; 3],
+  ) -> Vec<StreamEvent<()>> {
+    streamer
+      .update(
+        world,
+        &[observer],
+        |_, position| Ok(Some(Chunk::new(position, 16, 16, 16))),
+        |_, _| Ok(Some(())),
+      )
+      .unwrap()
+  }
+
+  #[test]
+  fn check_load_bake_unload() {
+    let mut world = World::<Chunk>::new([16, 16, 16]);
+    let mut streamer = ChunkStreamer::new(StreamerOptions {
+      load_radius: 16.,
+      unload_radius: 24.,
+      max_loads_per_update: 100,
+      max_bakes_per_update: 100,
+    });
+
+    let events = update(&mut streamer, &mut world, [0.0, 0.0, 0.0]);
+    let loaded = events
+      .iter()
+      .filter(|event| matches!(event, StreamEvent::ChunkLoaded(_)))
+      .count();
+    let baked = events
+      .iter()
+      .filter(|event| matches!(event, StreamEvent::ChunkBaked(_, _)))
+      .count();
+
+    // The 8 chunks around the origin
+    assert_eq!(loaded, 8);
+    assert_eq!(baked, 8);
+    assert_eq!(world.len(), 8);
+    assert!(streamer.is_baked([-1, -1, -1]));
+
+    // Nothing changes if the observer doesn't move
+    assert!(update(&mut streamer, &mut world, [0.0, 0.0, 0.0]).is_empty());
+
+    let events = update(&mut streamer, &mut world, [200.0, 0.0, 0.0]);
+    let unloaded = events
+      .iter()
+      .filter(|event| matches!(event, StreamEvent::ChunkUnloaded(_)))
+      .count();
+
+    assert_eq!(unloaded, 8);
+    assert!(!world.contains_chunk([0, 0, 0]));
+    assert!(!streamer.is_baked([0, 0, 0]));
+  }
+
+  #[test]
+  fn check_rebake_neighbors() {
+    let mut world = World::<Chunk>::new([16, 16, 16]);
+    let mut streamer = ChunkStreamer::new(StreamerOptions {
+      load_radius: 8.,
+      unload_radius: 100.,
+      max_loads_per_update: 100,
+      max_bakes_per_update: 100,
+    });
+
+    update(&mut streamer, &mut world, [8.0, 8.0, 8.0]);
+    assert!(streamer.is_baked([0, 0, 0]));
+
+    // Moving along +x loads [1, 0, 0], which makes [0, 0, 0] stale
+    let events = update(&mut streamer, &mut world, [24.0, 8.0, 8.0]);
+    assert!(events
+      .iter()
+      .any(|event| matches!(event, StreamEvent::ChunkBaked([0, 0, 0], _))));
+    // And so does loading [-1, 0, 0]
+    let events = update(&mut streamer, &mut world, [-8.0, 8.0, 8.0]);
+    assert!(events
+      .iter()
+      .any(|event| matches!(event, StreamEvent::ChunkLoaded([-1, 0, 0]))));
+    assert!(events
+      .iter()
+      .any(|event| matches!(event, StreamEvent::ChunkBaked([0, 0, 0], _))));
+  }
+
+  #[test]
+  fn check_rebake_unloaded_neighbors() {
+    let mut world = World::<Chunk>::new([16, 16, 16]);
+    let mut streamer = ChunkStreamer::new(StreamerOptions {
+      load_radius: 8.,
+      unload_radius: 20.,
+      max_loads_per_update: 100,
+      max_bakes_per_update: 100,
+    });
+
+    update(&mut streamer, &mut world, [8.0, 8.0, 8.0]);
+    update(&mut streamer, &mut world, [24.0, 8.0, 8.0]);
+    assert!(streamer.is_baked([1, 0, 0]));
+
+    // Only [0, 0, 0] is unloaded, [1, 0, 0] lost the neighbor it was baked with
+    let events = update(&mut streamer, &mut world, [29.0, 8.0, 8.0]);
+    assert!(events
+      .iter()
+      .any(|event| matches!(event, StreamEvent::ChunkUnloaded([0, 0, 0]))));
+    assert!(!events
+      .iter()
+      .any(|event| matches!(event, StreamEvent::ChunkLoaded(_))));
+    assert!(events
+      .iter()
+      .any(|event| matches!(event, StreamEvent::ChunkBaked([1, 0, 0], _))));
+  }
+
+  #[test]
+  fn check_nan_observer() {
+    let mut world = World::<Chunk>::new([16, 16, 16]);
+    let mut streamer = ChunkStreamer::default();
+
+    streamer
+      .update(
+        &mut world,
+        &[
+          [f32::NAN, 0.0, 0.0],
+          [0.0, f32::INFINITY, 0.0],
+          [0.0, 0.0, 0.0],
+        ],
+        |_, position| Ok(Some(Chunk::new(position, 16, 16, 16))),
+        |_, _| Ok(Some(())),
+      )
+      .unwrap();
+
+    // The closest chunks to the valid observer
+    assert_eq!(world.len(), 8);
+    assert!(world.contains_chunk([-1, -1, -1]));
+
+    // Without a valid observer everything is unloaded
+    let events = update(&mut streamer, &mut world, [f32::NAN; 3]);
+    assert_eq!(events.len(), 8);
+    assert!(world.is_empty());
+  }
+}
@@ -121,13 +121,31 @@ where
     )
   }
 
-  pub fn set_at_index(&mut self, index: u8, pixels: Vec<[u8; 4]>) {
+  /// Color of every tile, taken from its first pixel. Meant for the palette atlases created by
+  /// the format readers, so the meshes can be colored without using the texture.
+  pub fn palette(&self) -> Vec<[u8; 4]> {
+    (0..=u8::MAX)
+      .map(|index| {
+        let (x, y) = self.tile_origin(index);
+        self.texture.get_pixel(x, y).unwrap_or_default()
+      })
+      .collect()
+  }
+
+  // Texture xy of the first pixel of the tile
+  fn tile_origin(&self, index: u8) -> (u32, u32) {
     // Get UV position on the tex for this index
     let uv = self.get_uv(index).0;
 
+    (
+      (uv[0] * self.texture.width() as f32).floor() as u32, // Convert uv to tex xy
+      (uv[1] * self.texture.height() as f32).floor() as u32, // Convert uv to tex xy
+    )
+  }
+
+  pub fn set_at_index(&mut self, index: u8, pixels: Vec<[u8; 4]>) {
     // Convert uv to tex xy for the origin of this blit
-    let x_o = (uv[0] * self.texture.width() as f32).floor() as u32; // Convert uv to tex xy
-    let y_o = (uv[1] * self.texture.height() as f32).floor() as u32; // Convert uv to tex xy
+    let (x_o, y_o) = self.tile_origin(index);
 
     // TODO: We don't need to invert the y?
     // let y_o = ((1.0 - COL_SIZE - uv.1) * self.texture.height() as f32).floor() as u32; // Convert uv to tex xy and invert the Y axis, so it starts from top
@@ -220,7 +238,7 @@ impl Texturify2d for Texture2d {
 
   fn get_pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
     if x < self.width && y < self.height {
-      let index = (x + 4 + self.width * y + 4) as usize;
+      let index = (x * 4 + self.width * y * 4) as usize;
       Some([
         self.data[index],
         self.data[index + 1],
@@ -639,4 +657,20 @@ mod test {
     let tex_data = tex.get_data();
     assert!(!tex_data.iter().any(|v| *v != 255));
   }
+
+  #[test]
+  fn test_palette() {
+    let mut atlas = TextureAtlas2d::<Texture2d>::new(1);
+    atlas.fill_at_index(0, [255, 0, 0, 255]);
+    atlas.fill_at_index(17, [0, 255, 0, 255]);
+    atlas.fill_at_index(255, [0, 0, 255, 128]);
+
+    let palette = atlas.palette();
+
+    assert_eq!(palette.len(), 256);
+    assert_eq!(palette[0], [255, 0, 0, 255]);
+    assert_eq!(palette[1], [0, 0, 0, 0]);
+    assert_eq!(palette[17], [0, 255, 0, 255]);
+    assert_eq!(palette[255], [0, 0, 255, 128]);
+  }
 }
@@ -0,0 +1,249 @@
+use glam::Vec3;
+use std::collections::{BTreeMap, HashSet};
+
+const EPSILON: f32 = 1e-4;
+
+// The first faces are shared with regular cells
+const SHARED_FACES: usize = 5;
+
+// Faces of the transition cell as cycles of samples
+const FACES: [&[usize]; 9] = [
+  // High resolution face
+  &[0, 1, 4, 3],
+  &[1, 2, 5, 4],
+  &[3, 4, 7, 6],
+  &[4, 5, 8, 7],
+  // Low resolution face
+  &[9, 10, 12, 11],
+  // Sides
+  &[0, 1, 2, 10, 9],
+  &[2, 5, 8, 12, 10],
+  &[8, 7, 6, 11, 12],
+  &[6, 3, 0, 9, 11],
+];
+
+/// High resolution samples that are copied into the low resolution face.
+pub const LOW_RESOLUTION: [usize; 4] = [0, 2, 6, 8];
+
+type Edge = (usize, usize);
+
+/// Transition cell between the face of a low resolution cell and the 4 faces of the high
+/// resolution cells of the neighbor chunk, it fills the same slab the Transvoxel algorithm does.
+///
+/// The samples `0..9` are the high resolution face (`u + v * 3`), the samples `9..13` are the
+/// `LOW_RESOLUTION` samples moved inside the chunk where the face of the low resolution cell is.
+///
+/// Instead of the Transvoxel tables, the surface is traced over the faces of the cell: the
+/// segments of every face are linked into closed loops that are fanned around their center. The
+/// Transvoxel tables are made for the Transvoxel regular cells, which resolve the ambiguous faces
+/// on their own way, while the bakers use the marching cubes tables. Tracing the faces lets the
+/// transition cell split the ambiguous faces the same way those tables do (keeping the air
+/// corners apart), so the seams with the regular cells on both sides match by construction. It
+/// also works with the extra vertices of the modified table, the segments on the faces shared
+/// with the regular cells get a vertex in the middle with `split_shared_faces`.
+#[derive(Debug)]
+pub struct TransitionCell {
+  pub value: [f32; 13],
+  pub point: [Vec3; 13],
+}
+
+impl TransitionCell {
+  /// Returns the triangles, their uvs and the solid sample used for the atlas.
+  #[allow(clippy::type_complexity)]
+  pub fn polygonize(
+    &self,
+    isolevel: f32,
+    split_shared_faces: bool,
+  ) -> Vec<([[f32; 3]; 3], [[f32; 2]; 3], usize)> {
+    let air = |sample: usize| self.value[sample] < isolevel;
+
+    // Each edge crossing the surface is linked to other two, one for each face that contains it
+    let mut links: BTreeMap<Edge, Vec<(Edge, usize)>> = BTreeMap::new();
+    for (face_index, face) in FACES.iter().enumerate() {
+      let edge = |index: usize| {
+        let (a, b) = (face[index], face[(index + 1) % face.len()]);
+        if a < b {
+          (a, b)
+        } else {
+          (b, a)
+        }
+      };
+
+      let crossings: Vec<usize> = (0..face.len())
+        .filter(|&index| air(face[index]) != air(face[(index + 1) % face.len()]))
+        .collect();
+
+      let pairs: &[(usize, usize)] = match crossings.len() {
+        0 => &[],
+        2 => &[(0, 1)],
+        // The ambiguous squares and pentagons, the samples between the first two crossings are
+        // cut off when they are air
+        4 if air(face[(crossings[0] + 1) % face.len()]) => &[(0, 1), (2, 3)],
+        4 => &[(1, 2), (3, 0)],
+        _ => unreachable!(),
+      };
+
+      for (a, b) in pairs.iter() {
+        let (a, b) = (edge(crossings[*a]), edge(crossings[*b]));
+        links.entry(a).or_default().push((b, face_index));
+        links.entry(b).or_default().push((a, face_index));
+      }
+    }
+
+    let mut triangles = vec![];
+    let mut visited = HashSet::new();
+
+    for &start in links.keys() {
+      if visited.contains(&start) {
+        continue;
+      }
+
+      // Walk the closed loop of edges, keeping the face that links each one with the next
+      let mut edges = vec![start];
+      visited.insert(start);
+      let (mut current, face) = links[&start][0];
+      let (mut previous, mut faces) = (start, vec![face]);
+      while current != start {
+        visited.insert(current);
+        edges.push(current);
+
+        let next = if links[&current][0].0 == previous {
+          links[&current][1]
+        } else {
+          links[&current][0]
+        };
+        faces.push(next.1);
+        previous = current;
+        current = next.0;
+      }
+
+      triangles.extend(self.triangulate(&edges, &faces, isolevel, split_shared_faces));
+    }
+
+    triangles
+  }
+
+  #[allow(clippy::type_complexity)]
+  fn triangulate(
+    &self,
+    edges: &[Edge],
+    faces: &[usize],
+    isolevel: f32,
+    split_shared_faces: bool,
+  ) -> Vec<([[f32; 3]; 3], [[f32; 2]; 3], usize)> {
+    let crossings: Vec<Vec3> = edges
+      .iter()
+      .map(|&(a, b)| {
+        interpolate(
+          self.point[a],
+          self.value[a],
+          self.point[b],
+          self.value[b],
+          isolevel,
+        )
+        .into()
+      })
+      .collect();
+
+    // From the solid to the air sample of each edge, the normals should follow it
+    let mut points = vec![];
+    let mut directions = vec![];
+    for (index, &(a, b)) in edges.iter().enumerate() {
+      let direction = self.point[b] - self.point[a];
+      points.push(crossings[index]);
+      directions.push(if self.value[a] < isolevel {
+        -direction
+      } else {
+        direction
+      });
+
+      if split_shared_faces && faces[index] < SHARED_FACES {
+        let next = crossings[(index + 1) % crossings.len()];
+        points.push((crossings[index] + next) / 2.0);
+        directions.push(Vec3::zero());
+      }
+    }
+
+    let count = points.len();
+    let center = points.iter().fold(Vec3::zero(), |sum, point| sum + *point) / count as f32;
+
+    // Triangles don't need the center, bigger loops are fanned around it
+    let mut faces: Vec<[Vec3; 3]> = if count == 3 {
+      vec![[points[0], points[1], points[2]]]
+    } else {
+      (0..count)
+        .map(|index| [center, points[index], points[(index + 1) % count]])
+        .collect()
+    };
+
+    let orientation: f32 = (0..count)
+      .map(|index| {
+        let next = (index + 1) % count;
+        (points[index] - center)
+          .cross(points[next] - center)
+          .dot(directions[index] + directions[next])
+      })
+      .sum();
+    if orientation < 0.0 {
+      for face in faces.iter_mut() {
+        face.swap(1, 2);
+      }
+    }
+
+    // The atlas comes from the solid sample of the first edge
+    let (a, b) = edges[0];
+    let solid = if self.value[a] < isolevel { b } else { a };
+
+    let u_axis = self.point[2] - self.point[0];
+    let v_axis = self.point[6] - self.point[0];
+    let uv = |point: Vec3| {
+      let relative = point - self.point[0];
+      [
+        (relative.dot(u_axis) / u_axis.length_squared()).clamp(0.0, 1.0),
+        (relative.dot(v_axis) / v_axis.length_squared()).clamp(0.0, 1.0),
+      ]
+    };
+
+    faces
+      .iter()
+      .map(|face| {
+        (
+          [face[0].into(), face[1].into(), face[2].into()],
+          [uv(face[0]), uv(face[1]), uv(face[2])],
+          solid,
+        )
+      })
+      .collect()
+  }
+}
+
+/// Point of the edge between two samples where the surface crosses. The regular cells of the
+/// bakers use it too, so both sides of the transition compute exactly the same vertices.
+pub fn interpolate(
+  point1: Vec3,
+  value1: f32,
+  point2: Vec3,
+  value2: f32,
+  isolevel: f32,
+) -> [f32; 3] {
+  let (point1, value1, point2, value2) = if value2 < value1 {
+    (point2, value2, point1, value1)
+  } else {
+    (point1, value1, point2, value2)
+  };
+
+  if (point1 - point2).length() > EPSILON {
+    if (isolevel - value1).abs() <= EPSILON {
+      point1.into()
+    } else if (isolevel - value2).abs() <= EPSILON {
+      point2.into()
+    } else if isolevel < value1 || isolevel > value2 {
+      unreachable!();
+    } else {
+      let weight = (isolevel - value1) / (value2 - value1);
+      (point1 * weight + point2 * (1. - weight)).into()
+    }
+  } else {
+    point1.into()
+  }
+}
@@ -0,0 +1,293 @@
+use std::collections::{hash_map, HashMap};
+
+use crate::{
+  atlas::{Atlasify, AtlasifyMut},
+  boxify::*,
+  chunk::{Apronify, Chunkify, ChunkifyMut},
+};
+
+/// Integer coordinate of a chunk inside the `World`, a chunk at `[1, 0, 0]` starts at
+/// `chunk_size[0]` voxels on the x axis.
+pub type ChunkCoord = [i32; 3];
+
+/// Container of chunks keyed by their chunk coordinate, it translates world voxel coordinates
+/// into chunk and local coordinates so the terrain can be read and edited as a whole.
+#[derive(Debug, Clone)]
+pub struct World<C> {
+  chunk_size: [u16; 3],
+  chunks: HashMap<ChunkCoord, C>,
+}
+
+impl<C> World<C> {
+  pub fn new(chunk_size: [u16; 3]) -> Self {
+    assert!(chunk_size.iter().all(|&size| size > 0));
+
+    Self {
+      chunk_size,
+      chunks: HashMap::new(),
+    }
+  }
+
+  pub fn chunk_size(&self) -> [u16; 3] {
+    self.chunk_size
+  }
+
+  /// Converts a world voxel coordinate into the coordinate of the chunk that contains it and the
+  /// local coordinate inside that chunk.
+  pub fn to_chunk_coord(&self, [x, y, z]: [i32; 3]) -> (ChunkCoord, [usize; 3]) {
+    let [width, height, depth] = self.chunk_size;
+    let (width, height, depth) = (width as i32, height as i32, depth as i32);
+
+    (
+      [
+        x.div_euclid(width),
+        y.div_euclid(height),
+        z.div_euclid(depth),
+      ],
+      [
+        x.rem_euclid(width) as usize,
+        y.rem_euclid(height) as usize,
+        z.rem_euclid(depth) as usize,
+      ],
+    )
+  }
+
+  /// Returns the coordinate of the chunk that contains the world `position`.
+  pub fn chunk_coord_at(&self, position: [f32; 3]) -> ChunkCoord {
+    let mut coord = [0; 3];
+    for (axis, coord) in coord.iter_mut().enumerate() {
+      *coord = (position[axis] / self.chunk_size[axis] as f32).floor() as i32;
+    }
+    coord
+  }
+
+  /// Returns the world position of the chunk origin.
+  pub fn chunk_position(&self, coord: ChunkCoord) -> [f32; 3] {
+    [
+      coord[0] as f32 * self.chunk_size[0] as f32,
+      coord[1] as f32 * self.chunk_size[1] as f32,
+      coord[2] as f32 * self.chunk_size[2] as f32,
+    ]
+  }
+
+  pub fn get_chunk(&self, coord: ChunkCoord) -> Option<&C> {
+    self.chunks.get(&coord)
+  }
+
+  pub fn get_chunk_mut(&mut self, coord: ChunkCoord) -> Option<&mut C> {
+    self.chunks.get_mut(&coord)
+  }
+
+  pub fn contains_chunk(&self, coord: ChunkCoord) -> bool {
+    self.chunks.contains_key(&coord)
+  }
+
+  /// Inserts the chunk at `coord`, returning the chunk previously stored there.
+  pub fn insert_chunk(&mut self, coord: ChunkCoord, chunk: C) -> Option<C> {
+    self.chunks.insert(coord, chunk)
+  }
+
+  pub fn remove_chunk(&mut self, coord: ChunkCoord) -> Option<C> {
+    self.chunks.remove(&coord)
+  }
+
+  pub fn chunks(&self) -> hash_map::Iter<'_, ChunkCoord, C> {
+    self.chunks.iter()
+  }
+
+  pub fn chunks_mut(&mut self) -> hash_map::IterMut<'_, ChunkCoord, C> {
+    self.chunks.iter_mut()
+  }
+
+  pub fn coords(&self) -> hash_map::Keys<'_, ChunkCoord, C> {
+    self.chunks.keys()
+  }
+
+  pub fn len(&self) -> usize {
+    self.chunks.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.chunks.is_empty()
+  }
+}
+
+impl<C> World<C>
+where
+  C: Boxify,
+{
+  /// Inserts the chunk at the coordinate derived from its position, returning the chunk
+  /// previously stored there.
+  pub fn insert(&mut self, chunk: C) -> Option<C> {
+    let coord = self.chunk_coord_at(chunk.position());
+    self.insert_chunk(coord, chunk)
+  }
+
+  /// Returns the chunk at `coord`, creating an empty one if it doesn't exist.
+  pub fn get_or_create_chunk_mut(&mut self, coord: ChunkCoord) -> &mut C {
+    let position = self.chunk_position(coord);
+    let [width, height, depth] = self.chunk_size;
+
+    self
+      .chunks
+      .entry(coord)
+      .or_insert_with(|| C::new(position, width, height, depth))
+  }
+}
+
+impl<C> World<C>
+where
+  C: Chunkify<f32>,
+{
+  /// Returns the value at the world voxel coordinate, missing chunks are treated as air.
+  pub fn get(&self, x: i32, y: i32, z: i32) -> f32 {
+    let (coord, [x, y, z]) = self.to_chunk_coord([x, y, z]);
+    self
+      .get_chunk(coord)
+      .map(|chunk| chunk.get(x, y, z))
+      .unwrap_or(-1.)
+  }
+
+  pub fn is_air(&self, x: i32, y: i32, z: i32, isovalue: f32) -> bool {
+    let (coord, [x, y, z]) = self.to_chunk_coord([x, y, z]);
+    self
+      .get_chunk(coord)
+      .map(|chunk| chunk.is_air(x, y, z, isovalue))
+      .unwrap_or(true)
+  }
+}
+
+impl<C> World<C>
+where
+  C: ChunkifyMut<f32> + Boxify,
+{
+  /// Sets the value at the world voxel coordinate, creating the chunk if needed.
+  pub fn set(&mut self, x: i32, y: i32, z: i32, value: f32) {
+    let (coord, [x, y, z]) = self.to_chunk_coord([x, y, z]);
+    self.get_or_create_chunk_mut(coord).set(x, y, z, value);
+  }
+}
+
+impl<C> World<C>
+where
+  C: Atlasify<u8>,
+{
+  pub fn get_atlas(&self, x: i32, y: i32, z: i32) -> u8 {
+    let (coord, [x, y, z]) = self.to_chunk_coord([x, y, z]);
+    self
+      .get_chunk(coord)
+      .map(|chunk| chunk.get_atlas(x, y, z))
+      .unwrap_or(0)
+  }
+}
+
+impl<C> World<C>
+where
+  C: AtlasifyMut<u8> + Boxify,
+{
+  /// Sets the atlas at the world voxel coordinate, creating the chunk if needed.
+  pub fn set_atlas(&mut self, x: i32, y: i32, z: i32, value: u8) {
+    let (coord, [x, y, z]) = self.to_chunk_coord([x, y, z]);
+    self
+      .get_or_create_chunk_mut(coord)
+      .set_atlas(x, y, z, value);
+  }
+}
+
+impl<C> World<C>
+where
+  C: Apronify + Chunkify<f32> + Atlasify<u8> + Sizable,
+{
+  /// Copies into the chunk at `coord` the border samples of its 26 loaded neighbors, see
+  /// `Apronify::update_apron`, and removes the ones of the neighbors that are not loaded anymore.
+  pub fn update_neighbor_data(&mut self, coord: ChunkCoord) {
+    if let Some(mut chunk) = self.chunks.remove(&coord) {
+      for x in -1..=1 {
+        for y in -1..=1 {
+          for z in -1..=1 {
+            let neighbor = [coord[0] + x, coord[1] + y, coord[2] + z];
+            match self.chunks.get(&neighbor) {
+              Some(neighbor) => chunk.update_apron([x, y, z], neighbor),
+              None => chunk.remove_apron([x, y, z]),
+            };
+          }
+        }
+      }
+
+      self.chunks.insert(coord, chunk);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::chunk::Chunk;
+
+  #[test]
+  fn check_chunk_coord() {
+    let world = World::<Chunk>::new([16, 16, 16]);
+
+    assert_eq!(world.to_chunk_coord([0, 15, 16]), ([0, 0, 1], [0, 15, 0]));
+    assert_eq!(
+      world.to_chunk_coord([-1, -16, -17]),
+      ([-1, -1, -2], [15, 0, 15])
+    );
+    assert_eq!(world.chunk_position([-1, 0, 2]), [-16.0, 0.0, 32.0]);
+    assert_eq!(world.chunk_coord_at([-16.0, 0.0, 33.0]), [-1, 0, 2]);
+  }
+
+  #[test]
+  #[allow(clippy::float_cmp)]
+  fn check_get_set() {
+    let mut world = World::<Chunk>::new([4, 4, 4]);
+
+    assert!(world.is_air(-1, 0, 5, 0.));
+    assert_eq!(world.get(-1, 0, 5), -1.);
+
+    world.set(-1, 0, 5, 1.);
+    world.set_atlas(-1, 0, 5, 3);
+
+    assert_eq!(world.len(), 1);
+    assert!(!world.is_air(-1, 0, 5, 0.));
+    assert_eq!(world.get(-1, 0, 5), 1.);
+    assert_eq!(world.get_atlas(-1, 0, 5), 3);
+
+    let chunk = world.get_chunk([-1, 0, 1]).unwrap();
+    assert_eq!(chunk.position(), [-4.0, 0.0, 4.0]);
+    assert_eq!(chunk.get(3, 0, 1), 1.);
+  }
+
+  #[test]
+  #[allow(clippy::float_cmp)]
+  fn check_update_neighbor_data() {
+    let mut world = World::<Chunk>::new([4, 4, 4]);
+    world.set(-1, 0, 0, 1.);
+    world.set(4, 3, 0, 2.);
+    world.set(-1, -1, -1, 3.);
+    world.set(0, 0, 0, 4.);
+
+    world.update_neighbor_data([0, 0, 0]);
+    let chunk = world.get_chunk([0, 0, 0]).unwrap();
+    let before = usize::MAX;
+    assert!(chunk.has_neighbor_data());
+    assert_eq!(chunk.get(before, 0, 0), 1.);
+    assert_eq!(chunk.get(4, 3, 0), 2.);
+    assert_eq!(chunk.get(before, before, before), 3.);
+    assert_eq!(chunk.get(0, 0, 0), 4.);
+
+    // The samples of the unloaded neighbors are air again
+    world.remove_chunk([-1, 0, 0]);
+    world.update_neighbor_data([0, 0, 0]);
+    let chunk = world.get_chunk([0, 0, 0]).unwrap();
+    assert!(!chunk.has_neighbor([-1, 0, 0]));
+    assert_eq!(chunk.get(before, 0, 0), -1.);
+    assert_eq!(chunk.get(4, 3, 0), 2.);
+
+    for coord in [[1, 0, 0], [-1, -1, -1]].iter() {
+      world.remove_chunk(*coord);
+    }
+    world.update_neighbor_data([0, 0, 0]);
+    assert!(!world.get_chunk([0, 0, 0]).unwrap().has_neighbor_data());
+  }
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, ensure};
+use gaiku_common::Result;
+use std::{collections::HashMap, convert::TryInto};
+
+type Dict<'a> = HashMap<&'a str, &'a [u8]>;
+
+/// Material of a goxel layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoxMaterial {
+  pub name: String,
+  pub color: [f32; 4],
+  pub metallic: f32,
+  pub roughness: f32,
+  pub emission: [f32; 3],
+}
+
+/// Chunks of a goxel layer, see `GoxReader::load_layers`.
+#[derive(Debug)]
+pub struct GoxLayer<C> {
+  pub name: String,
+  pub visible: bool,
+  pub material: Option<GoxMaterial>,
+  pub chunks: Vec<C>,
+}
+
+/// Properties of the layer that the gox crate doesn't read.
+#[derive(Clone, Debug)]
+pub(crate) struct LayerInfo {
+  pub name: String,
+  pub visible: bool,
+  pub material: Option<GoxMaterial>,
+}
+
+/// Properties of the layers in the order they are in the file.
+pub(crate) fn layer_infos(bytes: &[u8]) -> Result<Vec<LayerInfo>> {
+  let mut bytes = bytes.get(8..).ok_or_else(|| anyhow!("Not a gox file"))?;
+  let mut materials = vec![];
+  // With the index of their material
+  let mut layers = vec![];
+
+  while bytes.len() >= 8 {
+    let kind = &bytes[0..4];
+    let len: usize = i32::from_le_bytes(bytes[4..8].try_into()?).try_into()?;
+    ensure!(bytes.len() >= 12 + len, "Unexpected end of file");
+    let data = &bytes[8..8 + len];
+    bytes = &bytes[12 + len..];
+
+    match kind {
+      b"MATE" => {
+        let dict = dict(data)?;
+        materials.push(GoxMaterial {
+          name: string(&dict, "name"),
+          color: floats(&dict, "color")
+            .and_then(|values| values.try_into().ok())
+            .unwrap_or([1.0; 4]),
+          metallic: floats(&dict, "metallic").map_or(0.0, |values| values[0]),
+          roughness: floats(&dict, "roughness").map_or(1.0, |values| values[0]),
+          emission: floats(&dict, "emission")
+            .and_then(|values| values.try_into().ok())
+            .unwrap_or([0.0; 3]),
+        });
+      }
+      b"LAYR" => {
+        ensure!(len >= 4, "Unexpected end of file");
+        let blocks: usize = i32::from_le_bytes(data[0..4].try_into()?).try_into()?;
+        let dict = dict(
+          data
+            .get(blocks.saturating_mul(20).saturating_add(4)..)
+            .unwrap_or_default(),
+        )?;
+        let material = dict
+          .get("material")
+          .and_then(|value| Some(i32::from_le_bytes((*value).try_into().ok()?)));
+
+        layers.push((
+          LayerInfo {
+            name: string(&dict, "name"),
+            visible: !matches!(dict.get("visible"), Some([0])),
+            material: None,
+          },
+          material,
+        ));
+      }
+      _ => {}
+    }
+  }
+
+  Ok(
+    layers
+      .into_iter()
+      .map(|(mut layer, material)| {
+        layer.material = material
+          .and_then(|index| index.try_into().ok())
+          .and_then(|index: usize| materials.get(index).cloned());
+        layer
+      })
+      .collect(),
+  )
+}
+
+// The dictionaries end with an empty key or with the data
+fn dict(mut bytes: &[u8]) -> Result<Dict<'_>> {
+  let mut result = HashMap::new();
+
+  while bytes.len() >= 4 {
+    let key_len: usize = i32::from_le_bytes(bytes[0..4].try_into()?).try_into()?;
+    if key_len == 0 {
+      break;
+    }
+
+    ensure!(bytes.len() >= 8 + key_len, "Unexpected end of file");
+    let key = std::str::from_utf8(&bytes[4..4 + key_len])?;
+    let value_len: usize =
+      i32::from_le_bytes(bytes[4 + key_len..8 + key_len].try_into()?).try_into()?;
+    ensure!(
+      bytes.len() >= 8 + key_len + value_len,
+      "Unexpected end of file"
+    );
+
+    result.insert(key, &bytes[8 + key_len..8 + key_len + value_len]);
+    bytes = &bytes[8 + key_len + value_len..];
+  }
+
+  Ok(result)
+}
+
+fn string(dict: &Dict, key: &str) -> String {
+  dict
+    .get(key)
+    .map(|value| {
+      // Goxel saves the strings with their null terminator
+      let end = value
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(value.len());
+      String::from_utf8_lossy(&value[..end]).into_owned()
+    })
+    .unwrap_or_default()
+}
+
+fn floats(dict: &Dict, key: &str) -> Option<Vec<f32>> {
+  let value = dict.get(key)?;
+  if value.is_empty() || value.len() % 4 != 0 {
+    return None;
+  }
+
+  value
+    .chunks(4)
+    .map(|bytes| Some(f32::from_le_bytes(bytes.try_into().ok()?)))
+    .collect()
+}
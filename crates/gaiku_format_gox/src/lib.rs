@@ -1,156 +1,585 @@
-use gaiku_common::{prelude::*, Result};
-use std::convert::TryInto;
+use gaiku_common::{prelude::*, quantization::quantize, Result};
+use std::{
+  collections::{BTreeMap, HashMap},
+  convert::TryInto,
+};
 
+use anyhow::{bail, ensure};
 use gox::{Block, Data, Gox, Only};
 
-/// Converts a `gox` file to 3d chunk data.
+mod layer;
+mod writer;
+
+pub use self::layer::{GoxLayer, GoxMaterial};
+use self::{
+  layer::{layer_infos, LayerInfo},
+  writer::Image,
+};
+
+type Bounds = ([i32; 3], [i32; 3]);
+
+/// Converts a `gox` file to 3d chunk data, and back.
+///
+/// `load` merges the visible layers, `load_layers` returns each layer (hidden included) on its own.
 pub struct GoxReader;
 
-// TODO: The generated data appears rotated, need to rotate from back to bottom
+/// How the colors of a loaded file fit in the atlas.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LoadReport {
+  /// Different colors of the voxels of the file.
+  pub colors: usize,
+  /// Colors of the atlas, less than `colors` when they were quantized.
+  pub palette: usize,
+  /// Error of the quantization, see `gaiku_common::quantization::Quantization::error`. `0` when the colors fit.
+  pub quantization_error: f32,
+}
+
+impl GoxReader {
+  /// Like `load_with_options`, with the report of how the colors were reduced to the atlas.
+  #[allow(clippy::type_complexity)]
+  pub fn load_with_report<C, T>(
+    bytes: Vec<u8>,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>, LoadReport)>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+    T: Texturify2d,
+  {
+    let (layers, atlas, report) = parse::<T>(bytes, options)?;
+    let mut bounds: Option<Bounds> = None;
+    let mut voxels = vec![];
+
+    // The hidden layers are usually helpers, they aren't part of the model
+    for layer in layers.into_iter().filter(|layer| layer.info.visible) {
+      if let Some((min, max)) = layer.bounds {
+        bounds = Some(match bounds {
+          Some(bounds) => merge_bounds(bounds, min, max),
+          None => (min, max),
+        });
+      }
+      voxels.extend(layer.voxels);
+    }
+
+    Ok((chunks(bounds, &voxels, options)?, atlas, report))
+  }
+
+  /// Loads each layer as a separate group of chunks, all of them share the atlas. The report is
+  /// the one of `load_with_report`.
+  #[allow(clippy::type_complexity)]
+  pub fn load_layers<C, T>(
+    bytes: Vec<u8>,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<GoxLayer<C>>, Option<TextureAtlas2d<T>>, LoadReport)>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+    T: Texturify2d,
+  {
+    let (layers, atlas, report) = parse::<T>(bytes, options)?;
+
+    let layers = layers
+      .into_iter()
+      .map(|layer| {
+        Ok(GoxLayer {
+          chunks: chunks(layer.bounds, &layer.voxels, options)?,
+          name: layer.info.name,
+          visible: layer.info.visible,
+          material: layer.info.material,
+        })
+      })
+      .collect::<Result<_>>()?;
+
+    Ok((layers, atlas, report))
+  }
+}
+
 impl FileFormat for GoxReader {
   type Value = f32;
   type AtlasValue = u8;
 
-  fn load<C, T>(bytes: Vec<u8>) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  fn load_with_options<C, T>(
+    bytes: Vec<u8>,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
   where
     C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
     T: Texturify2d,
   {
-    type Coord = usize;
-    let gox = Gox::from_bytes(bytes, vec![Only::Layers, Only::Blocks]);
-    let mut colors: Vec<[u8; 4]> = Vec::with_capacity(255);
-    let mut result = vec![];
-    let mut block_data: Vec<&Block> = vec![];
-
-    for data in gox.data.iter() {
-      if let Data::Blocks(data) = &data {
-        block_data.push(data);
-      }
+    let (chunks, atlas, _) = Self::load_with_report(bytes, options)?;
+    Ok((chunks, atlas))
+  }
+}
+
+// Voxels of a layer in the axes of the chunks, with their atlas index
+struct LayerVoxels {
+  info: LayerInfo,
+  // Of the blocks of the layer
+  bounds: Option<Bounds>,
+  voxels: Vec<([i32; 3], u8)>,
+}
+
+fn parse<T>(
+  bytes: Vec<u8>,
+  options: &FileFormatOptions,
+) -> Result<(Vec<LayerVoxels>, Option<TextureAtlas2d<T>>, LoadReport)>
+where
+  T: Texturify2d,
+{
+  let infos = layer_infos(&bytes)?;
+  let gox = Gox::from_bytes(bytes, vec![Only::Layers, Only::Blocks]);
+  let convert = |position| options.coordinate_system.from_z_up_right_handed(position);
+  // With the number of voxels that use them
+  let mut colors: Vec<([u8; 4], usize)> = Vec::with_capacity(255);
+  let mut color_indexes: HashMap<[u8; 4], usize> = HashMap::new();
+  // The voxels use the index of their color until the palette is known
+  let mut layer_voxels = vec![];
+  let mut block_data: Vec<&Block> = vec![];
+
+  for data in gox.data.iter() {
+    if let Data::Blocks(data) = &data {
+      block_data.push(data);
     }
+  }
+
+  for data in gox.data.iter() {
+    if let Data::Layers(layers, _bounds) = &data {
+      ensure!(layers.len() == infos.len(), "Invalid layers");
 
-    let mut starts = vec![];
-    for data in gox.data.iter() {
-      if let Data::Layers(layers, _bounds) = &data {
-        for layer in layers.iter() {
-          if !layer.blocks.is_empty() {
-            for data in layer.blocks.iter() {
-              starts.push([data.x, data.y, data.z]);
+      for (layer, info) in layers.iter().zip(infos.iter()) {
+        let mut bounds: Option<Bounds> = None;
+        let mut voxels = vec![];
+
+        for data in layer.blocks.iter() {
+          let origin = [data.x, data.y, data.z];
+          // The block corners are enough, the conversion only swaps and flips the axes
+          let (first, last) = (
+            convert(origin),
+            convert([origin[0] + 15, origin[1] + 15, origin[2] + 15]),
+          );
+          bounds = Some(match bounds {
+            Some(bounds) => merge_bounds(merge_bounds(bounds, first, first), last, last),
+            None => merge_bounds((first, first), last, last),
+          });
+
+          let block_colors = block_data[data.block_index];
+
+          for x in 0..16 {
+            for y in 0..16 {
+              for z in 0..16 {
+                if !block_colors.is_empty(x, y, z) {
+                  let color = block_colors.get_pixel(x, y, z);
+                  let index = *color_indexes.entry(color).or_insert_with(|| {
+                    colors.push((color, 0));
+                    colors.len() - 1
+                  });
+                  colors[index].1 += 1;
+
+                  voxels.push((
+                    convert([
+                      origin[0] + x as i32,
+                      origin[1] + y as i32,
+                      origin[2] + z as i32,
+                    ]),
+                    index,
+                  ));
+                }
+              }
             }
           }
         }
+
+        layer_voxels.push((info.clone(), bounds, voxels));
       }
     }
-    let init_coord = starts[0];
-
-    let min = [
-      starts
-        .iter()
-        .fold(init_coord[0], |acc, c| if c[0] < acc { c[0] } else { acc }),
-      starts
-        .iter()
-        .fold(init_coord[1], |acc, c| if c[1] < acc { c[1] } else { acc }),
-      starts
-        .iter()
-        .fold(init_coord[2], |acc, c| if c[2] < acc { c[2] } else { acc }),
-    ];
-    let max = [
-      starts.iter().fold(init_coord[0] + 16, |acc, c| {
-        if c[0] + 16 > acc {
-          c[0] + 16
-        } else {
-          acc
-        }
-      }),
-      starts.iter().fold(init_coord[1] + 16, |acc, c| {
-        if c[1] + 16 > acc {
-          c[1] + 16
-        } else {
-          acc
-        }
-      }),
-      starts.iter().fold(init_coord[2] + 16, |acc, c| {
-        if c[2] + 16 > acc {
-          c[2] + 16
-        } else {
-          acc
-        }
-      }),
+  }
+
+  let quantization = quantize(&colors, u8::MAX as usize + 1);
+  if colors.len() > quantization.palette.len() {
+    match options.palette_overflow {
+      PaletteOverflow::Error => bail!(
+        "The file has {} colors, more than the {} of the atlas",
+        colors.len(),
+        u8::MAX as usize + 1
+      ),
+      PaletteOverflow::Quantize { max_error } => ensure!(
+        quantization.error <= max_error,
+        "Reducing the {} colors of the file to the atlas has an error of {}, more than {}",
+        colors.len(),
+        quantization.error,
+        max_error
+      ),
+    }
+  }
+
+  let report = LoadReport {
+    colors: colors.len(),
+    palette: quantization.palette.len(),
+    quantization_error: quantization.error,
+  };
+  let result = layer_voxels
+    .into_iter()
+    .map(|(info, bounds, voxels)| LayerVoxels {
+      info,
+      bounds,
+      voxels: voxels
+        .into_iter()
+        .map(|(position, color)| (position, quantization.indexes[color] as u8))
+        .collect(),
+    })
+    .collect();
+
+  if !quantization.palette.is_empty() {
+    let mut atlas = TextureAtlas2d::new(1);
+
+    for (index, color) in quantization.palette.iter().enumerate() {
+      atlas.fill_at_index(index.try_into().unwrap(), *color);
+    }
+
+    Ok((result, Some(atlas), report))
+  } else {
+    Ok((result, None, report))
+  }
+}
+
+fn merge_bounds((mut min, mut max): Bounds, other_min: [i32; 3], other_max: [i32; 3]) -> Bounds {
+  for axis in 0..3 {
+    min[axis] = min[axis].min(other_min[axis]);
+    max[axis] = max[axis].max(other_max[axis]);
+  }
+  (min, max)
+}
+
+fn chunks<C>(
+  bounds: Option<Bounds>,
+  voxels: &[([i32; 3], u8)],
+  options: &FileFormatOptions,
+) -> Result<Vec<C>>
+where
+  C: ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+{
+  match (options.chunk_size, bounds) {
+    (Some(size), _) => Ok(split(voxels, size)),
+    (None, Some(bounds)) => Ok(vec![merge(bounds, voxels)?]),
+    (None, None) => Ok(vec![]),
+  }
+}
+
+// The whole model in a single chunk that covers all the blocks, with an empty voxel more on the
+// positive sides
+fn merge<C>((min, max): Bounds, voxels: &[([i32; 3], u8)]) -> Result<C>
+where
+  C: ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+{
+  let mut chunk = C::new(
+    [min[0] as f32, min[1] as f32, min[2] as f32],
+    (max[0] - min[0] + 2).try_into()?,
+    (max[1] - min[1] + 2).try_into()?,
+    (max[2] - min[2] + 2).try_into()?,
+  );
+
+  for (position, index) in voxels.iter() {
+    let [x, y, z] = [
+      (position[0] - min[0]) as usize,
+      (position[1] - min[1]) as usize,
+      (position[2] - min[2]) as usize,
     ];
+    chunk.set(x, y, z, 1.);
+    chunk.set_atlas(x, y, z, *index);
+  }
+
+  Ok(chunk)
+}
+
+// One chunk of `size` for each cell of the grid that has voxels
+fn split<C>(voxels: &[([i32; 3], u8)], size: u16) -> Vec<C>
+where
+  C: ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+{
+  let mut chunks: BTreeMap<[i32; 3], C> = BTreeMap::new();
+  let size_i32 = size as i32;
 
-    let chunk_size: [Coord; 3] = [
-      (max[0] - min[0] + 1).try_into().unwrap(),
-      (max[1] - min[1] + 1).try_into().unwrap(),
-      (max[2] - min[2] + 1).try_into().unwrap(),
+  for (position, index) in voxels.iter() {
+    let cell = [
+      position[0].div_euclid(size_i32),
+      position[1].div_euclid(size_i32),
+      position[2].div_euclid(size_i32),
+    ];
+    let [x, y, z] = [
+      (position[0] - cell[0] * size_i32) as usize,
+      (position[1] - cell[1] * size_i32) as usize,
+      (position[2] - cell[2] * size_i32) as usize,
     ];
 
-    let mut chunk = C::new(
-      [(min[0]) as f32, (min[2]) as f32, (min[1]) as f32],
-      chunk_size[0].try_into().unwrap(),
-      chunk_size[2].try_into().unwrap(), // goxel is in y up gaiku in z up
-      chunk_size[1].try_into().unwrap(),
-    );
+    let chunk = chunks.entry(cell).or_insert_with(|| {
+      C::new(
+        [
+          (cell[0] * size_i32) as f32,
+          (cell[1] * size_i32) as f32,
+          (cell[2] * size_i32) as f32,
+        ],
+        size,
+        size,
+        size,
+      )
+    });
+    chunk.set(x, y, z, 1.);
+    chunk.set_atlas(x, y, z, *index);
+  }
 
-    for data in gox.data.iter() {
-      if let Data::Layers(layers, _bounds) = &data {
-        for layer in layers.iter() {
-          if !layer.blocks.is_empty() {
-            for data in layer.blocks.iter() {
-              let block_colors = block_data[data.block_index];
-              let origin: [Coord; 3] = [
-                (data.x - min[0]).try_into().unwrap(),
-                (data.y - min[1]).try_into().unwrap(),
-                (data.z - min[2]).try_into().unwrap(),
-              ];
-
-              for x in 0..16 {
-                let x_c = x as Coord + origin[0];
-                for y in 0..16 {
-                  let y_c = y as Coord + origin[1];
-                  for z in 0..16 {
-                    let z_c = z as Coord + origin[2];
-                    if !block_colors.is_empty(x, y, z) {
-                      let color = block_colors.get_pixel(x, y, z);
-                      let index = if let Some((index, _)) =
-                        colors.iter().enumerate().find(|(_, value)| {
-                          value[0] == color[0]
-                            && value[1] == color[1]
-                            && value[2] == color[2]
-                            && value[3] == color[3]
-                        }) {
-                        index
-                      } else {
-                        let index = colors.len();
-                        colors.push(color);
-                        index
-                      };
-
-                      if index <= std::u8::MAX as usize {
-                        chunk.set(x_c, z_c, y_c, 1.); // goxel is in y up gaiku in zup
-                        chunk.set_atlas(x_c, z_c, y_c, index as Self::AtlasValue);
-                      }
-                    }
-                  }
-                }
-              }
+  chunks.into_values().collect()
+}
+
+impl FileFormatWriter for GoxReader {
+  /// Saves the voxels that aren't air (with the isovalue `0.0`) in a single layer. The voxels whose
+  /// atlas index has no color (or without `atlas`) are saved white.
+  ///
+  /// The reader assigns the atlas indexes in the order the colors are found, so they can change
+  /// when the file is loaded back.
+  fn save_with_options<C, T>(
+    chunks: &[C],
+    atlas: Option<&TextureAtlas2d<T>>,
+    options: &FileFormatOptions,
+  ) -> Result<Vec<u8>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    let palette = atlas.map(|atlas| atlas.palette());
+    let mut image = Image::default();
+
+    for chunk in chunks.iter() {
+      let position = chunk.position();
+      let origin = [
+        position[0].round() as i32,
+        position[1].round() as i32,
+        position[2].round() as i32,
+      ];
+
+      for x in 0..chunk.width() as usize {
+        for y in 0..chunk.height() as usize {
+          for z in 0..chunk.depth() as usize {
+            if chunk.is_air(x, y, z, 0.0) {
+              continue;
             }
+
+            let color = palette
+              .as_ref()
+              .map(|palette| palette[chunk.get_atlas(x, y, z) as usize])
+              .filter(|color| color[3] > 0)
+              .unwrap_or([255; 4]);
+
+            image.set(
+              options.coordinate_system.to_z_up_right_handed([
+                origin[0] + x as i32,
+                origin[1] + y as i32,
+                origin[2] + z as i32,
+              ]),
+              color,
+            );
           }
         }
       }
     }
 
-    result.push(chunk);
+    image.write()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use gaiku_common::{chunk::Chunk, texture::Texture2d};
+  use std::collections::HashMap;
 
-    if !colors.is_empty() {
-      let mut atlas = TextureAtlas2d::new(1);
+  // Color of each solid voxel by its world position
+  fn voxels(chunks: &[Chunk], atlas: &TextureAtlas2d<Texture2d>) -> HashMap<[i32; 3], [u8; 4]> {
+    let palette = atlas.palette();
+    let mut result = HashMap::new();
 
-      for (index, color) in colors.iter().enumerate() {
-        // colors should limited to 255 so (index.try_into().unwrap()) should fit into u8 for set_at_index
-        atlas.fill_at_index(index.try_into().unwrap(), *color);
+    for chunk in chunks.iter() {
+      let position = chunk.position();
+      for x in 0..chunk.width() as usize {
+        for y in 0..chunk.height() as usize {
+          for z in 0..chunk.depth() as usize {
+            if !chunk.is_air(x, y, z, 0.0) {
+              result.insert(
+                [
+                  position[0] as i32 + x as i32,
+                  position[1] as i32 + y as i32,
+                  position[2] as i32 + z as i32,
+                ],
+                palette[chunk.get_atlas(x, y, z) as usize],
+              );
+            }
+          }
+        }
       }
+    }
+
+    result
+  }
+
+  #[test]
+  fn test_save_and_load() {
+    let mut atlas = TextureAtlas2d::<Texture2d>::new(1);
+    atlas.fill_at_index(3, [255, 0, 0, 255]);
+    atlas.fill_at_index(7, [0, 0, 255, 255]);
+
+    // Crosses the blocks on every axis
+    let mut chunk = Chunk::new([-2.0, 14.0, 3.0], 4, 4, 4);
+    chunk.set(0, 0, 0, 1.0);
+    chunk.set_atlas(0, 0, 0, 3);
+    chunk.set(3, 3, 1, 1.0);
+    chunk.set_atlas(3, 3, 1, 7);
+    chunk.set(1, 2, 3, 1.0);
+    chunk.set_atlas(1, 2, 3, 3);
+
+    let bytes = GoxReader::save(&[chunk.clone()], Some(&atlas)).unwrap();
+    let (chunks, loaded_atlas) = GoxReader::load::<Chunk, Texture2d>(bytes).unwrap();
+
+    assert_eq!(
+      voxels(&chunks, &loaded_atlas.unwrap()),
+      voxels(&[chunk], &atlas)
+    );
+  }
+
+  #[test]
+  fn test_chunk_size() {
+    let mut atlas = TextureAtlas2d::<Texture2d>::new(1);
+    atlas.fill_at_index(0, [255, 0, 0, 255]);
+
+    let mut chunk = Chunk::new([-2.0, 14.0, 3.0], 4, 4, 4);
+    chunk.set(0, 0, 0, 1.0);
+    chunk.set(3, 3, 1, 1.0);
+    chunk.set(3, 0, 3, 1.0);
+
+    let bytes = GoxReader::save(&[chunk.clone()], Some(&atlas)).unwrap();
+    let options = FileFormatOptions {
+      chunk_size: Some(16),
+      ..Default::default()
+    };
+    let (chunks, loaded_atlas) =
+      GoxReader::load_with_options::<Chunk, Texture2d>(bytes, &options).unwrap();
+
+    // Each voxel is in a different block
+    assert_eq!(chunks.len(), 3);
+    for chunk in chunks.iter() {
+      assert_eq!([chunk.width(), chunk.height(), chunk.depth()], [16, 16, 16]);
+      assert!(chunk.position().iter().all(|value| value % 16.0 == 0.0));
+    }
+    assert_eq!(
+      voxels(&chunks, &loaded_atlas.unwrap()),
+      voxels(&[chunk], &atlas)
+    );
+  }
+
+  #[test]
+  fn test_coordinate_systems() {
+    let mut chunk = Chunk::new([1.0, 2.0, 3.0], 2, 2, 2);
+    chunk.set(1, 0, 0, 1.0);
+
+    let bytes = GoxReader::save::<_, Texture2d>(&[chunk], None).unwrap();
+    let options = FileFormatOptions {
+      chunk_size: Some(16),
+      coordinate_system: CoordinateSystem::ZUpRightHanded,
+      ..Default::default()
+    };
+    let (chunks, _) = GoxReader::load_with_options::<Chunk, Texture2d>(bytes, &options).unwrap();
+
+    // [2, 2, 3] with y up is [2, -4, 2] with z up
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].position(), [0.0, -16.0, 0.0]);
+    assert!(!chunks[0].is_air(2, 12, 2, 0.0));
+  }
+
+  #[test]
+  fn test_layers() {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 1, 1, 1);
+    chunk.set(0, 0, 0, 1.0);
+    let mut bytes = GoxReader::save::<_, Texture2d>(&[chunk], None).unwrap();
+
+    // Hidden layer with a material, using the same block further in x
+    let mut material = vec![];
+    for value in [0.5_f32, 0.5, 0.5, 1.0].iter() {
+      material.extend(&value.to_le_bytes());
+    }
+    writer::chunk(
+      &mut bytes,
+      b"MATE",
+      &writer::dict(&[
+        ("name", b"Metal\0"),
+        ("color", &material),
+        ("metallic", &1.0_f32.to_le_bytes()),
+      ]),
+    );
+
+    let mut layer = vec![];
+    for value in [1_i32, 0, 32, -16, 0, 0].iter() {
+      layer.extend(&value.to_le_bytes());
+    }
+    layer.extend(writer::dict(&[
+      ("name", b"Helper\0"),
+      ("visible", &[0]),
+      ("material", &0_i32.to_le_bytes()),
+    ]));
+    writer::chunk(&mut bytes, b"LAYR", &layer);
+
+    let (chunks, _) = GoxReader::load::<Chunk, Texture2d>(bytes.clone()).unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].position(), [0.0, 0.0, 0.0]);
+
+    let options = FileFormatOptions::default();
+    let (layers, _, _) = GoxReader::load_layers::<Chunk, Texture2d>(bytes, &options).unwrap();
+    assert_eq!(layers.len(), 2);
+
+    assert_eq!(layers[0].name, "Layer 1");
+    assert!(layers[0].visible);
+    assert_eq!(layers[0].material, None);
+
+    assert_eq!(layers[1].name, "Helper");
+    assert!(!layers[1].visible);
+    let material = layers[1].material.as_ref().unwrap();
+    assert_eq!(material.name, "Metal");
+    assert_eq!(material.color, [0.5, 0.5, 0.5, 1.0]);
+    assert_eq!(material.metallic, 1.0);
+    assert_eq!(layers[1].chunks[0].position(), [32.0, 0.0, 0.0]);
+    assert!(!layers[1].chunks[0].is_air(0, 0, 0, 0.0));
+  }
+
+  #[test]
+  fn test_palette_overflow() {
+    // A gradient of 300 colors
+    let mut image = Image::default();
+    for index in 0..300 {
+      image.set(
+        [index, 0, 0],
+        [(index / 2) as u8, (index % 2) as u8, 0, 255],
+      );
+    }
+    let bytes = image.write().unwrap();
+
+    assert!(GoxReader::load::<Chunk, Texture2d>(bytes.clone()).is_err());
+
+    let options = FileFormatOptions {
+      palette_overflow: PaletteOverflow::Quantize { max_error: 0.0 },
+      ..Default::default()
+    };
+    assert!(GoxReader::load_with_options::<Chunk, Texture2d>(bytes.clone(), &options).is_err());
+
+    let options = FileFormatOptions {
+      palette_overflow: PaletteOverflow::Quantize { max_error: 1.0 },
+      ..Default::default()
+    };
+    let (chunks, atlas, report) =
+      GoxReader::load_with_report::<Chunk, Texture2d>(bytes, &options).unwrap();
+    assert_eq!(report.colors, 300);
+    assert_eq!(report.palette, 256);
+    assert!(report.quantization_error > 0.0 && report.quantization_error <= 1.0);
 
-      Ok((result, Some(atlas)))
-    } else {
-      Ok((result, None))
+    // No voxel is lost
+    let voxels = voxels(&chunks, &atlas.unwrap());
+    assert_eq!(voxels.len(), 300);
+    for (position, color) in voxels.iter() {
+      assert!((color[0] as i32 - position[0] / 2).abs() <= 1);
     }
   }
 }
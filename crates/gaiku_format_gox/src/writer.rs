@@ -0,0 +1,121 @@
+use gaiku_common::Result;
+use std::collections::BTreeMap;
+
+const BLOCK_SIZE: i32 = 16;
+
+/// Voxels of a `gox` file grouped in blocks of 16x16x16, in the goxel axes (z up).
+#[derive(Default)]
+pub(crate) struct Image {
+  blocks: BTreeMap<[i32; 3], Vec<[u8; 4]>>,
+}
+
+impl Image {
+  pub fn set(&mut self, position: [i32; 3], color: [u8; 4]) {
+    let origin = [
+      position[0].div_euclid(BLOCK_SIZE) * BLOCK_SIZE,
+      position[1].div_euclid(BLOCK_SIZE) * BLOCK_SIZE,
+      position[2].div_euclid(BLOCK_SIZE) * BLOCK_SIZE,
+    ];
+    let [x, y, z] = [
+      (position[0] - origin[0]) as usize,
+      (position[1] - origin[1]) as usize,
+      (position[2] - origin[2]) as usize,
+    ];
+
+    let block = self
+      .blocks
+      .entry(origin)
+      .or_insert_with(|| vec![[0; 4]; (BLOCK_SIZE * BLOCK_SIZE * BLOCK_SIZE) as usize]);
+    block[x + y * BLOCK_SIZE as usize + z * (BLOCK_SIZE * BLOCK_SIZE) as usize] = color;
+  }
+
+  pub fn write(&self) -> Result<Vec<u8>> {
+    let mut result = b"GOX ".to_vec();
+    result.extend(&2_i32.to_le_bytes());
+
+    chunk(&mut result, b"IMG ", &dict(&[("box", &self.bounds())]));
+
+    for colors in self.blocks.values() {
+      chunk(&mut result, b"BL16", &block_png(colors)?);
+    }
+
+    let mut layer = (self.blocks.len() as i32).to_le_bytes().to_vec();
+    for (index, origin) in self.blocks.keys().enumerate() {
+      for value in [index as i32, origin[0], origin[1], origin[2], 0].iter() {
+        layer.extend(&value.to_le_bytes());
+      }
+    }
+    layer.extend(dict(&[("name", b"Layer 1"), ("visible", &[1])]));
+    chunk(&mut result, b"LAYR", &layer);
+
+    Ok(result)
+  }
+
+  // The box of the image as a 4x4 matrix, the columns are the half sizes and the center
+  fn bounds(&self) -> Vec<u8> {
+    let mut matrix = [[0.0_f32; 4]; 4];
+
+    if let Some(first) = self.blocks.keys().next() {
+      let (mut min, mut max) = (*first, *first);
+      for origin in self.blocks.keys() {
+        for axis in 0..3 {
+          min[axis] = min[axis].min(origin[axis]);
+          max[axis] = max[axis].max(origin[axis] + BLOCK_SIZE);
+        }
+      }
+
+      for axis in 0..3 {
+        matrix[axis][axis] = (max[axis] - min[axis]) as f32 / 2.0;
+        matrix[3][axis] = (max[axis] + min[axis]) as f32 / 2.0;
+      }
+    }
+    matrix[3][3] = 1.0;
+
+    matrix
+      .iter()
+      .flatten()
+      .flat_map(|value| value.to_le_bytes())
+      .collect()
+  }
+}
+
+// Like the png chunks, the crc covers the type and the data
+pub(crate) fn chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+  let mut hasher = crc32fast::Hasher::new();
+  hasher.update(kind);
+  hasher.update(data);
+
+  bytes.extend(kind);
+  bytes.extend(&(data.len() as i32).to_le_bytes());
+  bytes.extend(data);
+  bytes.extend(&hasher.finalize().to_le_bytes());
+}
+
+// The dictionaries end with an empty key
+pub(crate) fn dict(entries: &[(&str, &[u8])]) -> Vec<u8> {
+  let mut result = vec![];
+  for (key, value) in entries.iter() {
+    result.extend(&(key.len() as i32).to_le_bytes());
+    result.extend(key.as_bytes());
+    result.extend(&(value.len() as i32).to_le_bytes());
+    result.extend(*value);
+  }
+  result.extend(&0_i32.to_le_bytes());
+  result
+}
+
+// The blocks are saved as a 64x64 png image
+fn block_png(colors: &[[u8; 4]]) -> Result<Vec<u8>> {
+  let mut result = vec![];
+
+  {
+    let mut encoder = png::Encoder::new(&mut result, 64, 64);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Best);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&colors.concat())?;
+  }
+
+  Ok(result)
+}
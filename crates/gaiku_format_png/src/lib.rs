@@ -1,37 +1,180 @@
 use gaiku_common::{prelude::*, Result};
 
-use image::load_from_memory;
+use anyhow::ensure;
+use image::{load_from_memory, ColorType, DynamicImage, GenericImageView};
+use std::fs::read;
 
 /// Converts a `png` file to 2d chunk data.
+///
+/// The values are the gray levels in the `0.0` to `255.0` range, the 16 bit images keep their
+/// precision in the fractions. The atlas indexes are the 8 bit gray levels, or come from a material
+/// map with `load_with_materials`.
 pub struct PNGReader;
 
+impl PNGReader {
+  /// Loads the heightmap, taking the atlas indexes from a `materials` image of the same size.
+  ///
+  /// The gray levels of the grayscale material maps are the atlas indexes, the color ones are
+  /// splat maps where the strongest channel is the index (`0` red, `1` green, `2` blue and `3`
+  /// alpha when there is alpha).
+  pub fn load_with_materials<C, T>(
+    bytes: Vec<u8>,
+    materials: Vec<u8>,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+    T: Texturify2d,
+  {
+    let heights = load_from_memory(&bytes)?;
+    let materials = load_from_memory(&materials)?;
+    ensure!(
+      heights.dimensions() == materials.dimensions(),
+      "The material map is {:?} and the heightmap {:?}",
+      materials.dimensions(),
+      heights.dimensions()
+    );
+
+    let mut chunk = heightmap::<C>(&heights)?;
+    let color = materials.color();
+    let channels = if color.has_alpha() { 4 } else { 3 };
+    let materials = materials.into_rgba8();
+
+    for (x, y, pixel) in materials.enumerate_pixels() {
+      let index = if color.has_color() {
+        (0..channels).max_by_key(|&channel| pixel[channel]).unwrap() as u8
+      } else {
+        pixel[0]
+      };
+      chunk.set_atlas(x as usize, y as usize, 0, index);
+    }
+
+    Ok((vec![chunk], None))
+  }
+
+  pub fn read_with_materials<C, T>(
+    file: &str,
+    materials_file: &str,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+    T: Texturify2d,
+  {
+    Self::load_with_materials::<C, T>(read(file)?, read(materials_file)?)
+  }
+}
+
 impl FileFormat for PNGReader {
   type Value = f32;
   type AtlasValue = u8;
 
-  fn load<C, T>(bytes: Vec<u8>) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  fn load_with_options<C, T>(
+    bytes: Vec<u8>,
+    _options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
   where
     C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
     T: Texturify2d,
   {
-    let mut result = vec![];
-    let img = load_from_memory(&bytes)?.into_luma8();
+    let img = load_from_memory(&bytes)?;
+    let chunk = heightmap::<C>(&img)?;
+
+    Ok((vec![chunk], None))
+  }
+}
 
-    assert!(img.width() <= u16::MAX as u32);
-    assert!(img.height() <= u16::MAX as u32);
+fn heightmap<C>(img: &DynamicImage) -> Result<C>
+where
+  C: ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+{
+  ensure!(
+    img.width() <= u16::MAX as u32 && img.height() <= u16::MAX as u32,
+    "The image is too big for a chunk"
+  );
 
-    let mut chunk = C::new([0.0, 0.0, 0.0], img.width() as u16, img.height() as u16, 1);
+  let mut chunk = C::new([0.0, 0.0, 0.0], img.width() as u16, img.height() as u16, 1);
 
-    for x in 0..img.width() as u32 {
-      for y in 0..img.height() as u32 {
-        let color = img.get_pixel(x, y).0[0];
-        chunk.set(x as usize, y as usize, 0, color as f32);
-        chunk.set_atlas(x as usize, y as usize, 0, color);
+  match img.color() {
+    ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+      for (x, y, pixel) in img.to_luma16().enumerate_pixels() {
+        let gray = pixel.0[0];
+        // 257 maps 65535 to 255
+        chunk.set(x as usize, y as usize, 0, gray as f32 / 257.0);
+        chunk.set_atlas(x as usize, y as usize, 0, (gray >> 8) as u8);
+      }
+    }
+    _ => {
+      for (x, y, pixel) in img.to_luma8().enumerate_pixels() {
+        let gray = pixel.0[0];
+        chunk.set(x as usize, y as usize, 0, gray as f32);
+        chunk.set_atlas(x as usize, y as usize, 0, gray);
       }
     }
+  }
+
+  Ok(chunk)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use gaiku_common::{chunk::Chunk, texture::Texture2d};
+  use image::{png::PngEncoder, ImageBuffer, ImageOutputFormat, Luma, Rgb};
+
+  fn png(image: DynamicImage) -> Vec<u8> {
+    let mut bytes = vec![];
+    image.write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn test_16_bit() {
+    // The png samples are big endian, `write_to` would write them in the native order
+    let samples: Vec<u8> = [0_u16, 1000].iter().flat_map(|v| v.to_be_bytes()).collect();
+    let mut bytes = vec![];
+    PngEncoder::new(&mut bytes)
+      .encode(&samples, 2, 1, ColorType::L16)
+      .unwrap();
+
+    let (chunks, _) = PNGReader::load::<Chunk, Texture2d>(bytes).unwrap();
+
+    assert_eq!(chunks[0].get(0, 0, 0), 0.0);
+    assert!((chunks[0].get(1, 0, 0) - 1000.0 / 257.0).abs() < 1e-4);
+    assert_eq!(chunks[0].get_atlas(1, 0, 0), 3);
+  }
+
+  #[test]
+  fn test_materials() {
+    let heights = ImageBuffer::from_fn(3, 1, |_, _| Luma([10_u8]));
+    let heights = png(DynamicImage::ImageLuma8(heights));
+
+    let splat = ImageBuffer::from_fn(3, 1, |x, _| {
+      Rgb([[200, 0, 50], [10, 90, 20], [0, 100, 101]][x as usize])
+    });
+    let (chunks, _) = PNGReader::load_with_materials::<Chunk, Texture2d>(
+      heights.clone(),
+      png(DynamicImage::ImageRgb8(splat)),
+    )
+    .unwrap();
+
+    assert_eq!(chunks[0].get(0, 0, 0), 10.0);
+    assert_eq!(chunks[0].get_atlas(0, 0, 0), 0);
+    assert_eq!(chunks[0].get_atlas(1, 0, 0), 1);
+    assert_eq!(chunks[0].get_atlas(2, 0, 0), 2);
+
+    let indexes = ImageBuffer::from_fn(3, 1, |x, _| Luma([x as u8 * 7]));
+    let (chunks, _) = PNGReader::load_with_materials::<Chunk, Texture2d>(
+      heights.clone(),
+      png(DynamicImage::ImageLuma8(indexes)),
+    )
+    .unwrap();
 
-    result.push(chunk);
+    assert_eq!(chunks[0].get_atlas(2, 0, 0), 14);
 
-    Ok((result, None))
+    let small = ImageBuffer::from_fn(2, 1, |_, _| Luma([0_u8]));
+    assert!(PNGReader::load_with_materials::<Chunk, Texture2d>(
+      heights,
+      png(DynamicImage::ImageLuma8(small)),
+    )
+    .is_err());
   }
 }
@@ -0,0 +1,325 @@
+use gaiku_common::{prelude::*, Result};
+
+use anyhow::ensure;
+use image::{load_from_memory_with_format, ColorType, GenericImageView, ImageFormat};
+use std::{convert::TryInto, fs::read};
+
+/// Size of the samples of a `RawReader`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitDepth {
+  Eight,
+  Sixteen,
+}
+
+/// Order of the bytes of the 16 bit samples of a `RawReader`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ByteOrder {
+  LittleEndian,
+  BigEndian,
+}
+
+/// Converts a headerless `raw` heightmap, with the given dimensions and samples, to 2d chunk data.
+///
+/// Like the `png` heightmaps the values are in the `0.0` to `255.0` range, the 16 bit samples keep
+/// their precision in the fractions, and the atlas indexes are the 8 most significant bits. The
+/// samples are row by row starting from the top left corner.
+#[derive(Clone, Copy, Debug)]
+pub struct RawReader {
+  pub width: u32,
+  pub height: u32,
+  pub bit_depth: BitDepth,
+  pub byte_order: ByteOrder,
+}
+
+impl RawReader {
+  pub fn load_with_options<C, T>(
+    &self,
+    bytes: Vec<u8>,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+    T: Texturify2d,
+  {
+    let bytes_per_sample = match self.bit_depth {
+      BitDepth::Eight => 1,
+      BitDepth::Sixteen => 2,
+    };
+    let expected = self.width as usize * self.height as usize * bytes_per_sample;
+    ensure!(
+      bytes.len() == expected,
+      "Expected {} bytes for a {}x{} heightmap, got {}",
+      expected,
+      self.width,
+      self.height,
+      bytes.len()
+    );
+
+    let samples: Vec<u16> = match (self.bit_depth, self.byte_order) {
+      (BitDepth::Eight, _) => bytes.iter().map(|&sample| sample as u16 * 257).collect(),
+      (BitDepth::Sixteen, ByteOrder::LittleEndian) => bytes
+        .chunks(2)
+        .map(|sample| u16::from_le_bytes([sample[0], sample[1]]))
+        .collect(),
+      (BitDepth::Sixteen, ByteOrder::BigEndian) => bytes
+        .chunks(2)
+        .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+        .collect(),
+    };
+
+    Ok((heightmap(self.width, self.height, &samples, options)?, None))
+  }
+
+  pub fn load<C, T>(&self, bytes: Vec<u8>) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+    T: Texturify2d,
+  {
+    self.load_with_options(bytes, &FileFormatOptions::default())
+  }
+
+  pub fn read_with_options<C, T>(
+    &self,
+    file: &str,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+    T: Texturify2d,
+  {
+    self.load_with_options(read(file)?, options)
+  }
+
+  pub fn read<C, T>(&self, file: &str) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+    T: Texturify2d,
+  {
+    self.read_with_options(file, &FileFormatOptions::default())
+  }
+}
+
+/// Converts a `r16` heightmap to 2d chunk data, the square 16 bit little endian `raw` files that
+/// World Machine, Gaea and Unity export.
+pub struct R16Reader;
+
+impl FileFormat for R16Reader {
+  type Value = f32;
+  type AtlasValue = u8;
+
+  fn load_with_options<C, T>(
+    bytes: Vec<u8>,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    let size = ((bytes.len() / 2) as f64).sqrt() as u32;
+    ensure!(
+      size as usize * size as usize * 2 == bytes.len(),
+      "A r16 file of {} bytes is not a square heightmap",
+      bytes.len()
+    );
+
+    RawReader {
+      width: size,
+      height: size,
+      bit_depth: BitDepth::Sixteen,
+      byte_order: ByteOrder::LittleEndian,
+    }
+    .load_with_options(bytes, options)
+  }
+}
+
+/// Converts a grayscale `tiff` heightmap, 8 or 16 bit, to 2d chunk data. The floating point tiffs
+/// aren't supported.
+pub struct TIFFReader;
+
+impl FileFormat for TIFFReader {
+  type Value = f32;
+  type AtlasValue = u8;
+
+  fn load_with_options<C, T>(
+    bytes: Vec<u8>,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    let img = load_from_memory_with_format(&bytes, ImageFormat::Tiff)?;
+    let samples: Vec<u16> = match img.color() {
+      ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+        img.to_luma16().into_raw()
+      }
+      _ => img
+        .to_luma8()
+        .into_raw()
+        .into_iter()
+        .map(|sample| sample as u16 * 257)
+        .collect(),
+    };
+
+    Ok((
+      heightmap(img.width(), img.height(), &samples, options)?,
+      None,
+    ))
+  }
+}
+
+/// Fills the chunks with the samples, in tiles of `chunk_size` samples when it is set.
+///
+/// The tiles don't overlap, they are placed at their first sample on the x and y axes of the
+/// chunks, the convention of `HeightMapBaker`, so the neighbor data (see `Apronify`) joins their
+/// meshes. The positions are in samples, the baker scales them by the `cell_size`.
+fn heightmap<C>(
+  width: u32,
+  height: u32,
+  samples: &[u16],
+  options: &FileFormatOptions,
+) -> Result<Vec<C>>
+where
+  C: ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+{
+  let (columns, rows) = match options.chunk_size {
+    Some(chunk_size) => {
+      ensure!(
+        chunk_size > 0,
+        "The tiles need at least 1 sample on each side"
+      );
+      (
+        tiles(width, chunk_size as u32),
+        tiles(height, chunk_size as u32),
+      )
+    }
+    None => (vec![(0, width)], vec![(0, height)]),
+  };
+
+  let mut result = vec![];
+
+  for &(y, tile_height) in rows.iter() {
+    for &(x, tile_width) in columns.iter() {
+      let mut chunk = C::new(
+        [x as f32, y as f32, 0.0],
+        tile_width.try_into()?,
+        tile_height.try_into()?,
+        1,
+      );
+
+      for tile_y in 0..tile_height {
+        for tile_x in 0..tile_width {
+          let sample = samples[((y + tile_y) * width + x + tile_x) as usize];
+          // 257 maps 65535 to 255
+          chunk.set(tile_x as usize, tile_y as usize, 0, sample as f32 / 257.0);
+          chunk.set_atlas(tile_x as usize, tile_y as usize, 0, (sample >> 8) as u8);
+        }
+      }
+
+      result.push(chunk);
+    }
+  }
+
+  Ok(result)
+}
+
+/// Start and length of the tiles along an axis, each one starting after the last sample of the
+/// previous one.
+fn tiles(size: u32, tile: u32) -> Vec<(u32, u32)> {
+  (0..size)
+    .step_by(tile as usize)
+    .map(|start| (start, tile.min(size - start)))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use gaiku_common::{chunk::Chunk, texture::Texture2d, world::World};
+  use image::codecs::tiff::TiffEncoder;
+  use std::io::Cursor;
+
+  #[test]
+  fn test_raw() {
+    let reader = RawReader {
+      width: 2,
+      height: 1,
+      bit_depth: BitDepth::Sixteen,
+      byte_order: ByteOrder::BigEndian,
+    };
+    let (chunks, _) = reader
+      .load::<Chunk, Texture2d>(vec![0xff, 0xff, 0x03, 0xe8])
+      .unwrap();
+
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].width(), 2);
+    assert_eq!(chunks[0].height(), 1);
+    assert_eq!(chunks[0].get(0, 0, 0), 255.0);
+    assert!((chunks[0].get(1, 0, 0) - 1000.0 / 257.0).abs() < 1e-4);
+    assert_eq!(chunks[0].get_atlas(1, 0, 0), 3);
+
+    let reader = RawReader {
+      bit_depth: BitDepth::Eight,
+      ..reader
+    };
+    let (chunks, _) = reader.load::<Chunk, Texture2d>(vec![10, 20]).unwrap();
+
+    assert_eq!(chunks[0].get(1, 0, 0), 20.0);
+    assert_eq!(chunks[0].get_atlas(1, 0, 0), 20);
+
+    assert!(reader.load::<Chunk, Texture2d>(vec![10, 20, 30]).is_err());
+  }
+
+  #[test]
+  fn test_r16_tiles() {
+    // 5x5 samples, each one is its index
+    let bytes: Vec<u8> = (0..25_u16)
+      .flat_map(|sample| sample.to_le_bytes())
+      .collect();
+    let options = FileFormatOptions {
+      chunk_size: Some(3),
+      ..Default::default()
+    };
+    let (chunks, _) = R16Reader::load_with_options::<Chunk, Texture2d>(bytes, &options).unwrap();
+
+    // Starting at 0 and 3 on the x and y axes of the chunks
+    assert_eq!(chunks.len(), 4);
+    assert_eq!(chunks[3].position(), [3.0, 3.0, 0.0]);
+    assert_eq!(chunks[3].width(), 2);
+    assert_eq!(chunks[1].get(0, 0, 0) * 257.0, 3.0);
+    assert_eq!(chunks[2].get(2, 0, 0) * 257.0, 17.0);
+
+    // The neighbor data of the first tile reaches the first samples of the others
+    let mut world = World::<Chunk>::new([3, 3, 1]);
+    for chunk in chunks {
+      world.insert(chunk);
+    }
+    world.update_neighbor_data([0, 0, 0]);
+    let chunk = world.get_chunk([0, 0, 0]).unwrap();
+    assert_eq!(chunk.get(3, 0, 0) * 257.0, 3.0);
+    assert_eq!(chunk.get(3, 3, 0) * 257.0, 18.0);
+
+    assert!(R16Reader::load::<Chunk, Texture2d>(vec![0; 6]).is_err());
+  }
+
+  #[test]
+  fn test_tiles() {
+    assert_eq!(tiles(3, 16), vec![(0, 3)]);
+    assert_eq!(tiles(5, 3), vec![(0, 3), (3, 2)]);
+    assert_eq!(tiles(6, 3), vec![(0, 3), (3, 3)]);
+  }
+
+  #[test]
+  fn test_tiff() {
+    let samples: Vec<u8> = [0_u16, 1000].iter().flat_map(|v| v.to_ne_bytes()).collect();
+    let mut bytes = Cursor::new(vec![]);
+    TiffEncoder::new(&mut bytes)
+      .encode(&samples, 2, 1, ColorType::L16)
+      .unwrap();
+    let bytes = bytes.into_inner();
+
+    let (chunks, _) = TIFFReader::load::<Chunk, Texture2d>(bytes).unwrap();
+
+    assert!((chunks[0].get(1, 0, 0) - 1000.0 / 257.0).abs() < 1e-4);
+  }
+}
@@ -0,0 +1,480 @@
+use anyhow::ensure;
+use gaiku_common::{prelude::*, Result};
+use std::convert::TryInto;
+
+mod parser;
+mod scene;
+mod writer;
+
+use self::{
+  parser::{Model, VoxFile},
+  scene::instances,
+  writer::write,
+};
+
+/// Converts a MagicaVoxel `vox` file to 3d chunk data, and back.
+///
+/// Each model placed in the scene becomes a chunk, positioned with the transforms of the scene
+/// graph, the hidden nodes and layers are skipped. The chunks have an empty voxel more around the
+/// model. The atlas index of the voxels is their index in the palette.
+pub struct VoxReader;
+
+impl FileFormat for VoxReader {
+  type Value = f32;
+  type AtlasValue = u8;
+
+  fn load_with_options<C, T>(
+    bytes: Vec<u8>,
+    options: &FileFormatOptions,
+  ) -> Result<(Vec<C>, Option<TextureAtlas2d<T>>)>
+  where
+    C: Chunkify<Self::Value> + ChunkifyMut<Self::Value> + AtlasifyMut<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    let file = VoxFile::parse(&bytes)?;
+    let mut result = vec![];
+    let mut used = [false; 256];
+
+    for (model, transform) in instances(&file)? {
+      let model = &file.models[model];
+      if model.voxels.is_empty() {
+        continue;
+      }
+
+      let voxels: Vec<([i32; 3], u8)> = model
+        .voxels
+        .iter()
+        .map(|&[x, y, z, color]| {
+          let position = transform.apply([x as i32, y as i32, z as i32], model.size);
+          (
+            options.coordinate_system.from_z_up_right_handed(position),
+            color,
+          )
+        })
+        .collect();
+
+      let mut min = voxels[0].0;
+      let mut max = voxels[0].0;
+      for (position, _) in voxels.iter() {
+        for axis in 0..3 {
+          min[axis] = min[axis].min(position[axis]);
+          max[axis] = max[axis].max(position[axis]);
+        }
+      }
+
+      // The chunk has an empty voxel more on every side, so the bakers close the faces on the
+      // bounds of the model
+      let mut chunk = C::new(
+        [
+          (min[0] - 1) as f32,
+          (min[1] - 1) as f32,
+          (min[2] - 1) as f32,
+        ],
+        (max[0] - min[0] + 3).try_into()?,
+        (max[1] - min[1] + 3).try_into()?,
+        (max[2] - min[2] + 3).try_into()?,
+      );
+
+      for ([x, y, z], color) in voxels {
+        let (x, y, z) = (
+          (x - min[0] + 1) as usize,
+          (y - min[1] + 1) as usize,
+          (z - min[2] + 1) as usize,
+        );
+        chunk.set(x, y, z, 1.);
+        chunk.set_atlas(x, y, z, color);
+        used[color as usize] = true;
+      }
+
+      result.push(chunk);
+    }
+
+    if used.iter().any(|&used| used) {
+      let mut atlas = TextureAtlas2d::new(1);
+
+      for (index, color) in file.palette.iter().enumerate() {
+        if used[index] {
+          atlas.fill_at_index(index.try_into().unwrap(), *color);
+        }
+      }
+
+      Ok((result, Some(atlas)))
+    } else {
+      Ok((result, None))
+    }
+  }
+}
+
+impl FileFormatWriter for VoxReader {
+  /// Saves each chunk as a model, the voxels that aren't air (with the isovalue `0.0`) keep their
+  /// atlas index as color index. MagicaVoxel reserves the index `0` for the empty voxels, so when
+  /// it's used (like the atlases loaded from `gox` files) all the indexes are moved one up.
+  ///
+  /// Without `atlas` the file uses the default MagicaVoxel palette.
+  fn save_with_options<C, T>(
+    chunks: &[C],
+    atlas: Option<&TextureAtlas2d<T>>,
+    options: &FileFormatOptions,
+  ) -> Result<Vec<u8>>
+  where
+    C: Chunkify<Self::Value> + Atlasify<Self::AtlasValue> + Boxify,
+    T: Texturify2d,
+  {
+    let mut voxels = vec![];
+    let mut used = [false; 256];
+
+    for chunk in chunks.iter() {
+      let position = chunk.position();
+      let origin = [
+        position[0].round() as i32,
+        position[1].round() as i32,
+        position[2].round() as i32,
+      ];
+      let mut chunk_voxels = vec![];
+
+      for x in 0..chunk.width() as usize {
+        for y in 0..chunk.height() as usize {
+          for z in 0..chunk.depth() as usize {
+            if !chunk.is_air(x, y, z, 0.0) {
+              let index = chunk.get_atlas(x, y, z);
+              used[index as usize] = true;
+              chunk_voxels.push((
+                options.coordinate_system.to_z_up_right_handed([
+                  origin[0] + x as i32,
+                  origin[1] + y as i32,
+                  origin[2] + z as i32,
+                ]),
+                index,
+              ));
+            }
+          }
+        }
+      }
+
+      if !chunk_voxels.is_empty() {
+        voxels.push(chunk_voxels);
+      }
+    }
+
+    let shift = used[0] as u8;
+    ensure!(
+      shift == 0 || !used[255],
+      "The atlas indexes 0 and 255 can't be saved together"
+    );
+
+    let mut models = vec![];
+    for chunk_voxels in voxels.iter() {
+      let mut min = chunk_voxels[0].0;
+      let mut max = chunk_voxels[0].0;
+      for (position, _) in chunk_voxels.iter() {
+        for axis in 0..3 {
+          min[axis] = min[axis].min(position[axis]);
+          max[axis] = max[axis].max(position[axis]);
+        }
+      }
+
+      let size = [
+        max[0] - min[0] + 1,
+        max[1] - min[1] + 1,
+        max[2] - min[2] + 1,
+      ];
+      ensure!(
+        size.iter().all(|&size| size <= 256),
+        "The models can't be bigger than 256 voxels on any axis, found {:?}",
+        size
+      );
+
+      let model = Model {
+        size,
+        voxels: chunk_voxels
+          .iter()
+          .map(|(position, index)| {
+            [
+              (position[0] - min[0]) as u8,
+              (position[1] - min[1]) as u8,
+              (position[2] - min[2]) as u8,
+              index + shift,
+            ]
+          })
+          .collect(),
+      };
+
+      // The models are centered on the translation
+      let translation = [
+        min[0] + size[0] / 2,
+        min[1] + size[1] / 2,
+        min[2] + size[2] / 2,
+      ];
+
+      models.push((model, translation));
+    }
+
+    let palette = atlas.map(|atlas| {
+      let colors = atlas.palette();
+      let mut palette = [[0; 4]; 256];
+      for (index, color) in palette.iter_mut().enumerate().skip(shift as usize) {
+        *color = colors[index - shift as usize];
+      }
+      palette
+    });
+
+    Ok(write(&models, palette.as_ref()))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use gaiku_baker_voxel::VoxelBaker;
+  use gaiku_common::{chunk::Chunk, mesh::Mesh, texture::Texture2d};
+  use std::collections::HashMap;
+
+  fn chunk(id: &[u8], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut result = id.to_vec();
+    result.extend(&(content.len() as i32).to_le_bytes());
+    result.extend(&(children.len() as i32).to_le_bytes());
+    result.extend(content);
+    result.extend(children);
+    result
+  }
+
+  fn dict(pairs: &[(&str, &str)]) -> Vec<u8> {
+    let mut result = (pairs.len() as i32).to_le_bytes().to_vec();
+    for (key, value) in pairs.iter() {
+      for string in [key, value].iter() {
+        result.extend(&(string.len() as i32).to_le_bytes());
+        result.extend(string.as_bytes());
+      }
+    }
+    result
+  }
+
+  fn ints(values: &[i32]) -> Vec<u8> {
+    values
+      .iter()
+      .flat_map(|value| value.to_le_bytes())
+      .collect()
+  }
+
+  fn model(size: [i32; 3], voxels: &[[u8; 4]]) -> Vec<u8> {
+    let mut result = chunk(b"SIZE", &ints(&size), &[]);
+    let mut content = ints(&[voxels.len() as i32]);
+    content.extend(voxels.iter().flatten());
+    result.extend(chunk(b"XYZI", &content, &[]));
+    result
+  }
+
+  fn transform(
+    id: i32,
+    attributes: &[(&str, &str)],
+    child: i32,
+    frame: &[(&str, &str)],
+  ) -> Vec<u8> {
+    let mut content = ints(&[id]);
+    content.extend(dict(attributes));
+    content.extend(ints(&[child, -1, 0, 1]));
+    content.extend(dict(frame));
+    chunk(b"nTRN", &content, &[])
+  }
+
+  fn shape(id: i32, model: i32) -> Vec<u8> {
+    let mut content = ints(&[id]);
+    content.extend(dict(&[]));
+    content.extend(ints(&[1, model]));
+    content.extend(dict(&[]));
+    chunk(b"nSHP", &content, &[])
+  }
+
+  fn file(children: &[u8]) -> Vec<u8> {
+    let mut result = b"VOX ".to_vec();
+    result.extend(&150_i32.to_le_bytes());
+    result.extend(chunk(b"MAIN", &[], children));
+    result
+  }
+
+  // Color of each solid voxel by its world position
+  fn voxels(chunks: &[Chunk], atlas: &TextureAtlas2d<Texture2d>) -> HashMap<[i32; 3], [u8; 4]> {
+    let palette = atlas.palette();
+    let mut result = HashMap::new();
+
+    for chunk in chunks.iter() {
+      let position = chunk.position();
+      for x in 0..chunk.width() as usize {
+        for y in 0..chunk.height() as usize {
+          for z in 0..chunk.depth() as usize {
+            if !chunk.is_air(x, y, z, 0.0) {
+              result.insert(
+                [
+                  position[0] as i32 + x as i32,
+                  position[1] as i32 + y as i32,
+                  position[2] as i32 + z as i32,
+                ],
+                palette[chunk.get_atlas(x, y, z) as usize],
+              );
+            }
+          }
+        }
+      }
+    }
+
+    result
+  }
+
+  #[test]
+  fn test_single_model() {
+    let mut children = model([2, 3, 4], &[[0, 0, 0, 1], [1, 2, 3, 2]]);
+    let mut palette = vec![];
+    for index in 0..256 {
+      palette.extend(&[index as u8, 0, 0, 255]);
+    }
+    children.extend(chunk(b"RGBA", &palette, &[]));
+
+    let (chunks, atlas) = VoxReader::load::<Chunk, Texture2d>(file(&children)).unwrap();
+
+    assert_eq!(chunks.len(), 1);
+    let chunk = &chunks[0];
+    // The y axis of magicavoxel is the -z axis of gaiku
+    assert_eq!(chunk.position(), [-2.0, -3.0, -3.0]);
+    assert_eq!([chunk.width(), chunk.height(), chunk.depth()], [4, 6, 5]);
+    assert_eq!(chunk.get(1, 1, 3), 1.0);
+    assert_eq!(chunk.get_atlas(1, 1, 3), 1);
+    assert_eq!(chunk.get(2, 4, 1), 1.0);
+    assert_eq!(chunk.get_atlas(2, 4, 1), 2);
+    assert!(chunk.is_air(2, 1, 1, 0.5));
+    assert!(chunk.is_air(0, 1, 3, 0.5));
+
+    // The palette is shifted by one
+    let palette = atlas.unwrap().palette();
+    assert_eq!(palette[1], [0, 0, 0, 255]);
+    assert_eq!(palette[2], [1, 0, 0, 255]);
+  }
+
+  #[test]
+  fn test_scene_graph() {
+    let mut children = model([2, 1, 1], &[[0, 0, 0, 1], [1, 0, 0, 2]]);
+    children.extend(transform(0, &[], 1, &[]));
+
+    let mut group = ints(&[1]);
+    group.extend(dict(&[]));
+    group.extend(ints(&[3, 2, 4, 6]));
+    children.extend(chunk(b"nGRP", &group, &[]));
+
+    // Moved
+    children.extend(transform(2, &[], 3, &[("_t", "10 0 0")]));
+    children.extend(shape(3, 0));
+    // Mirrored on x
+    children.extend(transform(4, &[], 5, &[("_r", "20")]));
+    children.extend(shape(5, 0));
+    // Hidden
+    children.extend(transform(6, &[("_hidden", "1")], 7, &[]));
+    children.extend(shape(7, 0));
+
+    let (chunks, _) = VoxReader::load::<Chunk, Texture2d>(file(&children)).unwrap();
+
+    assert_eq!(chunks.len(), 2);
+
+    assert_eq!(chunks[0].position(), [8.0, -1.0, -2.0]);
+    assert_eq!(chunks[0].get_atlas(1, 1, 1), 1);
+    assert_eq!(chunks[0].get_atlas(2, 1, 1), 2);
+
+    // Mirrored in place
+    assert_eq!(chunks[1].position(), [-2.0, -1.0, -2.0]);
+    assert_eq!(chunks[1].get_atlas(1, 1, 1), 2);
+    assert_eq!(chunks[1].get_atlas(2, 1, 1), 1);
+  }
+
+  #[test]
+  fn test_default_palette() {
+    let children = model(
+      [4, 1, 1],
+      &[[0, 0, 0, 1], [1, 0, 0, 216], [2, 0, 0, 226], [3, 0, 0, 246]],
+    );
+
+    let (chunks, atlas) = VoxReader::load::<Chunk, Texture2d>(file(&children)).unwrap();
+    let palette = atlas.unwrap().palette();
+    let color = |x: usize| palette[chunks[0].get_atlas(x + 1, 1, 1) as usize];
+
+    assert_eq!(color(0), [0xff, 0xff, 0xff, 0xff]);
+    // The first color of the red, green and gray ramps
+    assert_eq!(color(1), [0xee, 0, 0, 0xff]);
+    assert_eq!(color(2), [0, 0xee, 0, 0xff]);
+    assert_eq!(color(3), [0xee, 0xee, 0xee, 0xff]);
+  }
+
+  #[test]
+  fn test_invalid_file() {
+    assert!(VoxReader::load::<Chunk, Texture2d>(b"GOX ".to_vec()).is_err());
+
+    // Truncated voxel data
+    let mut bytes = file(&model([1, 1, 1], &[[0, 0, 0, 1]]));
+    bytes.truncate(bytes.len() - 2);
+    assert!(VoxReader::load::<Chunk, Texture2d>(bytes).is_err());
+  }
+
+  #[test]
+  fn test_save_and_load() {
+    let mut atlas = TextureAtlas2d::<Texture2d>::new(1);
+    atlas.fill_at_index(0, [255, 0, 0, 255]);
+    atlas.fill_at_index(7, [0, 0, 255, 255]);
+
+    let mut first = Chunk::new([-2.0, 14.0, 3.0], 4, 4, 4);
+    first.set(0, 0, 0, 1.0);
+    first.set(3, 3, 1, 1.0);
+    first.set_atlas(3, 3, 1, 7);
+    let mut second = Chunk::new([5.0, 0.0, 0.0], 3, 3, 3);
+    second.set(1, 2, 0, 1.0);
+    let empty = Chunk::new([0.0, 0.0, 0.0], 2, 2, 2);
+
+    let chunks = vec![first, second, empty];
+    let bytes = VoxReader::save(&chunks, Some(&atlas)).unwrap();
+    let (loaded, loaded_atlas) = VoxReader::load::<Chunk, Texture2d>(bytes).unwrap();
+
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(
+      voxels(&loaded, &loaded_atlas.unwrap()),
+      voxels(&chunks, &atlas)
+    );
+  }
+
+  #[test]
+  fn test_save_without_atlas() {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 2, 2, 2);
+    chunk.set(1, 1, 1, 1.0);
+    chunk.set_atlas(1, 1, 1, 5);
+
+    let bytes = VoxReader::save::<_, Texture2d>(&[chunk], None).unwrap();
+    let (loaded, atlas) = VoxReader::load::<Chunk, Texture2d>(bytes).unwrap();
+
+    assert_eq!(loaded[0].get_atlas(1, 1, 1), 5);
+    assert_eq!(atlas.unwrap().palette()[5], [0xff, 0xff, 0x33, 0xff]);
+  }
+
+  #[test]
+  fn test_bake_loaded_model() {
+    // Two voxels next to each other on the border of the chunk
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 2, 2, 2);
+    chunk.set(0, 0, 0, 1.0);
+    chunk.set(1, 0, 0, 1.0);
+
+    let bytes = VoxReader::save::<_, Texture2d>(&[chunk], None).unwrap();
+    let (loaded, _) = VoxReader::load::<Chunk, Texture2d>(bytes).unwrap();
+    let options = BakerOptions::<Texture2d>::default();
+    let mesh = VoxelBaker::bake::<Chunk, Texture2d, Mesh>(&loaded[0], &options)
+      .unwrap()
+      .unwrap();
+
+    // The 10 faces of the box, each one split in 4 squares by the cells around the voxels, with 2
+    // triangles each
+    assert_eq!(mesh.get_indices().len(), 10 * 4 * 2 * 3);
+  }
+
+  #[test]
+  fn test_save_invalid_indexes() {
+    let mut chunk = Chunk::new([0.0, 0.0, 0.0], 2, 2, 2);
+    chunk.set(0, 0, 0, 1.0);
+    chunk.set(1, 1, 1, 1.0);
+    chunk.set_atlas(1, 1, 1, 255);
+
+    assert!(VoxReader::save::<_, Texture2d>(&[chunk], None).is_err());
+  }
+}
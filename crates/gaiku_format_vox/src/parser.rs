@@ -0,0 +1,247 @@
+use anyhow::{anyhow, ensure, Result};
+use std::{collections::HashMap, convert::TryInto};
+
+type Dict = HashMap<String, String>;
+
+/// Voxels of a model, `[x, y, z, color index]` in the MagicaVoxel axes (z up).
+#[derive(Debug)]
+pub(crate) struct Model {
+  pub size: [i32; 3],
+  pub voxels: Vec<[u8; 4]>,
+}
+
+/// Node of the scene graph.
+#[derive(Debug)]
+pub(crate) enum Node {
+  Transform {
+    child: i32,
+    layer: i32,
+    hidden: bool,
+    rotation: u8,
+    translation: [i32; 3],
+  },
+  Group {
+    children: Vec<i32>,
+  },
+  Shape {
+    models: Vec<usize>,
+  },
+}
+
+/// Content of a `.vox` file, only the chunks used to build the voxel data.
+#[derive(Debug)]
+pub(crate) struct VoxFile {
+  pub models: Vec<Model>,
+  /// Color of each color index, the index `0` is empty.
+  pub palette: [[u8; 4]; 256],
+  pub nodes: HashMap<i32, Node>,
+  pub hidden_layers: Vec<i32>,
+}
+
+impl VoxFile {
+  pub fn parse(bytes: &[u8]) -> Result<Self> {
+    let mut reader = Reader::new(bytes);
+    ensure!(reader.take(4)? == b"VOX ", "Not a MagicaVoxel file");
+    let _version = reader.i32()?;
+
+    let (id, _, mut children) = reader.chunk()?;
+    ensure!(id == b"MAIN", "Missing the MAIN chunk");
+
+    let mut file = Self {
+      models: vec![],
+      palette: default_palette(),
+      nodes: HashMap::new(),
+      hidden_layers: vec![],
+    };
+    let mut size = None;
+
+    while !children.is_empty() {
+      let (id, mut content, _) = children.chunk()?;
+
+      match id {
+        b"SIZE" => size = Some([content.i32()?, content.i32()?, content.i32()?]),
+        b"XYZI" => {
+          let size = size
+            .take()
+            .ok_or_else(|| anyhow!("XYZI chunk without SIZE"))?;
+          let count = content.len(4)?;
+          let voxels = (0..count)
+            .map(|_| Ok(content.take(4)?.try_into()?))
+            .collect::<Result<_>>()?;
+          file.models.push(Model { size, voxels });
+        }
+        b"RGBA" => {
+          // The colors are shifted by one, the index 0 is always empty
+          for index in 1..256 {
+            file.palette[index] = content.take(4)?.try_into()?;
+          }
+        }
+        b"nTRN" => {
+          let id = content.i32()?;
+          let attributes = content.dict()?;
+          let child = content.i32()?;
+          let _reserved = content.i32()?;
+          let layer = content.i32()?;
+          let frames = content.len(1)?;
+          ensure!(frames > 0, "Transform node {} without frames", id);
+          let frame = content.dict()?;
+
+          let rotation = match frame.get("_r") {
+            Some(rotation) => rotation.parse()?,
+            None => IDENTITY,
+          };
+          let translation = match frame.get("_t") {
+            Some(translation) => {
+              let values = translation
+                .split_whitespace()
+                .map(|value| value.parse())
+                .collect::<std::result::Result<Vec<i32>, _>>()?;
+              values
+                .try_into()
+                .map_err(|_| anyhow!("Invalid translation {:?}", translation))?
+            }
+            None => [0, 0, 0],
+          };
+
+          file.nodes.insert(
+            id,
+            Node::Transform {
+              child,
+              layer,
+              hidden: is_hidden(&attributes),
+              rotation,
+              translation,
+            },
+          );
+        }
+        b"nGRP" => {
+          let id = content.i32()?;
+          let _attributes = content.dict()?;
+          let count = content.len(4)?;
+          let children = (0..count).map(|_| content.i32()).collect::<Result<_>>()?;
+          file.nodes.insert(id, Node::Group { children });
+        }
+        b"nSHP" => {
+          let id = content.i32()?;
+          let _attributes = content.dict()?;
+          let count = content.len(8)?;
+          let mut models = Vec::with_capacity(count);
+          for _ in 0..count {
+            models.push(content.i32()?.try_into()?);
+            let _attributes = content.dict()?;
+          }
+          file.nodes.insert(id, Node::Shape { models });
+        }
+        b"LAYR" => {
+          let id = content.i32()?;
+          if is_hidden(&content.dict()?) {
+            file.hidden_layers.push(id);
+          }
+        }
+        // Materials, cameras, notes, etc.
+        _ => {}
+      }
+    }
+
+    Ok(file)
+  }
+}
+
+/// Rotation without changes, the first row points to x, the second to y and the third to z.
+pub(crate) const IDENTITY: u8 = 0b0000100;
+
+fn is_hidden(attributes: &Dict) -> bool {
+  attributes.get("_hidden").map(|hidden| hidden == "1") == Some(true)
+}
+
+// Palette used by MagicaVoxel when the file doesn't have the RGBA chunk: a 6x6x6 color cube
+// without black, followed by red, green, blue and gray ramps
+fn default_palette() -> [[u8; 4]; 256] {
+  const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+  const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+  let mut palette = [[0, 0, 0, 0]; 256];
+  let mut colors = palette.iter_mut().skip(1);
+
+  for &r in CUBE.iter() {
+    for &g in CUBE.iter() {
+      for &b in CUBE.iter() {
+        if r != 0 || g != 0 || b != 0 {
+          *colors.next().unwrap() = [r, g, b, 0xff];
+        }
+      }
+    }
+  }
+
+  for channel in 0..4 {
+    for &value in RAMP.iter() {
+      let mut color = [0, 0, 0, 0xff];
+      if channel == 3 {
+        color = [value, value, value, 0xff];
+      } else {
+        color[channel] = value;
+      }
+      *colors.next().unwrap() = color;
+    }
+  }
+
+  palette
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+  fn new(bytes: &'a [u8]) -> Self {
+    Self { bytes }
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bytes.is_empty()
+  }
+
+  fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+    ensure!(len <= self.bytes.len(), "Unexpected end of file");
+    let (taken, rest) = self.bytes.split_at(len);
+    self.bytes = rest;
+    Ok(taken)
+  }
+
+  fn i32(&mut self) -> Result<i32> {
+    Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+  }
+
+  /// Reads a count of items of `item_size` bytes, checking that they fit in the rest of the data.
+  fn len(&mut self, item_size: usize) -> Result<usize> {
+    let len: usize = self.i32()?.try_into()?;
+    ensure!(
+      len.saturating_mul(item_size) <= self.bytes.len(),
+      "Unexpected end of file"
+    );
+    Ok(len)
+  }
+
+  fn string(&mut self) -> Result<String> {
+    let len = self.len(1)?;
+    Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+  }
+
+  fn dict(&mut self) -> Result<Dict> {
+    let len = self.len(8)?;
+    (0..len)
+      .map(|_| Ok((self.string()?, self.string()?)))
+      .collect()
+  }
+
+  /// Returns the id, the content and the children of the next chunk.
+  fn chunk(&mut self) -> Result<(&'a [u8], Reader<'a>, Reader<'a>)> {
+    let id = self.take(4)?;
+    let content_len = self.i32()?.try_into()?;
+    let children_len = self.i32()?.try_into()?;
+    let content = Reader::new(self.take(content_len)?);
+    let children = Reader::new(self.take(children_len)?);
+
+    Ok((id, content, children))
+  }
+}
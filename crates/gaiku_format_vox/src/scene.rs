@@ -0,0 +1,149 @@
+use super::parser::{Node, VoxFile, IDENTITY};
+use anyhow::{anyhow, ensure, Result};
+
+/// Rotation and translation of a node in the MagicaVoxel axes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Transform {
+  rotation: [[i32; 3]; 3],
+  translation: [i32; 3],
+}
+
+impl Transform {
+  pub fn new(rotation: u8, translation: [i32; 3]) -> Result<Self> {
+    // The bits 0-1 and 2-3 are the column of the non zero entry of the first and second rows, the
+    // bits 4, 5 and 6 are the sign of each row
+    let first = (rotation & 3) as usize;
+    let second = ((rotation >> 2) & 3) as usize;
+    ensure!(
+      first < 3 && second < 3 && first != second,
+      "Invalid rotation {}",
+      rotation
+    );
+
+    let mut matrix = [[0; 3]; 3];
+    for (row, &column) in [first, second, 3 - first - second].iter().enumerate() {
+      matrix[row][column] = if rotation & (16 << row) != 0 { -1 } else { 1 };
+    }
+
+    Ok(Self {
+      rotation: matrix,
+      translation,
+    })
+  }
+
+  /// Applies `child` first and then this transform.
+  pub fn then(&self, child: &Transform) -> Self {
+    let mut rotation = [[0; 3]; 3];
+    for (row, rotation) in rotation.iter_mut().enumerate() {
+      for (column, rotation) in rotation.iter_mut().enumerate() {
+        *rotation = (0..3)
+          .map(|i| self.rotation[row][i] * child.rotation[i][column])
+          .sum();
+      }
+    }
+
+    let mut translation = self.translation;
+    for (row, translation) in translation.iter_mut().enumerate() {
+      *translation += (0..3)
+        .map(|i| self.rotation[row][i] * child.translation[i])
+        .sum::<i32>();
+    }
+
+    Self {
+      rotation,
+      translation,
+    }
+  }
+
+  /// Position of the voxel of a model of `size` once transformed.
+  ///
+  /// The models are centered on their pivot, so the voxel centers are rotated around it, this
+  /// keeps the mirrored models on the same voxels.
+  pub fn apply(&self, voxel: [i32; 3], size: [i32; 3]) -> [i32; 3] {
+    // Doubled, so the centers of the voxels are integers
+    let center = [
+      voxel[0] * 2 + 1 - size[0],
+      voxel[1] * 2 + 1 - size[1],
+      voxel[2] * 2 + 1 - size[2],
+    ];
+
+    let mut result = [0; 3];
+    for (row, result) in result.iter_mut().enumerate() {
+      let rotated: i32 = (0..3).map(|i| self.rotation[row][i] * center[i]).sum();
+      // The pivot is `size / 2`, rounding down the voxel corner on the even sizes
+      *result = rotated.div_euclid(2) + self.translation[row];
+    }
+
+    result
+  }
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Self::new(IDENTITY, [0, 0, 0]).unwrap()
+  }
+}
+
+/// Models placed in the scene with their transform, skipping the hidden nodes and layers.
+///
+/// The files without scene graph (older than MagicaVoxel 0.99) place every model at the origin.
+pub(crate) fn instances(file: &VoxFile) -> Result<Vec<(usize, Transform)>> {
+  let mut result = vec![];
+
+  if file.nodes.is_empty() {
+    for model in 0..file.models.len() {
+      result.push((model, Transform::default()));
+    }
+  } else {
+    visit(file, 0, Transform::default(), 0, &mut result)?;
+  }
+
+  for (model, _) in result.iter() {
+    ensure!(
+      *model < file.models.len(),
+      "Shape references the missing model {}",
+      model
+    );
+  }
+
+  Ok(result)
+}
+
+fn visit(
+  file: &VoxFile,
+  id: i32,
+  parent: Transform,
+  depth: usize,
+  result: &mut Vec<(usize, Transform)>,
+) -> Result<()> {
+  // Each level of the graph is a transform and a group or shape, so a cycle would never end
+  ensure!(depth <= file.nodes.len(), "The scene graph has a cycle");
+
+  match file.nodes.get(&id) {
+    Some(Node::Transform {
+      child,
+      layer,
+      hidden,
+      rotation,
+      translation,
+    }) => {
+      if !hidden && !file.hidden_layers.contains(layer) {
+        let transform = parent.then(&Transform::new(*rotation, *translation)?);
+        visit(file, *child, transform, depth + 1, result)?;
+      }
+    }
+    Some(Node::Group { children }) => {
+      for child in children.iter() {
+        visit(file, *child, parent, depth + 1, result)?;
+      }
+    }
+    Some(Node::Shape { models }) => {
+      for model in models.iter() {
+        result.push((*model, parent));
+      }
+    }
+    None => return Err(anyhow!("Missing scene node {}", id)),
+  }
+
+  Ok(())
+}
@@ -0,0 +1,91 @@
+use super::parser::Model;
+
+/// Serializes the models, each one placed with its translation under a single group.
+///
+/// The `palette` is indexed like the color indexes (the index `0` is empty), without it the file
+/// uses the default MagicaVoxel palette.
+pub(crate) fn write(models: &[(Model, [i32; 3])], palette: Option<&[[u8; 4]; 256]>) -> Vec<u8> {
+  let mut children = vec![];
+
+  for (model, _) in models.iter() {
+    chunk(&mut children, b"SIZE", &ints(&model.size));
+
+    let mut content = ints(&[model.voxels.len() as i32]);
+    content.extend(model.voxels.iter().flatten());
+    chunk(&mut children, b"XYZI", &content);
+  }
+
+  // Root transform and group, then a transform and shape for each model
+  let mut content = ints(&[0]);
+  content.extend(dict(&[]));
+  content.extend(ints(&[1, -1, -1, 1]));
+  content.extend(dict(&[]));
+  chunk(&mut children, b"nTRN", &content);
+
+  let mut content = ints(&[1]);
+  content.extend(dict(&[]));
+  content.extend(ints(&[models.len() as i32]));
+  content.extend(ints(
+    &(0..models.len() as i32)
+      .map(|index| 2 + index * 2)
+      .collect::<Vec<_>>(),
+  ));
+  chunk(&mut children, b"nGRP", &content);
+
+  for (index, (_, translation)) in models.iter().enumerate() {
+    let id = 2 + index as i32 * 2;
+    let translation = format!("{} {} {}", translation[0], translation[1], translation[2]);
+
+    let mut content = ints(&[id]);
+    content.extend(dict(&[]));
+    content.extend(ints(&[id + 1, -1, 0, 1]));
+    content.extend(dict(&[("_t", &translation)]));
+    chunk(&mut children, b"nTRN", &content);
+
+    let mut content = ints(&[id + 1]);
+    content.extend(dict(&[]));
+    content.extend(ints(&[1, index as i32]));
+    content.extend(dict(&[]));
+    chunk(&mut children, b"nSHP", &content);
+  }
+
+  if let Some(palette) = palette {
+    // The colors are shifted by one, the last one is never used
+    let mut content: Vec<u8> = palette[1..].iter().flatten().copied().collect();
+    content.extend(&[0; 4]);
+    chunk(&mut children, b"RGBA", &content);
+  }
+
+  let mut result = b"VOX ".to_vec();
+  result.extend(&150_i32.to_le_bytes());
+  result.extend(b"MAIN");
+  result.extend(&0_i32.to_le_bytes());
+  result.extend(&(children.len() as i32).to_le_bytes());
+  result.extend(children);
+  result
+}
+
+fn chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+  bytes.extend(id);
+  bytes.extend(&(content.len() as i32).to_le_bytes());
+  bytes.extend(&0_i32.to_le_bytes());
+  bytes.extend(content);
+}
+
+fn ints(values: &[i32]) -> Vec<u8> {
+  values
+    .iter()
+    .flat_map(|value| value.to_le_bytes())
+    .collect()
+}
+
+fn dict(entries: &[(&str, &str)]) -> Vec<u8> {
+  let mut result = ints(&[entries.len() as i32]);
+  for (key, value) in entries.iter() {
+    for string in [key, value].iter() {
+      result.extend(ints(&[string.len() as i32]));
+      result.extend(string.as_bytes());
+    }
+  }
+  result
+}
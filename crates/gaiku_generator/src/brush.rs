@@ -0,0 +1,342 @@
+use std::collections::HashSet;
+
+use gaiku_common::{
+  prelude::*,
+  world::{ChunkCoord, World},
+};
+
+use crate::sdf::{CsgOperation, Sdf, Transform};
+
+/// Voxels stamped around the shape on top of the smoothness, so the samples next to the surface
+/// get the exact distance too.
+const MARGIN: f32 = 2.0;
+
+/// A shape stamped into the chunks to edit them, building with `Union`, digging with `Subtract`
+/// or trimming with `Intersect`.
+///
+/// The densities of the chunks are used as signed distances, positive inside of the terrain, so
+/// a stamp combines them with the opposite of the shape distance. Only the samples within a couple
+/// of voxels and the `smoothness` of the shape are updated, the ones that don't change aren't
+/// written.
+#[derive(Clone, Debug)]
+pub struct Brush<S> {
+  pub shape: S,
+  pub transform: Transform,
+  pub operation: CsgOperation,
+  /// Width of the blend with the terrain, `0.0` for sharp edges.
+  pub smoothness: f32,
+  /// Atlas index of the samples where the shape is added, only used by the `Union`.
+  pub material: u8,
+}
+
+impl<S> Brush<S>
+where
+  S: Sdf,
+{
+  pub fn new(shape: S, transform: Transform, operation: CsgOperation) -> Self {
+    Self {
+      shape,
+      transform,
+      operation,
+      smoothness: 0.0,
+      material: 0,
+    }
+  }
+
+  pub fn with_smoothness(self, smoothness: f32) -> Self {
+    Self { smoothness, ..self }
+  }
+
+  pub fn with_material(self, material: u8) -> Self {
+    Self { material, ..self }
+  }
+
+  /// Stamps the brush into the world, returning the coordinates of the chunks that changed sorted.
+  ///
+  /// The `Union` creates the missing chunks it reaches, the other operations only edit the loaded
+  /// ones. The `Intersect` removes everything outside of the shape so it covers all of them, and
+  /// so do the unbounded shapes like the `Plane`.
+  ///
+  /// The changed chunks and the ones around them need their neighbor data updated before baking
+  /// them again.
+  pub fn stamp<C>(&self, world: &mut World<C>) -> Vec<ChunkCoord>
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+  {
+    let (min, max) = match (self.bounds(), world_bounds(world)) {
+      (Some(bounds), _) if self.operation != CsgOperation::Intersect => bounds,
+      (_, Some(bounds)) => bounds,
+      _ => return vec![],
+    };
+
+    let mut changed = HashSet::new();
+    for x in min[0]..=max[0] {
+      for y in min[1]..=max[1] {
+        for z in min[2]..=max[2] {
+          let (coord, _) = world.to_chunk_coord([x, y, z]);
+          if self.operation != CsgOperation::Union && !world.contains_chunk(coord) {
+            continue;
+          }
+
+          let point = [x as f32, y as f32, z as f32];
+          let (value, material) = self.apply(point, world.get(x, y, z));
+          if let Some(value) = value {
+            world.set(x, y, z, value);
+            changed.insert(coord);
+          }
+          if let Some(material) = material {
+            world.set_atlas(x, y, z, material);
+            changed.insert(coord);
+          }
+        }
+      }
+    }
+
+    let mut changed: Vec<_> = changed.into_iter().collect();
+    changed.sort_unstable();
+    changed
+  }
+
+  /// Stamps the brush into a single chunk, at its `position()`, returning if anything changed.
+  pub fn stamp_chunk<C>(&self, chunk: &mut C) -> bool
+  where
+    C: Chunkify<f32> + ChunkifyMut<f32> + AtlasifyMut<u8> + Boxify,
+  {
+    let position = chunk.position();
+    let size = [
+      chunk.width() as i32,
+      chunk.height() as i32,
+      chunk.depth() as i32,
+    ];
+
+    // The chunk samples inside of the brush bounds, local to the chunk
+    let mut min = [0; 3];
+    let mut max = [size[0] - 1, size[1] - 1, size[2] - 1];
+    if self.operation != CsgOperation::Intersect {
+      if let Some((bounds_min, bounds_max)) = self.bounds() {
+        for axis in 0..3 {
+          let origin = position[axis].floor() as i32;
+          min[axis] = min[axis].max(bounds_min[axis] - origin);
+          max[axis] = max[axis].min(bounds_max[axis] - origin);
+        }
+      }
+    }
+
+    let mut changed = false;
+    for x in min[0]..=max[0] {
+      for y in min[1]..=max[1] {
+        for z in min[2]..=max[2] {
+          let (x, y, z) = (x as usize, y as usize, z as usize);
+          let point = [
+            position[0] + x as f32,
+            position[1] + y as f32,
+            position[2] + z as f32,
+          ];
+
+          let (value, material) = self.apply(point, chunk.get(x, y, z));
+          if let Some(value) = value {
+            chunk.set(x, y, z, value);
+            changed = true;
+          }
+          if let Some(material) = material {
+            chunk.set_atlas(x, y, z, material);
+            changed = true;
+          }
+        }
+      }
+    }
+
+    changed
+  }
+
+  /// Min and max voxels that the brush can change, `None` if the shape is unbounded.
+  fn bounds(&self) -> Option<([i32; 3], [i32; 3])> {
+    let (min, max) = self.shape.bounds();
+    if min.iter().chain(max.iter()).any(|value| !value.is_finite()) {
+      return None;
+    }
+
+    // The box containing the transformed corners
+    let margin = self.smoothness.max(0.0) + MARGIN;
+    let mut voxel_min = [i32::MAX; 3];
+    let mut voxel_max = [i32::MIN; 3];
+    for corner in 0..8 {
+      let point = self.transform.apply([
+        if corner & 1 == 0 { min[0] } else { max[0] },
+        if corner & 2 == 0 { min[1] } else { max[1] },
+        if corner & 4 == 0 { min[2] } else { max[2] },
+      ]);
+      for axis in 0..3 {
+        voxel_min[axis] = voxel_min[axis].min((point[axis] - margin).floor() as i32);
+        voxel_max[axis] = voxel_max[axis].max((point[axis] + margin).ceil() as i32);
+      }
+    }
+
+    Some((voxel_min, voxel_max))
+  }
+
+  /// The new density and atlas index of the sample, `None` where they don't change.
+  fn apply(&self, point: [f32; 3], density: f32) -> (Option<f32>, Option<u8>) {
+    let local = self.transform.inverse_apply(point);
+    let distance = self.shape.distance(local) * self.transform.scale;
+    let value = -self.operation.apply(-density, distance, self.smoothness);
+
+    let value = if (value - density).abs() > f32::EPSILON {
+      Some(value)
+    } else {
+      None
+    };
+    // Where the shape is closer to the surface than the terrain
+    let material = if self.operation == CsgOperation::Union && distance < -density {
+      Some(self.material)
+    } else {
+      None
+    };
+
+    (value, material)
+  }
+}
+
+/// Min and max voxels of the loaded chunks, `None` if there are none.
+fn world_bounds<C>(world: &World<C>) -> Option<([i32; 3], [i32; 3])> {
+  let size = world.chunk_size();
+  let mut coords = world.coords();
+  let first = *coords.next()?;
+  let (mut min, mut max) = (first, first);
+
+  for coord in coords {
+    for axis in 0..3 {
+      min[axis] = min[axis].min(coord[axis]);
+      max[axis] = max[axis].max(coord[axis]);
+    }
+  }
+
+  for axis in 0..3 {
+    min[axis] *= size[axis] as i32;
+    max[axis] = (max[axis] + 1) * size[axis] as i32 - 1;
+  }
+
+  Some((min, max))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::sdf::{Cuboid, Plane, Sphere};
+  use gaiku_common::chunk::Chunk;
+
+  #[test]
+  fn test_stamp() {
+    let mut world = World::<Chunk>::new([8, 8, 8]);
+
+    // Centered on the corner of 8 chunks
+    let brush = Brush::new(
+      Sphere { radius: 3.0 },
+      Transform::from_translation([8.0, 8.0, 8.0]),
+      CsgOperation::Union,
+    )
+    .with_material(2);
+    let changed = brush.stamp(&mut world);
+
+    assert_eq!(changed.len(), 8);
+    assert_eq!(changed[0], [0, 0, 0]);
+    assert_eq!(changed[7], [1, 1, 1]);
+    assert_eq!(world.len(), 8);
+    assert_eq!(world.get(8, 8, 8), 3.0);
+    assert_eq!(world.get(7, 8, 8), 2.0);
+    assert_eq!(world.get_atlas(7, 7, 7), 2);
+    assert!(world.is_air(8, 8, 12, 0.0));
+    assert_eq!(world.get(8, 8, 15), -1.0);
+
+    // Stamping it again changes nothing
+    assert!(brush.stamp(&mut world).is_empty());
+
+    // Digs a hole in the middle
+    let dig = Brush::new(
+      Cuboid {
+        half_size: [1.0; 3],
+      },
+      Transform::from_translation([8.0, 8.0, 8.0]),
+      CsgOperation::Subtract,
+    );
+    let changed = dig.stamp(&mut world);
+    assert_eq!(changed.len(), 8);
+    assert!(world.is_air(8, 8, 8, 0.0));
+    assert!(world.is_air(7, 7, 7, 0.0));
+    assert!(!world.is_air(8, 8, 10, 0.0));
+
+    // Doesn't create chunks away from the terrain
+    let dig = Brush::new(
+      Sphere { radius: 3.0 },
+      Transform::from_translation([40.0, 8.0, 8.0]),
+      CsgOperation::Subtract,
+    );
+    assert!(dig.stamp(&mut world).is_empty());
+    assert_eq!(world.len(), 8);
+  }
+
+  #[test]
+  fn test_stamp_intersect() {
+    let mut world = World::<Chunk>::new([8, 8, 8]);
+    Brush::new(
+      Plane {
+        normal: [0.0, 1.0, 0.0],
+        offset: 4.0,
+      },
+      Transform::default(),
+      CsgOperation::Union,
+    )
+    .stamp(&mut world);
+    assert!(world.is_empty());
+
+    world.get_or_create_chunk_mut([0, 0, 0]);
+    world.get_or_create_chunk_mut([1, 0, 0]);
+    Brush::new(
+      Plane {
+        normal: [0.0, 1.0, 0.0],
+        offset: 4.0,
+      },
+      Transform::default(),
+      CsgOperation::Union,
+    )
+    .stamp(&mut world);
+    assert!(!world.is_air(12, 3, 4, 0.0));
+    assert!(world.is_air(12, 5, 4, 0.0));
+
+    // Keeps the ground inside of the sphere only
+    let changed = Brush::new(
+      Sphere { radius: 2.5 },
+      Transform::from_translation([4.0, 4.0, 4.0]),
+      CsgOperation::Intersect,
+    )
+    .stamp(&mut world);
+    assert_eq!(changed, vec![[0, 0, 0], [1, 0, 0]]);
+    assert!(!world.is_air(4, 3, 4, 0.0));
+    assert!(world.is_air(12, 3, 4, 0.0));
+  }
+
+  #[test]
+  fn test_stamp_chunk() {
+    let mut chunk = Chunk::new([8.0, 0.0, 0.0], 8, 8, 8);
+    let brush = Brush::new(
+      Sphere { radius: 2.0 },
+      Transform::from_translation([9.0, 1.0, 1.0]),
+      CsgOperation::Union,
+    )
+    .with_smoothness(1.0)
+    .with_material(1);
+
+    assert!(brush.stamp_chunk(&mut chunk));
+    assert_eq!(chunk.get(1, 1, 1), 2.0);
+    assert_eq!(chunk.get_atlas(0, 1, 1), 1);
+    assert_eq!(chunk.get(7, 7, 7), -1.0);
+
+    // Away from the chunk
+    let brush = Brush::new(
+      Sphere { radius: 2.0 },
+      Transform::from_translation([-9.0, 1.0, 1.0]),
+      CsgOperation::Union,
+    );
+    assert!(!brush.stamp_chunk(&mut chunk));
+  }
+}
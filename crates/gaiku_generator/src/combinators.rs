@@ -0,0 +1,222 @@
+use crate::Noise;
+
+/// How each octave of a `Fractal` transforms the noise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FractalKind {
+  /// Fractal Brownian motion, the noise as it is.
+  Fbm,
+  /// The inverted absolute noise, sharp ridges like mountain ranges on `1.0`.
+  Ridged,
+  /// The absolute noise, round bumps like clouds or hills.
+  Billow,
+}
+
+/// Sum of octaves of the noise with increasing frequency and decreasing amplitude, normalized so
+/// it keeps the `-1.0` to `1.0` range of the noise.
+#[derive(Clone, Debug)]
+pub struct Fractal<N> {
+  pub noise: N,
+  pub kind: FractalKind,
+  pub octaves: usize,
+  /// Frequency of the first octave, the features are about `1.0 / frequency` units wide.
+  pub frequency: f32,
+  /// Frequency multiplier of each octave.
+  pub lacunarity: f32,
+  /// Amplitude multiplier of each octave.
+  pub persistence: f32,
+}
+
+impl<N> Fractal<N> {
+  /// 4 octaves with features about 32 units wide.
+  pub fn new(noise: N, kind: FractalKind) -> Self {
+    Self {
+      noise,
+      kind,
+      octaves: 4,
+      frequency: 1.0 / 32.0,
+      lacunarity: 2.0,
+      persistence: 0.5,
+    }
+  }
+
+  pub fn fbm(noise: N) -> Self {
+    Self::new(noise, FractalKind::Fbm)
+  }
+
+  pub fn ridged(noise: N) -> Self {
+    Self::new(noise, FractalKind::Ridged)
+  }
+
+  pub fn billow(noise: N) -> Self {
+    Self::new(noise, FractalKind::Billow)
+  }
+}
+
+impl<N> Noise for Fractal<N>
+where
+  N: Noise,
+{
+  fn get(&self, point: [f32; 3]) -> f32 {
+    let mut frequency = self.frequency;
+    let mut amplitude = 1.0;
+    let mut total = 0.0;
+    let mut value = 0.0;
+
+    for index in 0..self.octaves {
+      // Shifted so the octaves don't share the zeros of the lattice on the origin
+      let shift = index as f32 * 17.31;
+      let octave = self.noise.get([
+        point[0] * frequency + shift,
+        point[1] * frequency + shift,
+        point[2] * frequency + shift,
+      ]);
+
+      value += amplitude
+        * match self.kind {
+          FractalKind::Fbm => octave,
+          FractalKind::Ridged => (1.0 - octave.abs()).powi(2) * 2.0 - 1.0,
+          FractalKind::Billow => octave.abs() * 2.0 - 1.0,
+        };
+      total += amplitude;
+      frequency *= self.lacunarity;
+      amplitude *= self.persistence;
+    }
+
+    if total > 0.0 {
+      value / total
+    } else {
+      0.0
+    }
+  }
+}
+
+/// Domain warping, displaces the point by the `warp` noise before sampling the `noise`, to bend
+/// the features into more organic shapes.
+#[derive(Clone, Debug)]
+pub struct Warp<N, W> {
+  pub noise: N,
+  pub warp: W,
+  /// Max displacement on each axis, in units.
+  pub strength: f32,
+}
+
+impl<N, W> Warp<N, W> {
+  pub fn new(noise: N, warp: W, strength: f32) -> Self {
+    Self {
+      noise,
+      warp,
+      strength,
+    }
+  }
+}
+
+impl<N, W> Noise for Warp<N, W>
+where
+  N: Noise,
+  W: Noise,
+{
+  fn get(&self, point: [f32; 3]) -> f32 {
+    // The axes sample the warp far from each other so they aren't correlated
+    let offset = |axis: f32| {
+      self.warp.get([
+        point[0] + axis * 71.3,
+        point[1] + axis * 31.7,
+        point[2] + axis * 53.9,
+      ]) * self.strength
+    };
+
+    self.noise.get([
+      point[0] + offset(0.0),
+      point[1] + offset(1.0),
+      point[2] + offset(2.0),
+    ])
+  }
+}
+
+/// Terrain density from a 2d height, the noise sampled on the `y = 0` plane is the height of the
+/// surface, positive below it and negative above it.
+#[derive(Clone, Debug)]
+pub struct HeightField<N> {
+  pub noise: N,
+  /// Height of the surface where the noise is `0.0`.
+  pub base_height: f32,
+  /// Height of the surface over the `base_height` where the noise is `1.0`.
+  pub amplitude: f32,
+}
+
+impl<N> HeightField<N> {
+  pub fn new(noise: N, base_height: f32, amplitude: f32) -> Self {
+    Self {
+      noise,
+      base_height,
+      amplitude,
+    }
+  }
+}
+
+impl<N> Noise for HeightField<N>
+where
+  N: Noise,
+{
+  fn get(&self, point: [f32; 3]) -> f32 {
+    let height = self.base_height + self.noise.get([point[0], 0.0, point[2]]) * self.amplitude;
+    height - point[1]
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{Perlin, Simplex};
+
+  #[test]
+  fn test_fractals() {
+    let point = [10.5, -3.25, 7.75];
+    let simplex = Simplex::new(3);
+
+    // One octave is the noise scaled by the frequency
+    let fbm = Fractal {
+      octaves: 1,
+      ..Fractal::fbm(simplex.clone())
+    };
+    assert_eq!(
+      fbm.get(point),
+      simplex.get([10.5 / 32.0, -3.25 / 32.0, 7.75 / 32.0])
+    );
+
+    for x in 0..100 {
+      let point = [x as f32 * 3.7, x as f32 * -1.3, x as f32 * 0.7];
+      for value in [
+        Fractal::fbm(simplex.clone()).get(point),
+        Fractal::ridged(simplex.clone()).get(point),
+        Fractal::billow(simplex.clone()).get(point),
+      ]
+      .iter()
+      {
+        assert!((-1.1..=1.1).contains(value));
+      }
+    }
+  }
+
+  #[test]
+  fn test_warp() {
+    let perlin = Perlin::new(5);
+    let point = [1.3, 2.7, -4.1];
+
+    let warp = Warp::new(perlin.clone(), |_: [f32; 3]| 1.0, 0.5);
+    assert_eq!(warp.get(point), perlin.get([1.8, 3.2, -3.6]));
+
+    let warp = Warp::new(perlin.clone(), Simplex::new(6), 0.0);
+    assert_eq!(warp.get(point), perlin.get(point));
+  }
+
+  #[test]
+  fn test_height_field() {
+    let field = HeightField::new(|point: [f32; 3]| point[0] / 10.0, 4.0, 10.0);
+
+    // The surface is at y = 4 + x
+    assert_eq!(field.get([2.0, 6.0, 9.0]), 0.0);
+    assert!(field.get([2.0, 1.0, 9.0]) > 0.0);
+    assert!(field.get([2.0, 8.0, 9.0]) < 0.0);
+  }
+}
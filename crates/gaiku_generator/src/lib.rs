@@ -0,0 +1,106 @@
+//! `gaiku_generator` fills the chunks with procedural density, built from seeded noise
+//! primitives and combinators.
+//!
+//! The densities follow the bakers, positive inside of the terrain and negative outside, so with
+//! the default isovalue the surface is where they cross zero.
+//!
+//! The `Brush` stamps signed distance shapes into the chunks, to dig, build or smooth the
+//! terrain once it's generated.
+use gaiku_common::prelude::*;
+
+mod brush;
+mod combinators;
+mod noise;
+mod sdf;
+
+pub use self::{
+  brush::Brush,
+  combinators::{Fractal, FractalKind, HeightField, Warp},
+  noise::{Perlin, Simplex, Worley},
+  sdf::{
+    Capsule, Csg, CsgOperation, Cuboid, Cylinder, Plane, Sdf, Sphere, Torus, Transform, Transformed,
+  },
+};
+
+/// Scalar field sampled at world positions, the noises go from about `-1.0` to `1.0`.
+///
+/// The closures taking a position implement it too, to combine the noises in custom ways.
+pub trait Noise {
+  fn get(&self, point: [f32; 3]) -> f32;
+}
+
+impl<F> Noise for F
+where
+  F: Fn([f32; 3]) -> f32,
+{
+  fn get(&self, point: [f32; 3]) -> f32 {
+    self(point)
+  }
+}
+
+/// Fills the chunk with the `density` of each sample, at the `position()` of the chunk plus the
+/// sample coordinates. The atlas indexes are set to `0`.
+pub fn fill<C, D>(chunk: &mut C, density: &D)
+where
+  C: Boxify + ChunkifyMut<f32> + AtlasifyMut<u8>,
+  D: Noise,
+{
+  fill_with_materials(chunk, density, |_, _| 0);
+}
+
+/// Like `fill`, with the atlas index of each sample picked by `material` from its world position
+/// and density.
+pub fn fill_with_materials<C, D, M>(chunk: &mut C, density: &D, material: M)
+where
+  C: Boxify + ChunkifyMut<f32> + AtlasifyMut<u8>,
+  D: Noise,
+  M: Fn([f32; 3], f32) -> u8,
+{
+  let position = chunk.position();
+
+  for x in 0..chunk.width() as usize {
+    for y in 0..chunk.height() as usize {
+      for z in 0..chunk.depth() as usize {
+        let point = [
+          position[0] + x as f32,
+          position[1] + y as f32,
+          position[2] + z as f32,
+        ];
+        let value = density.get(point);
+
+        chunk.set(x, y, z, value);
+        chunk.set_atlas(x, y, z, material(point, value));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use gaiku_common::chunk::Chunk;
+
+  #[test]
+  fn test_fill() {
+    let density = HeightField::new(Fractal::fbm(Simplex::new(1)), 8.0, 4.0);
+    let mut chunk = Chunk::new([16.0, 0.0, -16.0], 4, 16, 4);
+
+    fill_with_materials(
+      &mut chunk,
+      &density,
+      |point, _| {
+        if point[1] > 8.0 {
+          1
+        } else {
+          2
+        }
+      },
+    );
+
+    assert_eq!(chunk.get(1, 2, 3), density.get([17.0, 2.0, -13.0]));
+    assert!(!chunk.is_air(1, 0, 3, 0.0));
+    assert!(chunk.is_air(1, 15, 3, 0.0));
+    assert_eq!(chunk.get_atlas(0, 9, 0), 1);
+    assert_eq!(chunk.get_atlas(0, 8, 0), 2);
+  }
+}
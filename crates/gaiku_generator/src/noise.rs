@@ -0,0 +1,293 @@
+use crate::Noise;
+
+/// Classic improved Perlin gradient noise, from about `-1.0` to `1.0` and `0.0` on the integer
+/// coordinates.
+#[derive(Clone, Debug)]
+pub struct Perlin {
+  permutation: Vec<u8>,
+}
+
+impl Perlin {
+  pub fn new(seed: u64) -> Self {
+    Self {
+      permutation: permutation(seed),
+    }
+  }
+}
+
+impl Noise for Perlin {
+  fn get(&self, point: [f32; 3]) -> f32 {
+    let p = &self.permutation;
+    let [x, y, z] = point;
+    let (xi, yi, zi) = (
+      x.floor() as i32 as usize & 255,
+      y.floor() as i32 as usize & 255,
+      z.floor() as i32 as usize & 255,
+    );
+    let (x, y, z) = (x - x.floor(), y - y.floor(), z - z.floor());
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let a = p[xi] as usize + yi;
+    let (aa, ab) = (p[a] as usize + zi, p[a + 1] as usize + zi);
+    let b = p[xi + 1] as usize + yi;
+    let (ba, bb) = (p[b] as usize + zi, p[b + 1] as usize + zi);
+
+    lerp(
+      w,
+      lerp(
+        v,
+        lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+        lerp(
+          u,
+          grad(p[ab], x, y - 1.0, z),
+          grad(p[bb], x - 1.0, y - 1.0, z),
+        ),
+      ),
+      lerp(
+        v,
+        lerp(
+          u,
+          grad(p[aa + 1], x, y, z - 1.0),
+          grad(p[ba + 1], x - 1.0, y, z - 1.0),
+        ),
+        lerp(
+          u,
+          grad(p[ab + 1], x, y - 1.0, z - 1.0),
+          grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+        ),
+      ),
+    )
+  }
+}
+
+/// Simplex noise, from `-1.0` to `1.0`. Smoother and with less axis aligned artifacts than
+/// `Perlin`.
+#[derive(Clone, Debug)]
+pub struct Simplex {
+  permutation: Vec<u8>,
+}
+
+impl Simplex {
+  pub fn new(seed: u64) -> Self {
+    Self {
+      permutation: permutation(seed),
+    }
+  }
+}
+
+impl Noise for Simplex {
+  fn get(&self, point: [f32; 3]) -> f32 {
+    const SKEW: f32 = 1.0 / 3.0;
+    const UNSKEW: f32 = 1.0 / 6.0;
+
+    let p = &self.permutation;
+    let [x, y, z] = point;
+
+    // The simplex cell of the point and the offsets of its corners
+    let skew = (x + y + z) * SKEW;
+    let (i, j, k) = ((x + skew).floor(), (y + skew).floor(), (z + skew).floor());
+    let unskew = (i + j + k) * UNSKEW;
+    let first = [x - i + unskew, y - j + unskew, z - k + unskew];
+
+    let (second, third) = if first[0] >= first[1] {
+      if first[1] >= first[2] {
+        ([1, 0, 0], [1, 1, 0])
+      } else if first[0] >= first[2] {
+        ([1, 0, 0], [1, 0, 1])
+      } else {
+        ([0, 0, 1], [1, 0, 1])
+      }
+    } else if first[1] < first[2] {
+      ([0, 0, 1], [0, 1, 1])
+    } else if first[0] < first[2] {
+      ([0, 1, 0], [0, 1, 1])
+    } else {
+      ([0, 1, 0], [1, 1, 0])
+    };
+
+    let (i, j, k) = (
+      i as i32 as usize & 255,
+      j as i32 as usize & 255,
+      k as i32 as usize & 255,
+    );
+
+    [[0, 0, 0], second, third, [1, 1, 1]]
+      .iter()
+      .enumerate()
+      .map(|(corner, offset)| {
+        let distance = [
+          first[0] - offset[0] as f32 + corner as f32 * UNSKEW,
+          first[1] - offset[1] as f32 + corner as f32 * UNSKEW,
+          first[2] - offset[2] as f32 + corner as f32 * UNSKEW,
+        ];
+        let falloff =
+          0.6 - distance[0] * distance[0] - distance[1] * distance[1] - distance[2] * distance[2];
+        if falloff < 0.0 {
+          return 0.0;
+        }
+
+        let hash = p[i + offset[0] + p[j + offset[1] + p[k + offset[2]] as usize] as usize];
+        falloff.powi(4) * grad(hash, distance[0], distance[1], distance[2])
+      })
+      .sum::<f32>()
+      * 32.0
+  }
+}
+
+/// Cellular noise, the distance to the closest of the random points scattered one on each unit
+/// cube. Goes from `-1.0` on the points to `1.0` at one unit or more from them.
+#[derive(Clone, Debug)]
+pub struct Worley {
+  seed: u64,
+}
+
+impl Worley {
+  pub fn new(seed: u64) -> Self {
+    Self { seed }
+  }
+
+  fn feature_point(&self, cell: [i32; 3]) -> [f32; 3] {
+    let mut random = Random(
+      self.seed
+        ^ (cell[0] as u64).wrapping_mul(0x8da6_b343)
+        ^ (cell[1] as u64).wrapping_mul(0xd816_3841)
+        ^ (cell[2] as u64).wrapping_mul(0xcb1a_b31f),
+    );
+
+    [
+      cell[0] as f32 + random.next(),
+      cell[1] as f32 + random.next(),
+      cell[2] as f32 + random.next(),
+    ]
+  }
+}
+
+impl Noise for Worley {
+  fn get(&self, point: [f32; 3]) -> f32 {
+    let cell = [
+      point[0].floor() as i32,
+      point[1].floor() as i32,
+      point[2].floor() as i32,
+    ];
+    let mut closest = f32::MAX;
+
+    for x in -1..=1 {
+      for y in -1..=1 {
+        for z in -1..=1 {
+          let feature = self.feature_point([cell[0] + x, cell[1] + y, cell[2] + z]);
+          let distance = (0..3)
+            .map(|axis| (feature[axis] - point[axis]).powi(2))
+            .sum::<f32>();
+          closest = closest.min(distance);
+        }
+      }
+    }
+
+    closest.sqrt().min(1.0) * 2.0 - 1.0
+  }
+}
+
+/// SplitMix64, small and good enough for the permutations and feature points.
+pub(crate) struct Random(pub u64);
+
+impl Random {
+  fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut value = self.0;
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+  }
+
+  /// From `0.0` (included) to `1.0` (excluded).
+  pub fn next(&mut self) -> f32 {
+    (self.next_u64() >> 40) as f32 / (1_u64 << 24) as f32
+  }
+}
+
+/// Shuffled `0..256`, repeated so the hashes of the neighbor cells don't need to wrap.
+fn permutation(seed: u64) -> Vec<u8> {
+  let mut random = Random(seed);
+  let mut permutation: Vec<u8> = (0..=255).collect();
+
+  for index in (1..permutation.len()).rev() {
+    let other = (random.next_u64() % (index as u64 + 1)) as usize;
+    permutation.swap(index, other);
+  }
+
+  permutation.extend_from_within(..);
+  permutation
+}
+
+fn fade(t: f32) -> f32 {
+  t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+  a + t * (b - a)
+}
+
+// One of the 12 directions to the edges of a cube, from the low bits of the hash
+fn grad(hash: u8, x: f32, y: f32, z: f32) -> f32 {
+  let hash = hash & 15;
+  let u = if hash < 8 { x } else { y };
+  let v = if hash < 4 {
+    y
+  } else if hash == 12 || hash == 14 {
+    x
+  } else {
+    z
+  };
+
+  (if hash & 1 == 0 { u } else { -u }) + (if hash & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn points() -> impl Iterator<Item = [f32; 3]> {
+    let mut random = Random(42);
+    (0..2000).map(move |_| {
+      [
+        random.next() * 64.0 - 32.0,
+        random.next() * 64.0 - 32.0,
+        random.next() * 64.0 - 32.0,
+      ]
+    })
+  }
+
+  fn check<N: Noise>(first: N, second: N, other: N) {
+    let mut differences = 0;
+
+    for point in points() {
+      let value = first.get(point);
+      assert!((-1.1..=1.1).contains(&value), "{} at {:?}", value, point);
+      assert_eq!(value, second.get(point));
+      if (value - other.get(point)).abs() > 1e-3 {
+        differences += 1;
+      }
+    }
+
+    assert!(differences > 1000);
+  }
+
+  #[test]
+  fn test_perlin() {
+    check(Perlin::new(1), Perlin::new(1), Perlin::new(2));
+    assert_eq!(Perlin::new(1).get([3.0, -5.0, 7.0]), 0.0);
+  }
+
+  #[test]
+  fn test_simplex() {
+    check(Simplex::new(1), Simplex::new(1), Simplex::new(2));
+  }
+
+  #[test]
+  fn test_worley() {
+    check(Worley::new(1), Worley::new(1), Worley::new(2));
+
+    let worley = Worley::new(1);
+    assert_eq!(worley.get(worley.feature_point([2, -3, 4])), -1.0);
+  }
+}
@@ -0,0 +1,521 @@
+/// Signed distance field, negative inside of the shape, positive outside and `0.0` on its
+/// surface. The densities of the chunks are the opposite, see `Brush`.
+pub trait Sdf {
+  fn distance(&self, point: [f32; 3]) -> f32;
+
+  /// Min and max corners of the box containing the shape, infinite for the unbounded ones.
+  fn bounds(&self) -> ([f32; 3], [f32; 3]);
+}
+
+/// Sphere centered on the origin.
+#[derive(Clone, Debug)]
+pub struct Sphere {
+  pub radius: f32,
+}
+
+impl Sdf for Sphere {
+  fn distance(&self, point: [f32; 3]) -> f32 {
+    length(point) - self.radius
+  }
+
+  fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    ([-self.radius; 3], [self.radius; 3])
+  }
+}
+
+/// Box centered on the origin, from `-half_size` to `half_size`.
+#[derive(Clone, Debug)]
+pub struct Cuboid {
+  pub half_size: [f32; 3],
+}
+
+impl Sdf for Cuboid {
+  fn distance(&self, point: [f32; 3]) -> f32 {
+    let q = [
+      point[0].abs() - self.half_size[0],
+      point[1].abs() - self.half_size[1],
+      point[2].abs() - self.half_size[2],
+    ];
+    let outside = length([q[0].max(0.0), q[1].max(0.0), q[2].max(0.0)]);
+    let inside = q[0].max(q[1]).max(q[2]).min(0.0);
+    outside + inside
+  }
+
+  fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    (
+      [-self.half_size[0], -self.half_size[1], -self.half_size[2]],
+      self.half_size,
+    )
+  }
+}
+
+/// Segment from `start` to `end` with rounded ends.
+#[derive(Clone, Debug)]
+pub struct Capsule {
+  pub start: [f32; 3],
+  pub end: [f32; 3],
+  pub radius: f32,
+}
+
+impl Sdf for Capsule {
+  fn distance(&self, point: [f32; 3]) -> f32 {
+    let to_point = sub(point, self.start);
+    let segment = sub(self.end, self.start);
+    let length_squared = dot(segment, segment);
+    let t = if length_squared > 0.0 {
+      (dot(to_point, segment) / length_squared).clamp(0.0, 1.0)
+    } else {
+      0.0
+    };
+
+    length(sub(to_point, scale(segment, t))) - self.radius
+  }
+
+  fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    let mut min = [0.0; 3];
+    let mut max = [0.0; 3];
+    for axis in 0..3 {
+      min[axis] = self.start[axis].min(self.end[axis]) - self.radius;
+      max[axis] = self.start[axis].max(self.end[axis]) + self.radius;
+    }
+    (min, max)
+  }
+}
+
+/// Cylinder centered on the origin, along the y axis.
+#[derive(Clone, Debug)]
+pub struct Cylinder {
+  pub height: f32,
+  pub radius: f32,
+}
+
+impl Sdf for Cylinder {
+  fn distance(&self, point: [f32; 3]) -> f32 {
+    let radial = (point[0] * point[0] + point[2] * point[2]).sqrt() - self.radius;
+    let vertical = point[1].abs() - self.height / 2.0;
+    let outside = (radial.max(0.0).powi(2) + vertical.max(0.0).powi(2)).sqrt();
+    outside + radial.max(vertical).min(0.0)
+  }
+
+  fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    (
+      [-self.radius, -self.height / 2.0, -self.radius],
+      [self.radius, self.height / 2.0, self.radius],
+    )
+  }
+}
+
+/// Torus centered on the origin, lying on the xz plane.
+#[derive(Clone, Debug)]
+pub struct Torus {
+  /// Distance from the center to the center of the tube.
+  pub major_radius: f32,
+  /// Radius of the tube.
+  pub minor_radius: f32,
+}
+
+impl Sdf for Torus {
+  fn distance(&self, point: [f32; 3]) -> f32 {
+    let radial = (point[0] * point[0] + point[2] * point[2]).sqrt() - self.major_radius;
+    (radial * radial + point[1] * point[1]).sqrt() - self.minor_radius
+  }
+
+  fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    let radius = self.major_radius + self.minor_radius;
+    (
+      [-radius, -self.minor_radius, -radius],
+      [radius, self.minor_radius, radius],
+    )
+  }
+}
+
+/// Half space below the plane, the points where `dot(point, normal) < offset`. The `normal`
+/// doesn't need to be normalized.
+#[derive(Clone, Debug)]
+pub struct Plane {
+  pub normal: [f32; 3],
+  pub offset: f32,
+}
+
+impl Sdf for Plane {
+  fn distance(&self, point: [f32; 3]) -> f32 {
+    let normal_length = length(self.normal);
+    dot(point, self.normal) / normal_length - self.offset
+  }
+
+  fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    ([f32::NEG_INFINITY; 3], [f32::INFINITY; 3])
+  }
+}
+
+/// Boolean operations of the `Csg` shapes and the brushes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOperation {
+  /// Both shapes.
+  Union,
+  /// The first shape without the second one.
+  Subtract,
+  /// Only where both shapes are.
+  Intersect,
+}
+
+impl CsgOperation {
+  /// Combines the distances, blending them over `smoothness` units when it's greater than `0.0`.
+  pub fn apply(self, a: f32, b: f32, smoothness: f32) -> f32 {
+    match self {
+      CsgOperation::Union => smooth_min(a, b, smoothness),
+      CsgOperation::Subtract => -smooth_min(-a, b, smoothness),
+      CsgOperation::Intersect => -smooth_min(-a, -b, smoothness),
+    }
+  }
+}
+
+/// Two shapes combined with a boolean operation.
+#[derive(Clone, Debug)]
+pub struct Csg<A, B> {
+  pub a: A,
+  pub b: B,
+  pub operation: CsgOperation,
+  /// Width of the blend between the shapes, `0.0` for sharp edges.
+  pub smoothness: f32,
+}
+
+impl<A, B> Csg<A, B> {
+  pub fn new(a: A, b: B, operation: CsgOperation) -> Self {
+    Self {
+      a,
+      b,
+      operation,
+      smoothness: 0.0,
+    }
+  }
+
+  pub fn smooth(a: A, b: B, operation: CsgOperation, smoothness: f32) -> Self {
+    Self {
+      a,
+      b,
+      operation,
+      smoothness,
+    }
+  }
+}
+
+impl<A, B> Sdf for Csg<A, B>
+where
+  A: Sdf,
+  B: Sdf,
+{
+  fn distance(&self, point: [f32; 3]) -> f32 {
+    self.operation.apply(
+      self.a.distance(point),
+      self.b.distance(point),
+      self.smoothness,
+    )
+  }
+
+  fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    let (a_min, a_max) = self.a.bounds();
+    let (b_min, b_max) = self.b.bounds();
+    let mut min = a_min;
+    let mut max = a_max;
+
+    for axis in 0..3 {
+      match self.operation {
+        // The blend can add material between the shapes
+        CsgOperation::Union => {
+          min[axis] = a_min[axis].min(b_min[axis]) - self.smoothness;
+          max[axis] = a_max[axis].max(b_max[axis]) + self.smoothness;
+        }
+        CsgOperation::Subtract => {}
+        CsgOperation::Intersect => {
+          min[axis] = a_min[axis].max(b_min[axis]);
+          max[axis] = a_max[axis].min(b_max[axis]);
+        }
+      }
+    }
+
+    (min, max)
+  }
+}
+
+/// Translation, rotation and uniform scale, applied in the order scale, rotation and translation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+  pub translation: [f32; 3],
+  /// Unit quaternion, `[x, y, z, w]`.
+  pub rotation: [f32; 4],
+  pub scale: f32,
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Self {
+      translation: [0.0; 3],
+      rotation: [0.0, 0.0, 0.0, 1.0],
+      scale: 1.0,
+    }
+  }
+}
+
+impl Transform {
+  pub fn from_translation(translation: [f32; 3]) -> Self {
+    Self {
+      translation,
+      ..Default::default()
+    }
+  }
+
+  /// Rotation of `angle` radians around the `axis`, counter clockwise looking from its end.
+  pub fn with_rotation(self, axis: [f32; 3], angle: f32) -> Self {
+    let axis = scale(axis, 1.0 / length(axis));
+    let (sin, cos) = (angle / 2.0).sin_cos();
+    Self {
+      rotation: [axis[0] * sin, axis[1] * sin, axis[2] * sin, cos],
+      ..self
+    }
+  }
+
+  pub fn with_scale(self, scale: f32) -> Self {
+    Self { scale, ..self }
+  }
+
+  pub fn apply(&self, point: [f32; 3]) -> [f32; 3] {
+    let rotated = rotate(self.rotation, scale(point, self.scale));
+    [
+      rotated[0] + self.translation[0],
+      rotated[1] + self.translation[1],
+      rotated[2] + self.translation[2],
+    ]
+  }
+
+  pub fn inverse_apply(&self, point: [f32; 3]) -> [f32; 3] {
+    let [x, y, z, w] = self.rotation;
+    let rotated = rotate([-x, -y, -z, w], sub(point, self.translation));
+    scale(rotated, 1.0 / self.scale)
+  }
+}
+
+/// Shape placed with a `Transform`.
+#[derive(Clone, Debug)]
+pub struct Transformed<S> {
+  pub shape: S,
+  pub transform: Transform,
+}
+
+impl<S> Transformed<S> {
+  pub fn new(shape: S, transform: Transform) -> Self {
+    Self { shape, transform }
+  }
+}
+
+impl<S> Sdf for Transformed<S>
+where
+  S: Sdf,
+{
+  fn distance(&self, point: [f32; 3]) -> f32 {
+    self.shape.distance(self.transform.inverse_apply(point)) * self.transform.scale
+  }
+
+  fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+    let (local_min, local_max) = self.shape.bounds();
+    if local_min
+      .iter()
+      .chain(local_max.iter())
+      .any(|value| !value.is_finite())
+    {
+      return (local_min, local_max);
+    }
+
+    // The box containing the transformed corners
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for corner in 0..8 {
+      let point = self.transform.apply([
+        if corner & 1 == 0 {
+          local_min[0]
+        } else {
+          local_max[0]
+        },
+        if corner & 2 == 0 {
+          local_min[1]
+        } else {
+          local_max[1]
+        },
+        if corner & 4 == 0 {
+          local_min[2]
+        } else {
+          local_max[2]
+        },
+      ]);
+      for axis in 0..3 {
+        min[axis] = min[axis].min(point[axis]);
+        max[axis] = max[axis].max(point[axis]);
+      }
+    }
+
+    (min, max)
+  }
+}
+
+// Polynomial smooth min, the plain min without smoothness
+fn smooth_min(a: f32, b: f32, smoothness: f32) -> f32 {
+  if smoothness <= 0.0 {
+    return a.min(b);
+  }
+
+  let h = (0.5 + 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+  b + (a - b) * h - smoothness * h * (1.0 - h)
+}
+
+fn rotate(rotation: [f32; 4], point: [f32; 3]) -> [f32; 3] {
+  let [x, y, z, w] = rotation;
+  let axis = [x, y, z];
+  let t = scale(cross(axis, point), 2.0);
+  let u = cross(axis, t);
+  [
+    point[0] + w * t[0] + u[0],
+    point[1] + w * t[1] + u[1],
+    point[2] + w * t[2] + u[2],
+  ]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+  [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f32; 3], factor: f32) -> [f32; 3] {
+  [a[0] * factor, a[1] * factor, a[2] * factor]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+  a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+  [
+    a[1] * b[2] - a[2] * b[1],
+    a[2] * b[0] - a[0] * b[2],
+    a[0] * b[1] - a[1] * b[0],
+  ]
+}
+
+fn length(a: [f32; 3]) -> f32 {
+  dot(a, a).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn assert_close(value: f32, expected: f32) {
+    assert!((value - expected).abs() < 1e-4, "{} != {}", value, expected);
+  }
+
+  #[test]
+  fn test_primitives() {
+    let sphere = Sphere { radius: 2.0 };
+    assert_close(sphere.distance([0.0, 0.0, 0.0]), -2.0);
+    assert_close(sphere.distance([0.0, 3.0, 0.0]), 1.0);
+
+    let cuboid = Cuboid {
+      half_size: [1.0, 2.0, 3.0],
+    };
+    assert_close(cuboid.distance([0.0, 0.0, 0.0]), -1.0);
+    assert_close(cuboid.distance([4.0, 0.0, 0.0]), 3.0);
+    assert_close(cuboid.distance([4.0, 6.0, 0.0]), 5.0);
+
+    let capsule = Capsule {
+      start: [0.0, 0.0, 0.0],
+      end: [0.0, 4.0, 0.0],
+      radius: 1.0,
+    };
+    assert_close(capsule.distance([2.0, 2.0, 0.0]), 1.0);
+    assert_close(capsule.distance([0.0, 6.0, 0.0]), 1.0);
+    assert_eq!(capsule.bounds(), ([-1.0, -1.0, -1.0], [1.0, 5.0, 1.0]));
+
+    let cylinder = Cylinder {
+      height: 4.0,
+      radius: 1.0,
+    };
+    assert_close(cylinder.distance([0.0, 0.0, 0.0]), -1.0);
+    assert_close(cylinder.distance([0.0, 3.0, 0.0]), 1.0);
+    assert_close(cylinder.distance([3.0, 0.0, 0.0]), 2.0);
+
+    let torus = Torus {
+      major_radius: 3.0,
+      minor_radius: 1.0,
+    };
+    assert_close(torus.distance([3.0, 0.0, 0.0]), -1.0);
+    assert_close(torus.distance([0.0, 0.0, 0.0]), 2.0);
+
+    let plane = Plane {
+      normal: [0.0, 2.0, 0.0],
+      offset: 1.0,
+    };
+    assert_close(plane.distance([5.0, 0.0, 5.0]), -1.0);
+    assert_close(plane.distance([5.0, 3.0, 5.0]), 2.0);
+  }
+
+  #[test]
+  fn test_csg() {
+    let a = Sphere { radius: 2.0 };
+    let b = Transformed::new(
+      Sphere { radius: 2.0 },
+      Transform::from_translation([3.0, 0.0, 0.0]),
+    );
+
+    let union = Csg::new(a.clone(), b.clone(), CsgOperation::Union);
+    assert_close(union.distance([4.0, 0.0, 0.0]), -1.0);
+    assert_eq!(union.bounds(), ([-2.0, -2.0, -2.0], [5.0, 2.0, 2.0]));
+
+    let subtract = Csg::new(a.clone(), b.clone(), CsgOperation::Subtract);
+    assert_close(subtract.distance([-1.0, 0.0, 0.0]), -1.0);
+    assert!(subtract.distance([1.5, 0.0, 0.0]) > 0.0);
+
+    let intersect = Csg::new(a.clone(), b.clone(), CsgOperation::Intersect);
+    assert_close(intersect.distance([1.5, 0.0, 0.0]), -0.5);
+    assert!(intersect.distance([-1.0, 0.0, 0.0]) > 0.0);
+    assert_eq!(intersect.bounds(), ([1.0, -2.0, -2.0], [2.0, 2.0, 2.0]));
+
+    // The blend fills the gap between the spheres
+    let smooth = Csg::smooth(a, b, CsgOperation::Union, 1.0);
+    let point = [1.5, 1.9, 0.0];
+    assert!(smooth.distance(point) < union.distance(point));
+  }
+
+  #[test]
+  fn test_transform() {
+    let transform = Transform::from_translation([1.0, 2.0, 3.0])
+      .with_rotation([0.0, 1.0, 0.0], std::f32::consts::FRAC_PI_2)
+      .with_scale(2.0);
+
+    // +x rotates to -z
+    let point = transform.apply([1.0, 0.0, 0.0]);
+    for (value, expected) in point.iter().zip([1.0, 2.0, 1.0].iter()) {
+      assert_close(*value, *expected);
+    }
+    for (value, expected) in transform
+      .inverse_apply(point)
+      .iter()
+      .zip([1.0, 0.0, 0.0].iter())
+    {
+      assert_close(*value, *expected);
+    }
+
+    let cuboid = Transformed::new(
+      Cuboid {
+        half_size: [2.0, 1.0, 1.0],
+      },
+      transform,
+    );
+    assert_close(cuboid.distance([1.0, 2.0, 3.0]), -2.0);
+    let (min, max) = cuboid.bounds();
+    for (value, expected) in min
+      .iter()
+      .chain(max.iter())
+      .zip([-1.0, 0.0, -1.0, 3.0, 4.0, 7.0].iter())
+    {
+      assert_close(*value, *expected);
+    }
+  }
+}
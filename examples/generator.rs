@@ -0,0 +1,90 @@
+use std::time::Instant;
+
+use gaiku_baker_marching_cubes::MarchingCubesBaker;
+use gaiku_common::{
+  chunk::Chunk, mesh::Mesh, prelude::*, texture::Texture2d, world::World, Result,
+};
+use gaiku_generator::{
+  fill, Brush, Capsule, CsgOperation, Fractal, HeightField, Perlin, Simplex, Sphere, Transform,
+  Warp,
+};
+
+mod common;
+
+use crate::common::export;
+
+fn main() -> Result<()> {
+  let now = Instant::now();
+
+  // Rolling hills 24 units high around y = 16, bent by the warp
+  let density = HeightField::new(
+    Warp::new(
+      Fractal::fbm(Simplex::new(1)),
+      Fractal::fbm(Perlin::new(2)),
+      8.0,
+    ),
+    16.0,
+    12.0,
+  );
+
+  let mut world = World::<Chunk>::new([16, 32, 16]);
+  for x in 0..4 {
+    for z in 0..4 {
+      let coord = [x, 0, z];
+      let mut chunk = Chunk::new(world.chunk_position(coord), 16, 32, 16);
+      fill(&mut chunk, &density);
+      world.insert_chunk(coord, chunk);
+    }
+  }
+
+  // A tunnel across the hills and a mound on top of them, spanning several chunks
+  Brush::new(
+    Capsule {
+      start: [4.0, 14.0, 8.0],
+      end: [60.0, 14.0, 56.0],
+      radius: 4.0,
+    },
+    Transform::default(),
+    CsgOperation::Subtract,
+  )
+  .stamp(&mut world);
+  Brush::new(
+    Sphere { radius: 8.0 },
+    Transform::from_translation([40.0, 24.0, 16.0]),
+    CsgOperation::Union,
+  )
+  .with_smoothness(4.0)
+  .stamp(&mut world);
+
+  let coords: Vec<_> = world.coords().copied().collect();
+  for coord in coords.iter() {
+    world.update_neighbor_data(*coord);
+  }
+
+  let generator_elapsed = now.elapsed().as_micros();
+  let now = Instant::now();
+
+  let options = BakerOptions::<Texture2d>::default();
+  let mut meshes: Vec<(Mesh, [f32; 3])> = vec![];
+
+  for (_, chunk) in world.chunks() {
+    if let Some(mesh) = MarchingCubesBaker::bake(chunk, &options)? {
+      meshes.push((mesh, chunk.position()));
+    }
+  }
+
+  let baker_elapsed = now.elapsed().as_micros();
+  let now = Instant::now();
+
+  export(meshes, "generator_mc");
+
+  println!(
+    "<<generator>> Chunks: {} Generator: {} micros Baker: {} micros Export: {} micros",
+    world.len(),
+    generator_elapsed,
+    baker_elapsed,
+    now.elapsed().as_micros()
+  );
+
+  Ok(())
+}
@@ -23,6 +23,7 @@ fn read(name: &str) -> Result<()> {
   );
   let (chunks, texture): (Vec<Chunk>, Option<TextureAtlas2d<Texture2d>>) = GoxReader::read(&file)?;
   let options = BakerOptions {
+    palette: texture.as_ref().map(|texture| texture.palette()),
     texture,
     ..Default::default()
   };
@@ -7,7 +7,7 @@ use amethyst_rendy::{
       image::{Filter, Kind, SamplerInfo, ViewKind, WrapMode},
       Primitive,
     },
-    mesh::{MeshBuilder, Normal, Position, TexCoord},
+    mesh::{Color, MeshBuilder, Normal, Position, TexCoord},
     texture::{pixel::Rgba8Srgb, TextureBuilder},
   },
   types::{MeshData, TextureData},
@@ -25,11 +25,14 @@ pub struct GaikuMesh {
   pub positions: Vec<[f32; 3]>,
   pub normals: Vec<[f32; 3]>,
   pub uvs: Vec<[f32; 2]>,
+  pub colors: Vec<[u8; 4]>,
+  pub occlusions: Vec<f32>,
+  pub tiles: Vec<[f32; 4]>,
 }
 
 impl Meshify for GaikuMesh {
   fn new() -> Self {
-    Self::with(vec![], vec![], vec![], vec![])
+    Self::with(vec![], vec![], vec![], vec![], vec![], vec![], vec![])
   }
 
   fn with(
@@ -37,12 +40,18 @@ impl Meshify for GaikuMesh {
     positions: Vec<[f32; 3]>,
     normals: Vec<[f32; 3]>,
     uvs: Vec<[f32; 2]>,
+    colors: Vec<[u8; 4]>,
+    occlusions: Vec<f32>,
+    tiles: Vec<[f32; 4]>,
   ) -> Self {
     Self {
       indices,
       positions,
       normals,
       uvs,
+      colors,
+      occlusions,
+      tiles,
     }
   }
 
@@ -62,6 +71,18 @@ impl Meshify for GaikuMesh {
     &self.uvs
   }
 
+  fn get_colors(&self) -> &Vec<[u8; 4]> {
+    &self.colors
+  }
+
+  fn get_occlusions(&self) -> &Vec<f32> {
+    &self.occlusions
+  }
+
+  fn get_tiles(&self) -> &Vec<[f32; 4]> {
+    &self.tiles
+  }
+
   fn set_indices(&mut self, indices: Vec<u32>) {
     self.indices = indices;
   }
@@ -77,6 +98,18 @@ impl Meshify for GaikuMesh {
   fn set_uvs(&mut self, uvs: Vec<[f32; 2]>) {
     self.uvs = uvs;
   }
+
+  fn set_colors(&mut self, colors: Vec<[u8; 4]>) {
+    self.colors = colors;
+  }
+
+  fn set_occlusions(&mut self, occlusions: Vec<f32>) {
+    self.occlusions = occlusions;
+  }
+
+  fn set_tiles(&mut self, tiles: Vec<[f32; 4]>) {
+    self.tiles = tiles;
+  }
 }
 
 #[allow(clippy::from_over_into)]
@@ -104,6 +137,20 @@ impl Into<MeshData> for GaikuMesh {
           .map(|v| (*v).into())
           .collect::<Vec<Normal>>(),
       )
+      .with_vertices(
+        self
+          .colors
+          .iter()
+          .map(|[r, g, b, a]| {
+            Color([
+              *r as f32 / 255.0,
+              *g as f32 / 255.0,
+              *b as f32 / 255.0,
+              *a as f32 / 255.0,
+            ])
+          })
+          .collect::<Vec<Color>>(),
+      )
       .with_indices(self.indices.clone())
       .with_prim_type(Primitive::TriangleList);
 
@@ -134,7 +181,7 @@ impl Texturify2d for GaikuTexture2d {
 
   fn get_pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
     if x < self.width && y < self.height {
-      let index = (x + 4 + self.width * y + 4) as usize;
+      let index = (x * 4 + self.width * y * 4) as usize;
       Some([
         self.data[index],
         self.data[index + 1],
@@ -1,9 +1,13 @@
+#[cfg(feature = "gaiku_baker_dual_contouring")]
+pub use gaiku_baker_dual_contouring::*;
 #[cfg(feature = "gaiku_baker_heightmap")]
 pub use gaiku_baker_heightmap::*;
 #[cfg(feature = "gaiku_baker_marching_cubes")]
 pub use gaiku_baker_marching_cubes::*;
 #[cfg(feature = "gaiku_baker_marching_cubes")]
 pub use gaiku_baker_modified_marching_cubes::*;
+#[cfg(feature = "gaiku_baker_surface_nets")]
+pub use gaiku_baker_surface_nets::*;
 #[cfg(feature = "gaiku_baker_voxel")]
 pub use gaiku_baker_voxel::*;
 
@@ -11,8 +15,14 @@ pub use gaiku_baker_voxel::*;
 pub use gaiku_format_gox::*;
 #[cfg(feature = "gaiku_format_png")]
 pub use gaiku_format_png::*;
+#[cfg(feature = "gaiku_format_raw")]
+pub use gaiku_format_raw::*;
+#[cfg(feature = "gaiku_format_vox")]
+pub use gaiku_format_vox::*;
 
 #[cfg(feature = "gaiku_amethyst")]
 pub use gaiku_amethyst::*;
 
 pub use gaiku_common as common;
+#[cfg(feature = "gaiku_generator")]
+pub use gaiku_generator as generator;